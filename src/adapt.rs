@@ -0,0 +1,366 @@
+//! Adapters between the blocking `crate::Sender` / `crate::Receiver` traits and their
+//! async counterparts in `crate::asynch`.
+
+use core::future::Future;
+use core::pin::pin;
+use core::task::{Context, Poll};
+
+#[cfg(feature = "std")]
+pub use asynch_of::*;
+
+/// Run a future to completion on the current thread.
+///
+/// With `std`, the thread is parked while the future is pending. Without `std`, the future is
+/// busy-polled, which is only suitable for futures that are woken from another execution context
+/// (i.e. an ISR or another core).
+pub fn block_on<F>(fut: F) -> F::Output
+where
+    F: Future,
+{
+    let mut fut = pin!(fut);
+
+    #[cfg(feature = "std")]
+    let waker = {
+        struct ThreadWaker(std::thread::Thread);
+
+        impl std::task::Wake for ThreadWaker {
+            fn wake(self: std::sync::Arc<Self>) {
+                self.0.unpark();
+            }
+
+            fn wake_by_ref(self: &std::sync::Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        core::task::Waker::from(std::sync::Arc::new(ThreadWaker(std::thread::current())))
+    };
+
+    #[cfg(not(feature = "std"))]
+    let waker = {
+        use core::task::{RawWaker, RawWakerVTable, Waker};
+
+        const VTABLE: RawWakerVTable = RawWakerVTable::new(
+            |_| RawWaker::new(core::ptr::null(), &VTABLE),
+            |_| (),
+            |_| (),
+            |_| (),
+        );
+
+        unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) }
+    };
+
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+            break output;
+        }
+
+        #[cfg(feature = "std")]
+        std::thread::park();
+
+        #[cfg(not(feature = "std"))]
+        core::hint::spin_loop();
+    }
+}
+
+/// Exposes an async `crate::asynch::Sender` / `crate::asynch::Receiver` as a blocking
+/// `crate::Sender` / `crate::Receiver` by running each operation with `block_on`.
+pub struct Blocking<T>(T);
+
+impl<T> Blocking<T> {
+    pub const fn new(channel: T) -> Self {
+        Self(channel)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> crate::Sender for Blocking<T>
+where
+    T: crate::asynch::Sender,
+    T::Data: Clone,
+{
+    type Error = T::Error;
+
+    type Data = T::Data;
+
+    fn send(&mut self, data: &Self::Data) -> Result<(), Self::Error> {
+        block_on(self.0.send(data.clone()))
+    }
+}
+
+impl<T> crate::Receiver for Blocking<T>
+where
+    T: crate::asynch::Receiver,
+{
+    type Error = T::Error;
+
+    type Data = T::Data;
+
+    fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        block_on(self.0.recv())
+    }
+}
+
+#[cfg(feature = "std")]
+mod asynch_of {
+    use core::fmt::{self, Debug, Display};
+
+    use std::any::Any;
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+
+    use crate::notification::Notification;
+
+    #[derive(Debug)]
+    pub enum AsyncOfError<E> {
+        /// The wrapped channel returned an error
+        ChannelError(E),
+        /// An operation on the wrapped channel panicked, which also terminated the worker thread
+        WorkerPanicked,
+    }
+
+    impl<E> Display for AsyncOfError<E>
+    where
+        E: Display,
+    {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::ChannelError(e) => write!(f, "Channel Error: {e}"),
+                Self::WorkerPanicked => write!(f, "Worker Panicked Error"),
+            }
+        }
+    }
+
+    impl<E> std::error::Error for AsyncOfError<E> where E: Display + Debug {}
+
+    type Job<T> = Box<dyn FnOnce(&mut T) + Send>;
+
+    struct Completion<R> {
+        notification: Notification,
+        result: Mutex<Option<R>>,
+    }
+
+    impl<R> Completion<R> {
+        const fn new() -> Self {
+            Self {
+                notification: Notification::new(),
+                result: Mutex::new(None),
+            }
+        }
+
+        fn complete(&self, result: R) {
+            *self.result.lock().unwrap() = Some(result);
+            self.notification.notify();
+        }
+
+        async fn wait(&self) -> R {
+            loop {
+                if let Some(result) = self.result.lock().unwrap().take() {
+                    break result;
+                }
+
+                self.notification.wait().await;
+            }
+        }
+    }
+
+    /// Completes its `Completion` with `None` when dropped without having been completed,
+    /// i.e. when its job panicked or got dropped without running because the worker is gone.
+    struct CompletionGuard<R>(Option<Arc<Completion<Option<R>>>>);
+
+    impl<R> CompletionGuard<R> {
+        fn complete(mut self, result: R) {
+            if let Some(completion) = self.0.take() {
+                completion.complete(Some(result));
+            }
+        }
+    }
+
+    impl<R> Drop for CompletionGuard<R> {
+        fn drop(&mut self) {
+            if let Some(completion) = self.0.take() {
+                completion.complete(None);
+            }
+        }
+    }
+
+    /// Exposes a blocking `crate::Sender` / `crate::Receiver` as an async
+    /// `crate::asynch::Sender` / `crate::asynch::Receiver` by offloading all operations
+    /// to a dedicated worker thread which owns the wrapped channel.
+    ///
+    /// The worker thread exits once the `AsyncOf` instance is dropped and
+    /// the currently running operation (if any) completes. If an operation panics, the worker
+    /// thread exits as well and this and all subsequent operations fail with
+    /// `AsyncOfError::WorkerPanicked`.
+    pub struct AsyncOf<T> {
+        jobs: mpsc::Sender<Job<T>>,
+        pending_recv: Option<Arc<dyn Any + Send + Sync>>,
+    }
+
+    impl<T> AsyncOf<T>
+    where
+        T: Send + 'static,
+    {
+        pub fn new(channel: T) -> Self {
+            let (jobs, worker_jobs) = mpsc::channel::<Job<T>>();
+
+            thread::spawn(move || {
+                let mut channel = channel;
+
+                for job in worker_jobs {
+                    job(&mut channel);
+                }
+            });
+
+            Self {
+                jobs,
+                pending_recv: None,
+            }
+        }
+
+        /// Submit `f` to the worker thread. The returned completion resolves to `None`
+        /// if `f` panics or if the worker thread is already gone.
+        fn submit<R, F>(&self, f: F) -> Arc<Completion<Option<R>>>
+        where
+            F: FnOnce(&mut T) -> R + Send + 'static,
+            R: Send + 'static,
+        {
+            let completion = Arc::new(Completion::new());
+
+            let guard = CompletionGuard(Some(completion.clone()));
+
+            // If the worker is gone, the job is dropped with the returned error,
+            // and its guard completes the completion
+            let _ = self
+                .jobs
+                .send(Box::new(move |channel| guard.complete(f(channel))));
+
+            completion
+        }
+    }
+
+    impl<T> crate::asynch::Sender for AsyncOf<T>
+    where
+        T: crate::Sender + Send + 'static,
+        T::Data: Send + 'static,
+        T::Error: Send + 'static,
+    {
+        type Error = AsyncOfError<T::Error>;
+
+        type Data = T::Data;
+
+        async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+            self.submit(move |channel| channel.send(&data))
+                .wait()
+                .await
+                .ok_or(AsyncOfError::WorkerPanicked)?
+                .map_err(AsyncOfError::ChannelError)
+        }
+    }
+
+    impl<T> crate::asynch::Receiver for AsyncOf<T>
+    where
+        T: crate::Receiver + Send + 'static,
+        T::Data: Send + 'static,
+        T::Error: Send + 'static,
+    {
+        type Error = AsyncOfError<T::Error>;
+
+        type Data = T::Data;
+
+        async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+            // If a previous `recv` future was dropped before completion, pick up its result
+            // rather than issuing a new receive, so that no data is lost
+            let completion = match self
+                .pending_recv
+                .take()
+                .and_then(|pending| pending.downcast().ok())
+            {
+                Some(completion) => completion,
+                None => self.submit(|channel: &mut T| channel.recv()),
+            };
+
+            self.pending_recv = Some(completion.clone());
+
+            let result = completion.wait().await;
+
+            self.pending_recv = None;
+
+            result
+                .ok_or(AsyncOfError::WorkerPanicked)?
+                .map_err(AsyncOfError::ChannelError)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::adapt::block_on;
+        use crate::asynch::{Receiver, Sender};
+        use crate::ChannelError;
+
+        use super::*;
+
+        /// Panics when sending 0
+        struct Panicking;
+
+        impl crate::Sender for Panicking {
+            type Error = ChannelError;
+
+            type Data = u32;
+
+            fn send(&mut self, data: &Self::Data) -> Result<(), Self::Error> {
+                assert_ne!(*data, 0);
+
+                Ok(())
+            }
+        }
+
+        #[test]
+        fn roundtrip() {
+            let (sender, receiver) = mpsc::channel::<u32>();
+
+            let mut sender = AsyncOf::new(sender);
+            let mut receiver = AsyncOf::new(receiver);
+
+            block_on(async {
+                sender.send(1).await.unwrap();
+                sender.send(2).await.unwrap();
+
+                assert_eq!(receiver.recv().await.unwrap(), 1);
+                assert_eq!(receiver.recv().await.unwrap(), 2);
+
+                drop(sender);
+
+                assert!(matches!(
+                    receiver.recv().await,
+                    Err(AsyncOfError::ChannelError(ChannelError::Disconnected))
+                ));
+            });
+        }
+
+        #[test]
+        fn panicked() {
+            let mut sender = AsyncOf::new(Panicking);
+
+            block_on(async {
+                sender.send(1).await.unwrap();
+
+                assert!(matches!(
+                    sender.send(0).await,
+                    Err(AsyncOfError::WorkerPanicked)
+                ));
+
+                // The worker thread is gone now
+                assert!(matches!(
+                    sender.send(1).await,
+                    Err(AsyncOfError::WorkerPanicked)
+                ));
+            });
+        }
+    }
+}
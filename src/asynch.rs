@@ -1,10 +1,40 @@
 use core::fmt::Debug;
 use core::marker::PhantomData;
 
+#[cfg(feature = "aead")]
+pub mod aead;
+#[cfg(feature = "arq")]
+pub mod arq;
+#[cfg(feature = "async-channel")]
+pub mod async_channel;
+#[cfg(feature = "batch")]
+pub mod batch;
+#[cfg(feature = "futures")]
+pub mod futures;
+#[cfg(feature = "loopback")]
+pub mod loopback;
+#[cfg(feature = "std")]
+pub mod mock;
 pub mod mpmc;
+#[cfg(any(feature = "mqtt", feature = "embedded-svc"))]
+pub mod mqtt;
 pub mod notification;
+pub mod priority;
 pub mod pubsub;
+#[cfg(feature = "serial")]
+pub mod serial;
 pub mod signal;
+#[cfg(feature = "sse")]
+pub mod sse;
+#[cfg(feature = "stream")]
+pub mod stream;
+#[cfg(feature = "throttle")]
+pub mod throttle;
+pub mod time;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+#[cfg(feature = "udp")]
+pub mod udp;
 #[cfg(any(feature = "edge-ws", feature = "embedded-svc", feature = "wasm"))]
 pub mod ws;
 
@@ -121,3 +151,58 @@ where
         }
     }
 }
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::adapt::block_on;
+
+    use super::mock::{Event, MockReceiver, MockSender};
+    use super::*;
+
+    #[test]
+    fn mapper_sender() {
+        let mut sender = MockSender::<u32, &str>::new();
+        sender.ok_next().fail_next("down");
+
+        let mut mapper = Mapper::new(&mut sender, |data: i32| u32::try_from(data).ok());
+
+        block_on(async {
+            assert_eq!(mapper.send(1).await, Ok(()));
+            // Filtered out, so it does not reach the sender and its scripted failure
+            assert_eq!(mapper.send(-2).await, Ok(()));
+            assert_eq!(mapper.send(3).await, Err("down"));
+            assert_eq!(mapper.send(4).await, Ok(()));
+        });
+
+        assert_eq!(sender.sent(), &[1, 4]);
+    }
+
+    #[test]
+    fn mapper_receiver() {
+        let mut receiver = [
+            Event::Ok(1),
+            Event::Ok(-2),
+            Event::Pending,
+            Event::Ok(3),
+            Event::Err("down"),
+            Event::Ok(-4),
+            Event::Close,
+        ]
+        .into_iter()
+        .collect::<MockReceiver<i32, &str>>();
+
+        let mut mapper = Mapper::new(&mut receiver, |data: Option<i32>| match data {
+            Some(data) => u32::try_from(data).ok().map(Some),
+            None => Some(None),
+        });
+
+        block_on(async {
+            assert_eq!(mapper.recv().await, Ok(Some(1)));
+            assert_eq!(mapper.recv().await, Ok(Some(3)));
+            assert_eq!(mapper.recv().await, Err("down"));
+            assert_eq!(mapper.recv().await, Ok(None));
+        });
+
+        assert!(receiver.is_done());
+    }
+}
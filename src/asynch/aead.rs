@@ -0,0 +1,799 @@
+//! Authenticated encryption of byte-frame channels with ChaCha20-Poly1305, i.e. for talking
+//! to relays over links which are not encrypted themselves (plain `ws://`, UDP or serial).
+//!
+//! `AeadSender` and `AeadReceiver` wrap any `Sender` / `Receiver` pair of byte frames. Each
+//! frame carries a 64 bit counter, which is the nonce of the frame and is authenticated along
+//! with the payload: `[counter (8 bytes, LE)][encrypted payload][tag (16 bytes)]`.
+//! Every direction has its own key, so the counters of the two directions never collide.
+//!
+//! The receiver rejects frames which fail authentication, as well as frames which it has
+//! received before. Frames may arrive out of order within a window of `REPLAY_WINDOW` frames,
+//! so the layer also works over datagram links.
+//!
+//! The keys are fresh for every session, so that the counters can start from zero: they are
+//! derived either from a pre-shared key and random salts exchanged with `psk_handshake`, or
+//! from an X25519 key exchange with `handshake`.
+
+use core::fmt::{self, Display};
+
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce, Tag};
+
+use heapless::Vec;
+
+use hkdf::Hkdf;
+
+use rand_core::{CryptoRng, RngCore};
+
+use sha2::Sha256;
+
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+use super::{Receiver, Sender};
+
+/// The size of keys, including pre-shared keys.
+pub const KEY_SIZE: usize = 32;
+
+/// The number of bytes an encrypted frame is larger than its payload.
+pub const OVERHEAD: usize = COUNTER_SIZE + TAG_SIZE;
+
+/// The number of most recent frames which may arrive out of order.
+pub const REPLAY_WINDOW: u64 = 64;
+
+const COUNTER_SIZE: usize = 8;
+const TAG_SIZE: usize = 16;
+
+const KDF_INFO: &[u8] = b"channel-bridge aead v1";
+
+#[derive(Debug)]
+pub enum AeadError<E> {
+    IoError(E),
+    /// The frame was forged, corrupted or encrypted with another key
+    AuthenticationFailed,
+    /// The frame with the given counter was received before, or is too old to tell
+    Replayed(u64),
+    /// The handshake frame of the peer was malformed, or the peer derived other keys
+    /// (i.e. because it uses another pre-shared key)
+    HandshakeFailed,
+    /// The counter of the sender ran out, so a new session with fresh keys is needed
+    CounterExhausted,
+    OversizedFrame(usize),
+}
+
+impl<E> Display for AeadError<E>
+where
+    E: Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IoError(e) => write!(f, "IO Error: {e}"),
+            Self::AuthenticationFailed => write!(f, "Authentication Failed"),
+            Self::Replayed(counter) => write!(f, "Replayed Frame: Frame {counter} received before"),
+            Self::HandshakeFailed => write!(f, "Handshake Failed"),
+            Self::CounterExhausted => write!(f, "Counter Exhausted"),
+            Self::OversizedFrame(delta) => write!(
+                f,
+                "Oversized Frame Error: Frame exceeds max size by {delta}"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E> std::error::Error for AeadError<E> where E: Display + fmt::Debug {}
+
+/// The side of the channel, which decides which of the derived keys is used for sending.
+/// The two peers need to take different roles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Initiator,
+    Responder,
+}
+
+/// The keys of both directions of a channel.
+#[derive(Clone)]
+pub struct Keys {
+    send: Key,
+    recv: Key,
+}
+
+impl Keys {
+    /// Derive the keys of a session from a pre-shared key and the random salts of the
+    /// initiator and the responder.
+    ///
+    /// The salts need to be fresh for every session, as the counters start from zero with every
+    /// `AeadSender`, and the same keys would then reuse the same nonces; `psk_handshake`
+    /// exchanges them.
+    pub fn from_psk(
+        psk: &[u8; KEY_SIZE],
+        initiator_salt: &[u8; KEY_SIZE],
+        responder_salt: &[u8; KEY_SIZE],
+        role: Role,
+    ) -> Self {
+        let mut ikm = [0; 3 * KEY_SIZE];
+        ikm[..KEY_SIZE].copy_from_slice(psk);
+        ikm[KEY_SIZE..2 * KEY_SIZE].copy_from_slice(initiator_salt);
+        ikm[2 * KEY_SIZE..].copy_from_slice(responder_salt);
+
+        Self::derive(&ikm, None, &[], role)
+    }
+
+    fn derive(ikm: &[u8], salt: Option<&[u8]>, transcript: &[u8], role: Role) -> Self {
+        let mut okm = [0; 2 * KEY_SIZE];
+
+        Hkdf::<Sha256>::new(salt, ikm)
+            .expand_multi_info(&[KDF_INFO, transcript], &mut okm)
+            .unwrap();
+
+        let initiator = Key::clone_from_slice(&okm[..KEY_SIZE]);
+        let responder = Key::clone_from_slice(&okm[KEY_SIZE..]);
+
+        match role {
+            Role::Initiator => Self {
+                send: initiator,
+                recv: responder,
+            },
+            Role::Responder => Self {
+                send: responder,
+                recv: initiator,
+            },
+        }
+    }
+}
+
+/// Agree on fresh keys with the peer over an X25519 key exchange with ephemeral keys,
+/// and wrap `sender` and `receiver` with them.
+///
+/// With a pre-shared key, the keys are derived from both the exchanged secret and the
+/// pre-shared key, so that only a peer knowing the pre-shared key can complete the handshake.
+/// Without one, the handshake protects against eavesdropping, but not against a man in the middle.
+///
+/// Both peers exchange their public keys, and then an empty encrypted frame to confirm that
+/// they derived the same keys. `N` needs to be at least `KEY_SIZE`.
+pub async fn handshake<S, R, G, const N: usize>(
+    mut sender: S,
+    mut receiver: R,
+    role: Role,
+    rng: G,
+    psk: Option<&[u8; KEY_SIZE]>,
+) -> Result<(AeadSender<S, N>, AeadReceiver<R, N>), AeadError<S::Error>>
+where
+    S: Sender<Data = Vec<u8, N>>,
+    R: Receiver<Error = S::Error, Data = Vec<u8, N>>,
+    G: RngCore + CryptoRng,
+{
+    let secret = EphemeralSecret::random_from_rng(rng);
+    let public = PublicKey::from(&secret);
+
+    let peer_public =
+        PublicKey::from(exchange(&mut sender, &mut receiver, public.as_bytes()).await?);
+
+    let shared = secret.diffie_hellman(&peer_public);
+
+    if !shared.was_contributory() {
+        return Err(AeadError::HandshakeFailed);
+    }
+
+    let (initiator, responder) = match role {
+        Role::Initiator => (public, peer_public),
+        Role::Responder => (peer_public, public),
+    };
+
+    let mut transcript = [0; 2 * KEY_SIZE];
+    transcript[..KEY_SIZE].copy_from_slice(initiator.as_bytes());
+    transcript[KEY_SIZE..].copy_from_slice(responder.as_bytes());
+
+    let keys = Keys::derive(
+        shared.as_bytes(),
+        psk.map(|psk| psk.as_slice()),
+        &transcript,
+        role,
+    );
+
+    confirm(sender, receiver, &keys).await
+}
+
+/// Agree on fresh keys with the peer from a pre-shared key, and wrap `sender` and `receiver`
+/// with them.
+///
+/// Both peers exchange a random salt, derive the keys from the pre-shared key and the two
+/// salts (see `Keys::from_psk`), and then exchange an empty encrypted frame to confirm that
+/// they derived the same keys. As each peer contributes a fresh salt, the keys are fresh even
+/// if the other peer replays the frames of an earlier session. `N` needs to be at least
+/// `KEY_SIZE`.
+pub async fn psk_handshake<S, R, G, const N: usize>(
+    mut sender: S,
+    mut receiver: R,
+    role: Role,
+    mut rng: G,
+    psk: &[u8; KEY_SIZE],
+) -> Result<(AeadSender<S, N>, AeadReceiver<R, N>), AeadError<S::Error>>
+where
+    S: Sender<Data = Vec<u8, N>>,
+    R: Receiver<Error = S::Error, Data = Vec<u8, N>>,
+    G: RngCore + CryptoRng,
+{
+    let mut salt = [0; KEY_SIZE];
+    rng.fill_bytes(&mut salt);
+
+    let peer_salt = exchange(&mut sender, &mut receiver, &salt).await?;
+
+    let keys = match role {
+        Role::Initiator => Keys::from_psk(psk, &salt, &peer_salt, role),
+        Role::Responder => Keys::from_psk(psk, &peer_salt, &salt, role),
+    };
+
+    confirm(sender, receiver, &keys).await
+}
+
+/// Send `value` to the peer and receive its own.
+async fn exchange<S, R, const N: usize>(
+    sender: &mut S,
+    receiver: &mut R,
+    value: &[u8; KEY_SIZE],
+) -> Result<[u8; KEY_SIZE], AeadError<S::Error>>
+where
+    S: Sender<Data = Vec<u8, N>>,
+    R: Receiver<Error = S::Error, Data = Vec<u8, N>>,
+{
+    let frame = Vec::from_slice(value).map_err(|_| AeadError::OversizedFrame(KEY_SIZE - N))?;
+
+    sender.send(frame).await.map_err(AeadError::IoError)?;
+
+    let frame = receiver.recv().await.map_err(AeadError::IoError)?;
+
+    frame
+        .as_slice()
+        .try_into()
+        .map_err(|_| AeadError::HandshakeFailed)
+}
+
+/// Wrap `sender` and `receiver` with `keys`, once confirmed that the peer derived the same keys.
+async fn confirm<S, R, const N: usize>(
+    sender: S,
+    receiver: R,
+    keys: &Keys,
+) -> Result<(AeadSender<S, N>, AeadReceiver<R, N>), AeadError<S::Error>>
+where
+    S: Sender<Data = Vec<u8, N>>,
+    R: Receiver<Error = S::Error, Data = Vec<u8, N>>,
+{
+    let mut sender = AeadSender::new(sender, keys);
+    let mut receiver = AeadReceiver::new(receiver, keys);
+
+    sender.send(Vec::new()).await?;
+
+    match receiver.recv().await {
+        Ok(frame) if frame.is_empty() => Ok((sender, receiver)),
+        Ok(_) | Err(AeadError::AuthenticationFailed) | Err(AeadError::Replayed(_)) => {
+            Err(AeadError::HandshakeFailed)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// A `Sender` of byte frames, which encrypts and authenticates them before passing them
+/// to the wrapped sender. Payloads can be up to `N - OVERHEAD` bytes.
+///
+/// Once the counter runs out, sending fails with `AeadError::CounterExhausted`, as the keys
+/// cannot be used any further without reusing a nonce.
+pub struct AeadSender<S, const N: usize> {
+    sender: S,
+    cipher: ChaCha20Poly1305,
+    counter: u64,
+}
+
+impl<S, const N: usize> AeadSender<S, N>
+where
+    S: Sender<Data = Vec<u8, N>>,
+{
+    pub fn new(sender: S, keys: &Keys) -> Self {
+        Self {
+            sender,
+            cipher: ChaCha20Poly1305::new(&keys.send),
+            counter: 0,
+        }
+    }
+
+    /// The counter of the next frame.
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+
+    pub async fn send(&mut self, data: Vec<u8, N>) -> Result<(), AeadError<S::Error>> {
+        if data.len() + OVERHEAD > N {
+            return Err(AeadError::OversizedFrame(data.len() + OVERHEAD - N));
+        }
+
+        let counter = self.counter.to_le_bytes();
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or(AeadError::CounterExhausted)?;
+
+        let mut frame = Vec::<u8, N>::new();
+        frame.extend_from_slice(&counter).unwrap();
+        frame.extend_from_slice(&data).unwrap();
+
+        let tag = self
+            .cipher
+            .encrypt_in_place_detached(&nonce(&counter), &counter, &mut frame[COUNTER_SIZE..])
+            .unwrap();
+
+        frame.extend_from_slice(&tag).unwrap();
+
+        self.sender.send(frame).await.map_err(AeadError::IoError)
+    }
+}
+
+impl<S, const N: usize> Sender for AeadSender<S, N>
+where
+    S: Sender<Data = Vec<u8, N>>,
+{
+    type Error = AeadError<S::Error>;
+
+    type Data = Vec<u8, N>;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        AeadSender::send(self, data).await
+    }
+}
+
+/// A `Receiver` of byte frames, which authenticates and decrypts the frames received
+/// by the wrapped receiver.
+///
+/// Frames which fail authentication or which were received before are reported as errors,
+/// after which the receiver can still be used.
+pub struct AeadReceiver<R, const N: usize> {
+    receiver: R,
+    cipher: ChaCha20Poly1305,
+    // The counter following the highest received one
+    next: u64,
+    // Bit `i` is set if frame `next - 1 - i` was received
+    received: u64,
+}
+
+impl<R, const N: usize> AeadReceiver<R, N>
+where
+    R: Receiver<Data = Vec<u8, N>>,
+{
+    pub fn new(receiver: R, keys: &Keys) -> Self {
+        Self {
+            receiver,
+            cipher: ChaCha20Poly1305::new(&keys.recv),
+            next: 0,
+            received: 0,
+        }
+    }
+
+    /// The counter following the highest received one.
+    pub fn counter(&self) -> u64 {
+        self.next
+    }
+
+    pub async fn recv(&mut self) -> Result<Vec<u8, N>, AeadError<R::Error>> {
+        let mut frame = self.receiver.recv().await.map_err(AeadError::IoError)?;
+
+        if frame.len() < OVERHEAD {
+            return Err(AeadError::AuthenticationFailed);
+        }
+
+        let counter: [u8; COUNTER_SIZE] = frame[..COUNTER_SIZE].try_into().unwrap();
+
+        let value = u64::from_le_bytes(counter);
+
+        if !self.is_new(value) {
+            return Err(AeadError::Replayed(value));
+        }
+
+        let payload_end = frame.len() - TAG_SIZE;
+        let tag = Tag::clone_from_slice(&frame[payload_end..]);
+
+        self.cipher
+            .decrypt_in_place_detached(
+                &nonce(&counter),
+                &counter,
+                &mut frame[COUNTER_SIZE..payload_end],
+                &tag,
+            )
+            .map_err(|_| AeadError::AuthenticationFailed)?;
+
+        // Only authenticated frames move the window, so that forged frames cannot block
+        // the genuine ones
+        self.mark(value);
+
+        Ok(Vec::from_slice(&frame[COUNTER_SIZE..payload_end]).unwrap())
+    }
+
+    fn is_new(&self, counter: u64) -> bool {
+        if counter >= self.next {
+            true
+        } else {
+            let age = self.next - 1 - counter;
+
+            age < REPLAY_WINDOW && self.received & (1 << age) == 0
+        }
+    }
+
+    fn mark(&mut self, counter: u64) {
+        if counter >= self.next {
+            let shift = counter - self.next + 1;
+
+            self.received = if shift >= REPLAY_WINDOW {
+                0
+            } else {
+                self.received << shift
+            };
+
+            self.received |= 1;
+            self.next = counter + 1;
+        } else {
+            self.received |= 1 << (self.next - 1 - counter);
+        }
+    }
+}
+
+impl<R, const N: usize> Receiver for AeadReceiver<R, N>
+where
+    R: Receiver<Data = Vec<u8, N>>,
+{
+    type Error = AeadError<R::Error>;
+
+    type Data = Vec<u8, N>;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        AeadReceiver::recv(self).await
+    }
+}
+
+fn nonce(counter: &[u8; COUNTER_SIZE]) -> Nonce {
+    let mut nonce = Nonce::default();
+    nonce[..COUNTER_SIZE].copy_from_slice(counter);
+
+    nonce
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use core::cell::RefCell;
+    use core::convert::Infallible;
+    use core::future::poll_fn;
+    use core::task::Poll;
+
+    use std::collections::VecDeque;
+
+    use embassy_futures::join::join;
+
+    use rand_core::impls;
+
+    use crate::adapt::block_on;
+
+    use super::*;
+
+    const PSK: [u8; KEY_SIZE] = [0x42; KEY_SIZE];
+
+    type Frame = Vec<u8, 64>;
+
+    type Session<'a> = (AeadSender<&'a Link, 64>, AeadReceiver<&'a Link, 64>);
+
+    /// One direction of an in-memory link, whose frames can be inspected and tampered with.
+    #[derive(Default)]
+    struct Link(RefCell<VecDeque<Frame>>);
+
+    impl Link {
+        fn take(&self) -> Frame {
+            self.0.borrow_mut().pop_front().unwrap()
+        }
+
+        fn put(&self, frame: Frame) {
+            self.0.borrow_mut().push_back(frame);
+        }
+    }
+
+    impl Sender for &Link {
+        type Error = Infallible;
+
+        type Data = Frame;
+
+        async fn send(&mut self, data: Frame) -> Result<(), Infallible> {
+            self.put(data);
+
+            Ok(())
+        }
+    }
+
+    impl Receiver for &Link {
+        type Error = Infallible;
+
+        type Data = Frame;
+
+        async fn recv(&mut self) -> Result<Frame, Infallible> {
+            poll_fn(|cx| match self.0.borrow_mut().pop_front() {
+                Some(frame) => Poll::Ready(Ok(frame)),
+                None => {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            })
+            .await
+        }
+    }
+
+    /// A deterministic stand-in for a cryptographically secure RNG.
+    struct TestRng(u64);
+
+    impl RngCore for TestRng {
+        fn next_u32(&mut self) -> u32 {
+            self.next_u64() as u32
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            // SplitMix64
+            self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            impls::fill_bytes_via_next(self, dest)
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+            self.fill_bytes(dest);
+
+            Ok(())
+        }
+    }
+
+    impl CryptoRng for TestRng {}
+
+    fn psk_sessions<'a>(
+        to_responder: &'a Link,
+        to_initiator: &'a Link,
+        seeds: (u64, u64),
+        psks: (&[u8; KEY_SIZE], &[u8; KEY_SIZE]),
+    ) -> (
+        Result<Session<'a>, AeadError<Infallible>>,
+        Result<Session<'a>, AeadError<Infallible>>,
+    ) {
+        block_on(join(
+            psk_handshake(
+                to_responder,
+                to_initiator,
+                Role::Initiator,
+                TestRng(seeds.0),
+                psks.0,
+            ),
+            psk_handshake(
+                to_initiator,
+                to_responder,
+                Role::Responder,
+                TestRng(seeds.1),
+                psks.1,
+            ),
+        ))
+    }
+
+    fn sessions<'a>(
+        to_responder: &'a Link,
+        to_initiator: &'a Link,
+        psks: (Option<&[u8; KEY_SIZE]>, Option<&[u8; KEY_SIZE]>),
+    ) -> (
+        Result<Session<'a>, AeadError<Infallible>>,
+        Result<Session<'a>, AeadError<Infallible>>,
+    ) {
+        block_on(join(
+            handshake(
+                to_responder,
+                to_initiator,
+                Role::Initiator,
+                TestRng(1),
+                psks.0,
+            ),
+            handshake(
+                to_initiator,
+                to_responder,
+                Role::Responder,
+                TestRng(2),
+                psks.1,
+            ),
+        ))
+    }
+
+    fn roundtrip(initiator: &mut Session<'_>, responder: &mut Session<'_>) {
+        block_on(async {
+            for data in [&b""[..], b"hello", &[0xa5; 64 - OVERHEAD]] {
+                let data = Frame::from_slice(data).unwrap();
+
+                initiator.0.send(data.clone()).await.unwrap();
+                assert_eq!(responder.1.recv().await.unwrap(), data);
+
+                responder.0.send(data.clone()).await.unwrap();
+                assert_eq!(initiator.1.recv().await.unwrap(), data);
+            }
+
+            assert!(matches!(
+                initiator
+                    .0
+                    .send(Frame::from_slice(&[0; 64 - OVERHEAD + 1]).unwrap())
+                    .await,
+                Err(AeadError::OversizedFrame(1))
+            ));
+        });
+    }
+
+    #[test]
+    fn psk_handshake_roundtrip() {
+        let (to_responder, to_initiator) = (Link::default(), Link::default());
+
+        let (initiator, responder) =
+            psk_sessions(&to_responder, &to_initiator, (1, 2), (&PSK, &PSK));
+
+        roundtrip(&mut initiator.unwrap(), &mut responder.unwrap());
+    }
+
+    #[test]
+    fn psk_handshake_fresh_keys() {
+        let (to_responder, to_initiator) = (Link::default(), Link::default());
+
+        let (initiator, _) = psk_sessions(&to_responder, &to_initiator, (1, 2), (&PSK, &PSK));
+        let mut initiator = initiator.unwrap();
+
+        block_on(initiator.0.send(Frame::from_slice(b"secret").unwrap())).unwrap();
+        let frame = to_responder.take();
+
+        // The next session starts from the same counter, but with another salt of the responder
+        let (initiator, responder) =
+            psk_sessions(&to_responder, &to_initiator, (1, 3), (&PSK, &PSK));
+        let (mut initiator, mut responder) = (initiator.unwrap(), responder.unwrap());
+
+        block_on(initiator.0.send(Frame::from_slice(b"secret").unwrap())).unwrap();
+        let next_frame = to_responder.take();
+
+        assert_eq!(frame[..COUNTER_SIZE], next_frame[..COUNTER_SIZE]);
+        assert_ne!(frame, next_frame);
+
+        // The frame of the earlier session is rejected
+        to_responder.put(frame);
+        assert!(matches!(
+            block_on(responder.1.recv()),
+            Err(AeadError::AuthenticationFailed)
+        ));
+    }
+
+    #[test]
+    fn psk_handshake_mismatch() {
+        let (to_responder, to_initiator) = (Link::default(), Link::default());
+
+        let (initiator, responder) =
+            psk_sessions(&to_responder, &to_initiator, (1, 2), (&PSK, &[0; KEY_SIZE]));
+
+        assert!(matches!(initiator, Err(AeadError::HandshakeFailed)));
+        assert!(matches!(responder, Err(AeadError::HandshakeFailed)));
+    }
+
+    #[test]
+    fn handshake_roundtrip() {
+        for psk in [None, Some(&PSK)] {
+            let (to_responder, to_initiator) = (Link::default(), Link::default());
+
+            let (initiator, responder) = sessions(&to_responder, &to_initiator, (psk, psk));
+
+            roundtrip(&mut initiator.unwrap(), &mut responder.unwrap());
+        }
+
+        let (to_responder, to_initiator) = (Link::default(), Link::default());
+
+        let (initiator, responder) = sessions(&to_responder, &to_initiator, (Some(&PSK), None));
+
+        assert!(matches!(initiator, Err(AeadError::HandshakeFailed)));
+        assert!(matches!(responder, Err(AeadError::HandshakeFailed)));
+    }
+
+    fn pair(link: &Link) -> (AeadSender<&Link, 64>, AeadReceiver<&Link, 64>) {
+        let salts = ([1; KEY_SIZE], [2; KEY_SIZE]);
+
+        let sender = AeadSender::new(
+            link,
+            &Keys::from_psk(&PSK, &salts.0, &salts.1, Role::Initiator),
+        );
+        let receiver = AeadReceiver::new(
+            link,
+            &Keys::from_psk(&PSK, &salts.0, &salts.1, Role::Responder),
+        );
+
+        (sender, receiver)
+    }
+
+    #[test]
+    fn tamper() {
+        let link = Link::default();
+        let (mut sender, mut receiver) = pair(&link);
+
+        block_on(sender.send(Frame::from_slice(b"payload").unwrap())).unwrap();
+        let frame = link.take();
+
+        // The counter, the payload and the tag
+        for index in [0, COUNTER_SIZE, frame.len() - 1] {
+            let mut tampered = frame.clone();
+            tampered[index] ^= 1;
+
+            link.put(tampered);
+            assert!(matches!(
+                block_on(receiver.recv()),
+                Err(AeadError::AuthenticationFailed)
+            ));
+        }
+
+        link.put(Frame::from_slice(&frame[..OVERHEAD - 1]).unwrap());
+        assert!(matches!(
+            block_on(receiver.recv()),
+            Err(AeadError::AuthenticationFailed)
+        ));
+
+        // Forged frames do not affect the genuine one
+        link.put(frame);
+        assert_eq!(&block_on(receiver.recv()).unwrap(), b"payload");
+    }
+
+    #[test]
+    fn replay() {
+        let link = Link::default();
+        let (mut sender, mut receiver) = pair(&link);
+
+        let frames = block_on(async {
+            let mut frames = std::vec::Vec::new();
+
+            for data in 0..REPLAY_WINDOW as u8 + 3 {
+                sender
+                    .send(Frame::from_slice(&[data]).unwrap())
+                    .await
+                    .unwrap();
+                frames.push(link.take());
+            }
+
+            frames
+        });
+
+        let mut deliver = |index: usize| {
+            link.put(frames[index].clone());
+            block_on(receiver.recv())
+        };
+
+        assert_eq!(&deliver(1).unwrap(), &[1]);
+        assert!(matches!(deliver(1), Err(AeadError::Replayed(1))));
+
+        // Out of order within the window
+        assert_eq!(&deliver(0).unwrap(), &[0]);
+        assert!(matches!(deliver(0), Err(AeadError::Replayed(0))));
+
+        // Frame 2 falls out of the window, even though it was never received
+        let last = REPLAY_WINDOW as usize + 2;
+        assert_eq!(&deliver(last).unwrap(), &[last as u8]);
+        assert!(matches!(deliver(2), Err(AeadError::Replayed(2))));
+        assert_eq!(&deliver(3).unwrap(), &[3]);
+
+        assert_eq!(receiver.counter(), REPLAY_WINDOW + 3);
+    }
+
+    #[test]
+    fn counter_exhausted() {
+        let link = Link::default();
+        let (mut sender, mut receiver) = pair(&link);
+
+        sender.counter = u64::MAX - 1;
+
+        block_on(sender.send(Frame::from_slice(b"last").unwrap())).unwrap();
+        assert_eq!(&block_on(receiver.recv()).unwrap(), b"last");
+
+        assert!(matches!(
+            block_on(sender.send(Frame::new())),
+            Err(AeadError::CounterExhausted)
+        ));
+        assert!(link.0.borrow().is_empty());
+        assert_eq!(sender.counter(), u64::MAX);
+    }
+}
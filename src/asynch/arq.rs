@@ -0,0 +1,749 @@
+//! Reliable, in-order and exactly-once delivery over lossy links (i.e. serial or UDP),
+//! implemented as a Go-Back-N ARQ with cumulative acknowledgements.
+//!
+//! The link is any `Sender` / `Receiver` pair of byte frames (see `LinkFrame` for the
+//! data types the receiver can produce, i.e. those of `SerialReceiver` and `UdpReceiver`,
+//! once the `heapless::Vec` frames are sent through them). Each data frame carries
+//! a sequence number; the peer acknowledges the next sequence number it expects, and
+//! all unacknowledged frames are retransmitted once the retransmit timeout elapses.
+//! Frames which are out of order or duplicated are dropped by the receiving side.
+//!
+//! Both peers need to start with a fresh `Arq` instance, as sequence numbers are not
+//! negotiated.
+
+use core::cell::RefCell;
+use core::fmt::{self, Display};
+
+use embassy_futures::select::{select, Either};
+
+use embassy_sync::blocking_mutex::{raw::RawMutex, Mutex};
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+
+use heapless::{Deque, Vec};
+
+use super::time::Clock;
+use super::{Receiver, Sender};
+
+/// The size of the frame header: frame kind and a 16 bit sequence number.
+pub const HEADER_SIZE: usize = 3;
+
+const KIND_DATA: u8 = 0;
+const KIND_ACK: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArqError {
+    OversizedFrame(usize),
+}
+
+impl Display for ArqError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OversizedFrame(delta) => write!(
+                f,
+                "Oversized Frame Error: Frame exceeds max size by {delta}"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ArqError {}
+
+/// The data received from a link, which carries a frame unless the link was closed.
+pub trait LinkFrame<const N: usize> {
+    /// The frame, or `None` if the link was closed.
+    fn into_frame(self) -> Option<Vec<u8, N>>;
+}
+
+impl<const N: usize> LinkFrame<N> for Vec<u8, N> {
+    fn into_frame(self) -> Option<Vec<u8, N>> {
+        Some(self)
+    }
+}
+
+/// As received by `SerialReceiver`, or `WsReceiver`.
+impl<T, const N: usize> LinkFrame<N> for Option<T>
+where
+    T: LinkFrame<N>,
+{
+    fn into_frame(self) -> Option<Vec<u8, N>> {
+        self.and_then(LinkFrame::into_frame)
+    }
+}
+
+/// As received by `UdpReceiver`, along with the address of the peer.
+impl<A, T, const N: usize> LinkFrame<N> for (A, T)
+where
+    T: LinkFrame<N>,
+{
+    fn into_frame(self) -> Option<Vec<u8, N>> {
+        self.1.into_frame()
+    }
+}
+
+struct State<const N: usize, const W: usize> {
+    window: Deque<Vec<u8, N>, W>,
+    base: u16,
+    unsent: usize,
+    deadline: Option<u64>,
+    expected: u16,
+    ack_pending: bool,
+    lost: u32,
+}
+
+impl<const N: usize, const W: usize> State<N, W> {
+    const fn new() -> Self {
+        Self {
+            window: Deque::new(),
+            base: 0,
+            unsent: 0,
+            deadline: None,
+            expected: 0,
+            ack_pending: false,
+            lost: 0,
+        }
+    }
+}
+
+/// The state of one end of a reliable link.
+///
+/// - `N` is the maximum size of a link frame; application payloads can be up to `N - HEADER_SIZE` bytes
+/// - `W` is the size of the sliding window, i.e. the maximum number of unacknowledged frames
+///   (and also the number of received frames which are buffered until the application receives them);
+///   it needs to be below 32768, so that the 16 bit sequence numbers stay unambiguous
+pub struct Arq<M, const N: usize, const W: usize>
+where
+    M: RawMutex,
+{
+    state: Mutex<M, RefCell<State<N, W>>>,
+    timeout: u64,
+    tx_ready: Signal<M, ()>,
+    space: Signal<M, ()>,
+    received: Channel<M, Vec<u8, N>, W>,
+}
+
+impl<M, const N: usize, const W: usize> Arq<M, N, W>
+where
+    M: RawMutex,
+{
+    const WINDOW_CHECK: () = assert!(W < 32768, "The window size needs to be below 32768");
+
+    /// Create a new link end which retransmits unacknowledged frames after `timeout` milliseconds.
+    pub const fn new(timeout: u64) -> Self {
+        let () = Self::WINDOW_CHECK;
+
+        Self {
+            state: Mutex::new(RefCell::new(State::new())),
+            timeout,
+            tx_ready: Signal::new(),
+            space: Signal::new(),
+            received: Channel::new(),
+        }
+    }
+
+    /// The application-facing sender.
+    pub fn sender(&self) -> ArqSender<'_, M, N, W> {
+        ArqSender(self)
+    }
+
+    /// The application-facing receiver.
+    pub fn receiver(&self) -> ArqReceiver<'_, M, N, W> {
+        ArqReceiver(self)
+    }
+
+    /// The number of link frames which could not be received (i.e. receive errors,
+    /// like corrupted or oversized frames) or which were malformed.
+    ///
+    /// Lost data frames and acknowledgements are recovered by retransmission.
+    pub fn lost(&self) -> u32 {
+        self.state.lock(|state| state.borrow().lost)
+    }
+
+    /// Drive the link over the provided byte-frame `sender` and `receiver`.
+    ///
+    /// Needs to be polled for as long as the application sender and receiver are in use.
+    /// Returns when the link is closed by the receiver, or with an error if sending fails;
+    /// receive errors only count as lost frames (see `lost`).
+    pub async fn run<S, R, C>(&self, sender: S, receiver: R, clock: C) -> Result<(), S::Error>
+    where
+        S: Sender<Data = Vec<u8, N>>,
+        R: Receiver,
+        R::Data: LinkFrame<N>,
+        C: Clock,
+    {
+        match select(self.run_tx(sender, &clock), self.run_rx(receiver, &clock)).await {
+            Either::First(result) => result,
+            Either::Second(()) => Ok(()),
+        }
+    }
+
+    async fn run_tx<S, C>(&self, mut sender: S, clock: &C) -> Result<(), S::Error>
+    where
+        S: Sender<Data = Vec<u8, N>>,
+        C: Clock,
+    {
+        loop {
+            let (ack, frame, deadline) = self.state.lock(|state| {
+                let mut state = state.borrow_mut();
+
+                if state
+                    .deadline
+                    .is_some_and(|deadline| clock.now() >= deadline)
+                {
+                    // Go back N
+                    state.unsent = 0;
+                    state.deadline = None;
+                }
+
+                let ack = state.ack_pending.then(|| {
+                    state.ack_pending = false;
+                    state.expected
+                });
+
+                let frame = (state.unsent < state.window.len()).then(|| {
+                    let seq = state.base.wrapping_add(state.unsent as u16);
+                    let payload = state.window.iter().nth(state.unsent).unwrap();
+
+                    let mut frame = Vec::new();
+                    frame.extend_from_slice(&header(KIND_DATA, seq)).unwrap();
+                    frame.extend_from_slice(payload).unwrap();
+
+                    state.unsent += 1;
+
+                    frame
+                });
+
+                if frame.is_some() && state.deadline.is_none() {
+                    state.deadline = Some(clock.now() + self.timeout);
+                }
+
+                (ack, frame, state.deadline)
+            });
+
+            let idle = ack.is_none() && frame.is_none();
+
+            if let Some(ack) = ack {
+                sender
+                    .send(Vec::from_slice(&header(KIND_ACK, ack)).unwrap())
+                    .await?;
+            }
+
+            if let Some(frame) = frame {
+                sender.send(frame).await?;
+            }
+
+            if idle {
+                let timeout = async {
+                    match deadline {
+                        Some(deadline) => clock.wait_until(deadline).await,
+                        None => core::future::pending().await,
+                    }
+                };
+
+                select(self.tx_ready.wait(), timeout).await;
+            }
+        }
+    }
+
+    async fn run_rx<R, C>(&self, mut receiver: R, clock: &C)
+    where
+        R: Receiver,
+        R::Data: LinkFrame<N>,
+        C: Clock,
+    {
+        loop {
+            let frame = match receiver.recv().await {
+                Ok(data) => match data.into_frame() {
+                    Some(frame) => frame,
+                    None => break,
+                },
+                Err(_) => {
+                    self.lose();
+                    continue;
+                }
+            };
+
+            if frame.len() < HEADER_SIZE {
+                self.lose();
+                continue;
+            }
+
+            let seq = u16::from_le_bytes([frame[1], frame[2]]);
+
+            match frame[0] {
+                KIND_DATA => self.state.lock(|state| {
+                    let mut state = state.borrow_mut();
+
+                    if seq == state.expected
+                        && self
+                            .received
+                            .try_send(Vec::from_slice(&frame[HEADER_SIZE..]).unwrap())
+                            .is_ok()
+                    {
+                        state.expected = state.expected.wrapping_add(1);
+                    }
+
+                    // Out of order and duplicate frames are (re-)acknowledged too,
+                    // so that the peer learns about lost acknowledgements
+                    state.ack_pending = true;
+                }),
+                KIND_ACK => self.state.lock(|state| {
+                    let mut state = state.borrow_mut();
+
+                    let acked = seq.wrapping_sub(state.base) as usize;
+
+                    if acked > 0 && acked <= state.window.len() {
+                        for _ in 0..acked {
+                            state.window.pop_front();
+                        }
+
+                        state.base = seq;
+                        state.unsent = state.unsent.saturating_sub(acked);
+                        state.deadline =
+                            (!state.window.is_empty()).then(|| clock.now() + self.timeout);
+
+                        self.space.signal(());
+                    }
+                }),
+                _ => {
+                    self.lose();
+                    continue;
+                }
+            }
+
+            self.tx_ready.signal(());
+        }
+    }
+
+    fn lose(&self) {
+        self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+
+            state.lost = state.lost.wrapping_add(1);
+        });
+    }
+}
+
+fn header(kind: u8, seq: u16) -> [u8; HEADER_SIZE] {
+    let seq = seq.to_le_bytes();
+
+    [kind, seq[0], seq[1]]
+}
+
+pub struct ArqSender<'a, M, const N: usize, const W: usize>(&'a Arq<M, N, W>)
+where
+    M: RawMutex;
+
+impl<M, const N: usize, const W: usize> Sender for ArqSender<'_, M, N, W>
+where
+    M: RawMutex,
+{
+    type Error = ArqError;
+
+    type Data = Vec<u8, N>;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        if data.len() + HEADER_SIZE > N {
+            return Err(ArqError::OversizedFrame(data.len() + HEADER_SIZE - N));
+        }
+
+        let mut data = Some(data);
+
+        loop {
+            self.0.state.lock(|state| {
+                let mut state = state.borrow_mut();
+
+                if !state.window.is_full() {
+                    state.window.push_back(data.take().unwrap()).unwrap();
+                }
+            });
+
+            if data.is_none() {
+                self.0.tx_ready.signal(());
+
+                break Ok(());
+            }
+
+            self.0.space.wait().await;
+        }
+    }
+}
+
+pub struct ArqReceiver<'a, M, const N: usize, const W: usize>(&'a Arq<M, N, W>)
+where
+    M: RawMutex;
+
+impl<M, const N: usize, const W: usize> Receiver for ArqReceiver<'_, M, N, W>
+where
+    M: RawMutex,
+{
+    type Error = core::convert::Infallible;
+
+    type Data = Vec<u8, N>;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        Ok(self.0.received.receive().await)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use core::cell::Cell;
+    use core::future::poll_fn;
+    use core::task::Poll;
+
+    use std::collections::VecDeque;
+
+    use embassy_futures::join::join;
+    use embassy_futures::yield_now;
+
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use crate::adapt::block_on;
+
+    use super::*;
+
+    const TIMEOUT: u64 = 100;
+
+    type Frame = Vec<u8, 8>;
+
+    type TestArq = Arq<NoopRawMutex, 8, 4>;
+
+    /// A simulated clock, which only moves on once the other tasks are idle.
+    struct TestClock(Cell<u64>);
+
+    impl Clock for TestClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+
+        async fn wait_until(&self, instant: u64) {
+            for _ in 0..16 {
+                if self.now() >= instant {
+                    return;
+                }
+
+                yield_now().await;
+            }
+
+            self.0.set(self.now().max(instant));
+        }
+    }
+
+    /// What happens to the N-th frame sent over a `Wire`.
+    #[derive(Clone, Copy)]
+    enum Fault {
+        Deliver,
+        Drop,
+        Duplicate,
+        /// Delivered after the next frame
+        Reorder,
+        /// Received as an error
+        Error,
+    }
+
+    /// One direction of a simulated link.
+    struct Wire {
+        faults: fn(u32) -> Fault,
+        sent: Cell<u32>,
+        held: Cell<Option<Frame>>,
+        queue: RefCell<VecDeque<Result<Option<Frame>, &'static str>>>,
+    }
+
+    impl Wire {
+        fn new(faults: fn(u32) -> Fault) -> Self {
+            Self {
+                faults,
+                sent: Cell::new(0),
+                held: Cell::new(None),
+                queue: RefCell::new(VecDeque::new()),
+            }
+        }
+    }
+
+    impl Sender for &Wire {
+        type Error = core::convert::Infallible;
+
+        type Data = Frame;
+
+        async fn send(&mut self, frame: Frame) -> Result<(), Self::Error> {
+            self.sent.set(self.sent.get() + 1);
+
+            let mut queue = self.queue.borrow_mut();
+
+            match (self.faults)(self.sent.get()) {
+                Fault::Deliver => queue.push_back(Ok(Some(frame))),
+                Fault::Drop => (),
+                Fault::Duplicate => {
+                    queue.push_back(Ok(Some(frame.clone())));
+                    queue.push_back(Ok(Some(frame)));
+                }
+                Fault::Reorder => {
+                    self.held.set(Some(frame));
+                    return Ok(());
+                }
+                Fault::Error => queue.push_back(Err("corrupted")),
+            }
+
+            if let Some(held) = self.held.take() {
+                queue.push_back(Ok(Some(held)));
+            }
+
+            Ok(())
+        }
+    }
+
+    impl Receiver for &Wire {
+        type Error = &'static str;
+
+        // As with `SerialReceiver`
+        type Data = Option<Frame>;
+
+        async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+            poll_fn(|cx| match self.queue.borrow_mut().pop_front() {
+                Some(frame) => Poll::Ready(frame),
+                None => {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            })
+            .await
+        }
+    }
+
+    struct Transfer {
+        received: std::vec::Vec<u16>,
+        sent: u32,
+        lost: u32,
+        elapsed: u64,
+    }
+
+    /// Send `count` messages from `a` to `b`, with the given faults on the way there
+    /// and on the way back, and starting with the sequence number `seq`.
+    fn transfer(
+        count: u16,
+        forward: fn(u32) -> Fault,
+        backward: fn(u32) -> Fault,
+        seq: u16,
+    ) -> Transfer {
+        let clock = TestClock(Cell::new(0));
+
+        let a = TestArq::new(TIMEOUT);
+        let b = TestArq::new(TIMEOUT);
+
+        a.state.lock(|state| state.borrow_mut().base = seq);
+        b.state.lock(|state| state.borrow_mut().expected = seq);
+
+        let forward = Wire::new(forward);
+        let backward = Wire::new(backward);
+
+        let mut received = std::vec::Vec::new();
+
+        block_on(select(
+            join(
+                async {
+                    let mut sender = a.sender();
+
+                    for value in 0..count {
+                        sender
+                            .send(Vec::from_slice(&value.to_le_bytes()).unwrap())
+                            .await
+                            .unwrap();
+                    }
+                },
+                async {
+                    let mut receiver = b.receiver();
+
+                    while received.len() < count as usize {
+                        let data = receiver.recv().await.unwrap();
+                        received.push(u16::from_le_bytes([data[0], data[1]]));
+                    }
+                },
+            ),
+            join(
+                a.run(&forward, &backward, &clock),
+                b.run(&backward, &forward, &clock),
+            ),
+        ));
+
+        // Nothing got delivered twice
+        assert!(b.received.try_receive().is_err());
+
+        Transfer {
+            received,
+            sent: forward.sent.get(),
+            lost: b.lost(),
+            elapsed: clock.now(),
+        }
+    }
+
+    fn expected(count: u16) -> std::vec::Vec<u16> {
+        (0..count).collect()
+    }
+
+    #[test]
+    fn deliver() {
+        let transfer = transfer(20, |_| Fault::Deliver, |_| Fault::Deliver, 0);
+
+        assert_eq!(transfer.received, expected(20));
+        assert_eq!(transfer.sent, 20);
+        assert_eq!(transfer.elapsed, 0);
+    }
+
+    #[test]
+    fn loss() {
+        let transfer = transfer(
+            20,
+            |n| {
+                if n % 3 == 0 {
+                    Fault::Drop
+                } else {
+                    Fault::Deliver
+                }
+            },
+            |n| {
+                if n % 4 == 0 {
+                    Fault::Drop
+                } else {
+                    Fault::Deliver
+                }
+            },
+            0,
+        );
+
+        assert_eq!(transfer.received, expected(20));
+        assert!(transfer.sent > 20);
+    }
+
+    #[test]
+    fn duplication() {
+        let transfer = transfer(
+            20,
+            |n| {
+                if n % 2 == 0 {
+                    Fault::Duplicate
+                } else {
+                    Fault::Deliver
+                }
+            },
+            |n| {
+                if n % 3 == 0 {
+                    Fault::Duplicate
+                } else {
+                    Fault::Deliver
+                }
+            },
+            0,
+        );
+
+        assert_eq!(transfer.received, expected(20));
+    }
+
+    #[test]
+    fn reordering() {
+        let transfer = transfer(
+            20,
+            |n| {
+                if n % 3 == 1 {
+                    Fault::Reorder
+                } else {
+                    Fault::Deliver
+                }
+            },
+            |n| {
+                if n % 4 == 1 {
+                    Fault::Reorder
+                } else {
+                    Fault::Deliver
+                }
+            },
+            0,
+        );
+
+        assert_eq!(transfer.received, expected(20));
+    }
+
+    #[test]
+    fn receive_errors() {
+        let transfer = transfer(
+            20,
+            |n| {
+                if n % 5 == 0 {
+                    Fault::Error
+                } else {
+                    Fault::Deliver
+                }
+            },
+            |_| Fault::Deliver,
+            0,
+        );
+
+        assert_eq!(transfer.received, expected(20));
+        assert!(transfer.lost > 0);
+    }
+
+    #[test]
+    fn retransmit_timeout() {
+        let transfer = transfer(
+            1,
+            |n| if n == 1 { Fault::Drop } else { Fault::Deliver },
+            |_| Fault::Deliver,
+            0,
+        );
+
+        assert_eq!(transfer.received, expected(1));
+        assert_eq!(transfer.sent, 2);
+        assert_eq!(transfer.elapsed, TIMEOUT);
+    }
+
+    #[test]
+    fn sequence_wrap() {
+        let transfer = transfer(
+            20,
+            |n| {
+                if n % 3 == 0 {
+                    Fault::Drop
+                } else {
+                    Fault::Deliver
+                }
+            },
+            |n| {
+                if n % 5 == 0 {
+                    Fault::Duplicate
+                } else {
+                    Fault::Deliver
+                }
+            },
+            u16::MAX - 5,
+        );
+
+        assert_eq!(transfer.received, expected(20));
+    }
+
+    #[test]
+    fn link_frames() {
+        let frame = Frame::from_slice(&[1, 2, 3]).unwrap();
+
+        // As received by `SerialReceiver` and `UdpReceiver`
+        assert_eq!(Some(frame.clone()).into_frame(), Some(frame.clone()));
+        assert_eq!(None::<Frame>.into_frame(), None);
+        assert_eq!(("peer", frame.clone()).into_frame(), Some(frame));
+    }
+
+    #[test]
+    fn closed() {
+        let arq = TestArq::new(TIMEOUT);
+
+        let wire = Wire::new(|_| Fault::Deliver);
+
+        // A malformed frame, then the link gets closed
+        wire.queue.borrow_mut().push_back(Ok(Some(Vec::new())));
+        wire.queue.borrow_mut().push_back(Ok(None));
+
+        let clock = TestClock(Cell::new(0));
+
+        assert_eq!(block_on(arq.run(&wire, &wire, &clock)), Ok(()));
+        assert_eq!(arq.lost(), 1);
+    }
+}
@@ -0,0 +1,69 @@
+use crate::ChannelError;
+
+use super::{Receiver, Sender};
+
+impl<T> Sender for async_channel::Sender<T> {
+    type Error = ChannelError;
+
+    type Data = T;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        async_channel::Sender::send(self, data)
+            .await
+            .map_err(|_| ChannelError::Disconnected)
+    }
+}
+
+impl<T> Receiver for async_channel::Receiver<T> {
+    type Error = ChannelError;
+
+    type Data = T;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        async_channel::Receiver::recv(self)
+            .await
+            .map_err(|_| ChannelError::Disconnected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adapt::block_on;
+    use crate::asynch::{Receiver, Sender};
+    use crate::ChannelError;
+
+    #[test]
+    fn roundtrip() {
+        block_on(async {
+            let (mut sender, mut receiver) = async_channel::bounded(2);
+
+            Sender::send(&mut sender, 1).await.unwrap();
+            Sender::send(&mut sender, 2).await.unwrap();
+
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(1));
+
+            // The messages sent before the disconnection are still received
+            drop(sender);
+
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(2));
+            assert_eq!(
+                Receiver::recv(&mut receiver).await,
+                Err(ChannelError::Disconnected)
+            );
+        });
+    }
+
+    #[test]
+    fn send_disconnected() {
+        block_on(async {
+            let (mut sender, receiver) = async_channel::unbounded::<u32>();
+
+            drop(receiver);
+
+            assert_eq!(
+                Sender::send(&mut sender, 1).await,
+                Err(ChannelError::Disconnected)
+            );
+        });
+    }
+}
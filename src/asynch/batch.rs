@@ -0,0 +1,337 @@
+//! Packing of many small messages into one frame.
+//!
+//! `Batcher` collects the messages sent to it into a `heapless::Vec` of up to `N` messages,
+//! and sends the whole batch as a single message over the wrapped sender (i.e. a `WsSender`
+//! of `Vec<D, N>`). On the other end, `Unbatcher` receives the batches and yields their
+//! messages one by one.
+
+use heapless::Vec;
+
+use super::time::Clock;
+use super::{Receiver, Sender};
+
+/// A `Sender` wrapper which sends its messages in batches of up to `N` messages.
+///
+/// A batch is flushed once it is full, once `window` milliseconds have elapsed since its first
+/// message was added, or explicitly with `flush`.
+pub struct Batcher<S, C, D, const N: usize> {
+    sender: S,
+    clock: C,
+    window: u64,
+    batch: Vec<D, N>,
+    deadline: Option<u64>,
+}
+
+impl<S, C, D, const N: usize> Batcher<S, C, D, N>
+where
+    S: Sender<Data = Vec<D, N>>,
+    C: Clock,
+{
+    pub fn new(sender: S, clock: C, window: u64) -> Self {
+        Self {
+            sender,
+            clock,
+            window,
+            batch: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    /// The number of messages in the current batch.
+    pub fn len(&self) -> usize {
+        self.batch.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batch.is_empty()
+    }
+
+    /// Add `data` to the current batch, sending the batch if it is full.
+    ///
+    /// If the window of the current batch has elapsed already, the batch is sent before adding
+    /// `data` to a new one.
+    pub async fn send(&mut self, data: D) -> Result<(), S::Error> {
+        let now = self.clock.now();
+
+        if self.deadline.is_some_and(|deadline| now >= deadline) {
+            self.flush().await?;
+        }
+
+        if self.batch.is_empty() {
+            self.deadline = Some(now + self.window);
+        }
+
+        // Cannot fail, as a full batch is always flushed right away
+        let _ = self.batch.push(data);
+
+        if self.batch.is_full() {
+            self.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Send the current batch, if it is not empty.
+    pub async fn flush(&mut self) -> Result<(), S::Error> {
+        self.deadline = None;
+
+        if self.batch.is_empty() {
+            Ok(())
+        } else {
+            self.sender.send(core::mem::take(&mut self.batch)).await
+        }
+    }
+
+    /// Wait until the window of the current batch elapses and send it.
+    /// Never completes while the batch is empty.
+    ///
+    /// This is cancel-safe up until the batch is handed over to the wrapped sender,
+    /// so it can be raced against the arrival of the next message.
+    pub async fn flush_when_due(&mut self) -> Result<(), S::Error> {
+        match self.deadline {
+            Some(deadline) => {
+                self.clock.wait_until(deadline).await;
+                self.flush().await
+            }
+            None => core::future::pending().await,
+        }
+    }
+}
+
+impl<S, C, D, const N: usize> Sender for Batcher<S, C, D, N>
+where
+    S: Sender<Data = Vec<D, N>>,
+    C: Clock,
+{
+    type Error = S::Error;
+
+    type Data = D;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        Batcher::send(self, data).await
+    }
+}
+
+/// A `Receiver` wrapper which expands the batches sent by a `Batcher` into their messages.
+///
+/// As with the framed receivers it wraps, `Data` is `Option<D>`, with `None` signalling
+/// that the channel is closed.
+pub struct Unbatcher<R, D, const N: usize> {
+    receiver: R,
+    // The rest of the current batch, in reverse order
+    batch: Vec<D, N>,
+}
+
+impl<R, D, const N: usize> Unbatcher<R, D, N>
+where
+    R: Receiver<Data = Option<Vec<D, N>>>,
+{
+    pub fn new(receiver: R) -> Self {
+        Self {
+            receiver,
+            batch: Vec::new(),
+        }
+    }
+
+    pub async fn recv(&mut self) -> Result<Option<D>, R::Error> {
+        loop {
+            if let Some(data) = self.batch.pop() {
+                break Ok(Some(data));
+            }
+
+            match self.receiver.recv().await? {
+                Some(mut batch) => {
+                    batch.reverse();
+                    self.batch = batch;
+                }
+                None => break Ok(None),
+            }
+        }
+    }
+}
+
+impl<R, D, const N: usize> Receiver for Unbatcher<R, D, N>
+where
+    R: Receiver<Data = Option<Vec<D, N>>>,
+{
+    type Error = R::Error;
+
+    type Data = Option<D>;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        Unbatcher::recv(self).await
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use core::cell::Cell;
+
+    use crate::adapt::block_on;
+    use crate::asynch::mock::MockSender;
+
+    use super::*;
+
+    /// A simulated clock, which jumps straight to the instant waited for.
+    struct TestClock(Cell<u64>);
+
+    impl TestClock {
+        fn set(&self, now: u64) {
+            self.0.set(now);
+        }
+    }
+
+    impl Clock for TestClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+
+        async fn wait_until(&self, instant: u64) {
+            self.0.set(self.0.get().max(instant));
+        }
+    }
+
+    fn batch(data: &[u32]) -> Vec<u32, 4> {
+        Vec::from_slice(data).unwrap()
+    }
+
+    #[test]
+    fn size() {
+        let clock = TestClock(Cell::new(0));
+        let mut sender = MockSender::<Vec<u32, 4>>::new();
+        let mut batcher = Batcher::new(&mut sender, &clock, 100);
+
+        block_on(async {
+            for data in 0..10 {
+                batcher.send(data).await.unwrap();
+            }
+
+            assert_eq!(batcher.len(), 2);
+
+            batcher.flush().await.unwrap();
+            batcher.flush().await.unwrap();
+        });
+
+        assert!(batcher.is_empty());
+        assert_eq!(
+            sender.sent(),
+            [batch(&[0, 1, 2, 3]), batch(&[4, 5, 6, 7]), batch(&[8, 9])]
+        );
+    }
+
+    #[test]
+    fn window() {
+        let clock = TestClock(Cell::new(0));
+        let mut sender = MockSender::<Vec<u32, 4>>::new();
+        let mut batcher = Batcher::new(&mut sender, &clock, 100);
+
+        block_on(async {
+            batcher.send(0).await.unwrap();
+
+            clock.set(50);
+            batcher.send(1).await.unwrap();
+
+            // The window of the first batch has elapsed, so it is sent first
+            clock.set(100);
+            batcher.send(2).await.unwrap();
+
+            clock.set(150);
+            batcher.send(3).await.unwrap();
+
+            // The window of the second batch started with its first message
+            batcher.flush_when_due().await.unwrap();
+        });
+
+        assert_eq!(clock.now(), 200);
+        assert!(batcher.is_empty());
+        assert_eq!(sender.sent(), [batch(&[0, 1]), batch(&[2, 3])]);
+    }
+
+    #[cfg(all(feature = "stream", not(feature = "prost")))]
+    #[test]
+    fn roundtrip() {
+        use crate::asynch::stream::{StreamReceiver, StreamSender};
+
+        let clock = TestClock(Cell::new(0));
+
+        let mut wire = [0; 256];
+
+        let len = {
+            let mut write = &mut wire[..];
+            let mut buf = [0; 64];
+            let sender = StreamSender::<_, Vec<u32, 4>>::new(&mut write, &mut buf);
+            let mut batcher = Batcher::new(sender, &clock, 100);
+
+            block_on(async {
+                for data in 0..6 {
+                    batcher.send(data).await.unwrap();
+                }
+
+                batcher.flush().await.unwrap();
+
+                batcher.send(u32::MAX).await.unwrap();
+                batcher.flush().await.unwrap();
+            });
+
+            256 - write.len()
+        };
+
+        let mut buf = [0; 64];
+        let receiver = StreamReceiver::<_, Vec<u32, 4>>::new(&wire[..len], &mut buf);
+        let mut unbatcher = Unbatcher::new(receiver);
+
+        block_on(async {
+            for data in (0..6).chain([u32::MAX]) {
+                assert_eq!(unbatcher.recv().await.unwrap(), Some(data));
+            }
+
+            assert_eq!(unbatcher.recv().await.unwrap(), None);
+        });
+    }
+
+    #[cfg(all(feature = "stream", not(feature = "prost")))]
+    #[test]
+    fn oversized() {
+        use crate::asynch::stream::{StreamError, StreamReceiver, StreamSender};
+        use crate::codec::CodecError;
+
+        let clock = TestClock(Cell::new(0));
+
+        let mut wire = [0; 256];
+
+        let len = {
+            let mut write = &mut wire[..];
+            let mut buf = [0; 16];
+            let sender = StreamSender::<_, Vec<&[u8], 4>>::new(&mut write, &mut buf);
+            let mut batcher = Batcher::new(sender, &clock, 100);
+
+            block_on(async {
+                batcher.send(b"small").await.unwrap();
+
+                // A single message which does not fit in the buffer of the wrapped sender
+                batcher.send(&[0x55; 32]).await.unwrap();
+
+                assert!(matches!(
+                    batcher.flush().await,
+                    Err(StreamError::CodecError(CodecError::PostcardError(_)))
+                ));
+
+                // The failed batch is gone, and the next one is sent as usual
+                assert!(batcher.is_empty());
+
+                batcher.send(b"next").await.unwrap();
+                batcher.flush().await.unwrap();
+            });
+
+            256 - write.len()
+        };
+
+        let mut buf = [0; 16];
+        let mut receiver = StreamReceiver::<_, Vec<&[u8], 4>>::new(&wire[..len], &mut buf);
+
+        block_on(async {
+            let batch = receiver.recv_borrowed::<Vec<&[u8], 4>>().await.unwrap();
+            assert_eq!(batch.as_deref(), Some(&[&b"next"[..]][..]));
+        });
+    }
+}
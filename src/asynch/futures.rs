@@ -0,0 +1,477 @@
+use core::fmt::Debug;
+use core::future::{poll_fn, Future};
+use core::marker::PhantomData;
+use core::pin::Pin;
+use core::task::{ready, Context, Poll};
+
+use futures_core::{FusedStream, Stream};
+use futures_sink::Sink;
+
+use pin_project_lite::pin_project;
+
+use crate::ChannelError;
+
+use super::{Receiver, Sender};
+
+/// Convert a `Receiver` into a `Stream` yielding the results of consecutive `recv` calls.
+///
+/// The stream ends after yielding the first error, which is how most receivers report that
+/// they are closed. A receiver which signals closure by a `None` value will yield that value.
+pub fn into_stream<R>(receiver: R) -> impl FusedStream<Item = Result<R::Data, R::Error>>
+where
+    R: Receiver,
+{
+    RecvStream {
+        recv: |mut receiver: R| async move {
+            let result = receiver.recv().await;
+
+            (result, receiver)
+        },
+        state: State::Value { value: receiver },
+    }
+}
+
+/// Convert a `Sender` into a `Sink`.
+pub fn into_sink<S>(sender: S) -> impl Sink<S::Data, Error = S::Error>
+where
+    S: Sender,
+{
+    SendSink {
+        send: |mut sender: S, data: S::Data| async move {
+            sender.send(data).await?;
+
+            Ok(sender)
+        },
+        state: State::Value { value: sender },
+        _data: PhantomData,
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    #[project_replace = StateProjReplace]
+    enum State<T, F> {
+        Value { value: T },
+        Future { #[pin] future: F },
+        Empty,
+    }
+}
+
+impl<T, F> State<T, F> {
+    fn take_value(self: Pin<&mut Self>) -> Option<T> {
+        match &*self {
+            Self::Value { .. } => match self.project_replace(Self::Empty) {
+                StateProjReplace::Value { value } => Some(value),
+                _ => unreachable!(),
+            },
+            _ => None,
+        }
+    }
+}
+
+pin_project! {
+    struct RecvStream<R, F, Fut> {
+        recv: F,
+        #[pin]
+        state: State<R, Fut>,
+    }
+}
+
+impl<R, F, Fut, T, E> Stream for RecvStream<R, F, Fut>
+where
+    F: FnMut(R) -> Fut,
+    Fut: Future<Output = (Result<T, E>, R)>,
+{
+    type Item = Result<T, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        if let Some(receiver) = this.state.as_mut().take_value() {
+            this.state.set(State::Future {
+                future: (this.recv)(receiver),
+            });
+        }
+
+        let (result, receiver) = match this.state.as_mut().project() {
+            StateProj::Future { future } => ready!(future.poll(cx)),
+            _ => return Poll::Ready(None),
+        };
+
+        if result.is_err() {
+            this.state.set(State::Empty);
+        } else {
+            this.state.set(State::Value { value: receiver });
+        }
+
+        Poll::Ready(Some(result))
+    }
+}
+
+impl<R, F, Fut, T, E> FusedStream for RecvStream<R, F, Fut>
+where
+    F: FnMut(R) -> Fut,
+    Fut: Future<Output = (Result<T, E>, R)>,
+{
+    fn is_terminated(&self) -> bool {
+        matches!(self.state, State::Empty)
+    }
+}
+
+pin_project! {
+    struct SendSink<S, F, Fut, T> {
+        send: F,
+        #[pin]
+        state: State<S, Fut>,
+        _data: PhantomData<fn(T)>,
+    }
+}
+
+impl<S, F, Fut, T, E> SendSink<S, F, Fut, T>
+where
+    F: FnMut(S, T) -> Fut,
+    Fut: Future<Output = Result<S, E>>,
+{
+    /// Complete the send in progress, if any.
+    fn poll_sent(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), E>> {
+        let mut this = self.project();
+
+        let result = match this.state.as_mut().project() {
+            StateProj::Future { future } => ready!(future.poll(cx)),
+            _ => return Poll::Ready(Ok(())),
+        };
+
+        match result {
+            Ok(sender) => {
+                this.state.set(State::Value { value: sender });
+
+                Poll::Ready(Ok(()))
+            }
+            Err(e) => {
+                this.state.set(State::Empty);
+
+                Poll::Ready(Err(e))
+            }
+        }
+    }
+}
+
+impl<S, F, Fut, T, E> Sink<T> for SendSink<S, F, Fut, T>
+where
+    F: FnMut(S, T) -> Fut,
+    Fut: Future<Output = Result<S, E>>,
+{
+    type Error = E;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_sent(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        let mut this = self.project();
+
+        let sender = this
+            .state
+            .as_mut()
+            .take_value()
+            .expect("start_send called without poll_ready or after an error");
+
+        this.state.set(State::Future {
+            future: (this.send)(sender, item),
+        });
+
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_sent(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_sent(cx)
+    }
+}
+
+/// Receive the next item of a `Stream`.
+async fn next<S>(stream: &mut S) -> Option<S::Item>
+where
+    S: Stream + Unpin,
+{
+    poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+}
+
+/// Send an item to a `Sink` and flush it.
+async fn send<S, T>(sink: &mut S, data: T) -> Result<(), S::Error>
+where
+    S: Sink<T> + Unpin,
+{
+    poll_fn(|cx| Pin::new(&mut *sink).poll_ready(cx)).await?;
+    Pin::new(&mut *sink).start_send(data)?;
+    poll_fn(|cx| Pin::new(&mut *sink).poll_flush(cx)).await
+}
+
+/// A `Receiver` over any `Stream`.
+///
+/// The end of the stream is reported as `ChannelError::Disconnected`.
+pub struct FromStream<S>(S);
+
+impl<S> FromStream<S> {
+    pub const fn new(stream: S) -> Self {
+        Self(stream)
+    }
+
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+}
+
+impl<S> Receiver for FromStream<S>
+where
+    S: Stream + Unpin,
+{
+    type Error = ChannelError;
+
+    type Data = S::Item;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        next(&mut self.0).await.ok_or(ChannelError::Disconnected)
+    }
+}
+
+/// A `Sender` over any `Sink`.
+pub struct FromSink<S, T>(S, PhantomData<fn() -> T>);
+
+impl<S, T> FromSink<S, T> {
+    pub const fn new(sink: S) -> Self {
+        Self(sink, PhantomData)
+    }
+
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+}
+
+impl<S, T> Sender for FromSink<S, T>
+where
+    S: Sink<T> + Unpin,
+    S::Error: Debug,
+{
+    type Error = S::Error;
+
+    type Data = T;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        send(&mut self.0, data).await
+    }
+}
+
+#[cfg(feature = "std")]
+mod channel_impl {
+    use core::future::poll_fn;
+
+    use futures_channel::mpsc;
+
+    use crate::asynch::{Receiver, Sender};
+    use crate::ChannelError;
+
+    impl<T> Sender for mpsc::Sender<T> {
+        type Error = ChannelError;
+
+        type Data = T;
+
+        async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+            poll_fn(|cx| self.poll_ready(cx))
+                .await
+                .and_then(|()| self.start_send(data))
+                .map_err(|_| ChannelError::Disconnected)
+        }
+    }
+
+    impl<T> Sender for mpsc::UnboundedSender<T> {
+        type Error = ChannelError;
+
+        type Data = T;
+
+        async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+            self.unbounded_send(data)
+                .map_err(|_| ChannelError::Disconnected)
+        }
+    }
+
+    impl<T> Receiver for mpsc::Receiver<T> {
+        type Error = ChannelError;
+
+        type Data = T;
+
+        async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+            super::next(self).await.ok_or(ChannelError::Disconnected)
+        }
+    }
+
+    impl<T> Receiver for mpsc::UnboundedReceiver<T> {
+        type Error = ChannelError;
+
+        type Data = T;
+
+        async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+            super::next(self).await.ok_or(ChannelError::Disconnected)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use core::pin::pin;
+
+    use std::boxed::Box;
+
+    use futures_channel::mpsc;
+
+    use crate::adapt::block_on;
+
+    use super::*;
+
+    #[test]
+    fn channel() {
+        block_on(async {
+            let (mut sender, mut receiver) = mpsc::channel(2);
+
+            Sender::send(&mut sender, 1).await.unwrap();
+            Sender::send(&mut sender, 2).await.unwrap();
+
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(1));
+
+            drop(sender);
+
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(2));
+            assert_eq!(
+                Receiver::recv(&mut receiver).await,
+                Err(ChannelError::Disconnected)
+            );
+
+            let (mut sender, receiver) = mpsc::channel(2);
+
+            drop(receiver);
+
+            assert_eq!(
+                Sender::send(&mut sender, 1).await,
+                Err(ChannelError::Disconnected)
+            );
+        });
+    }
+
+    #[test]
+    fn unbounded_channel() {
+        block_on(async {
+            let (mut sender, mut receiver) = mpsc::unbounded();
+
+            Sender::send(&mut sender, 1).await.unwrap();
+
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(1));
+
+            drop(receiver);
+
+            assert_eq!(
+                Sender::send(&mut sender, 2).await,
+                Err(ChannelError::Disconnected)
+            );
+        });
+    }
+
+    #[test]
+    fn stream() {
+        let (mut sender, receiver) = mpsc::channel::<u32>(4);
+
+        block_on(async {
+            Sender::send(&mut sender, 1).await.unwrap();
+            Sender::send(&mut sender, 2).await.unwrap();
+        });
+
+        drop(sender);
+
+        let mut stream = pin!(into_stream(receiver));
+
+        block_on(async {
+            assert_eq!(next(&mut stream).await, Some(Ok(1)));
+            assert_eq!(next(&mut stream).await, Some(Ok(2)));
+            assert!(!stream.is_terminated());
+
+            assert_eq!(
+                next(&mut stream).await,
+                Some(Err(ChannelError::Disconnected))
+            );
+            assert!(stream.is_terminated());
+
+            // The stream stays closed instead of polling the receiver again
+            assert_eq!(next(&mut stream).await, None);
+            assert_eq!(next(&mut stream).await, None);
+        });
+    }
+
+    #[test]
+    fn sink() {
+        let (sender, mut receiver) = mpsc::unbounded::<u32>();
+
+        let mut sink = pin!(into_sink(sender));
+
+        block_on(async {
+            send(&mut sink, 1).await.unwrap();
+            send(&mut sink, 2).await.unwrap();
+
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(1));
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(2));
+
+            drop(receiver);
+
+            assert_eq!(send(&mut sink, 3).await, Err(ChannelError::Disconnected));
+        });
+    }
+
+    #[test]
+    fn from_stream_and_sink() {
+        let (sender, receiver) = mpsc::channel::<u32>(4);
+
+        let mut sender = FromSink::new(sender);
+        let mut receiver = FromStream::new(receiver);
+
+        block_on(async {
+            Sender::send(&mut sender, 1).await.unwrap();
+            Sender::send(&mut sender, 2).await.unwrap();
+
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(1));
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(2));
+
+            drop(sender);
+
+            assert_eq!(
+                Receiver::recv(&mut receiver).await,
+                Err(ChannelError::Disconnected)
+            );
+        });
+    }
+
+    #[test]
+    fn roundtrip() {
+        // A `Receiver` turned into a `Stream` and back again
+        let (mut sender, receiver) = mpsc::unbounded::<u32>();
+
+        let mut receiver = FromStream::new(Box::pin(into_stream(receiver)));
+
+        block_on(async {
+            Sender::send(&mut sender, 1).await.unwrap();
+
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(Ok(1)));
+
+            drop(sender);
+
+            assert_eq!(
+                Receiver::recv(&mut receiver).await,
+                Ok(Err(ChannelError::Disconnected))
+            );
+            assert_eq!(
+                Receiver::recv(&mut receiver).await,
+                Err(ChannelError::Disconnected)
+            );
+        });
+    }
+}
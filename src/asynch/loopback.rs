@@ -0,0 +1,573 @@
+//! An in-memory, connected pair of websocket channels for testing.
+//!
+//! The pair runs the exact encode / decode path of `WsSender` and `WsReceiver` over an in-memory
+//! pipe, so codec errors and oversized frames surface just like they would over a real socket.
+//! Each frame travels through a `FaultInjector`, which can drop, duplicate or corrupt it,
+//! and is then held back by the `Latency` of the link until it is due.
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::pipe::Pipe;
+
+use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+
+use edge_ws::io;
+
+use crate::codec::{ReceiveData, SendData};
+
+use super::time::Clock;
+use super::ws::{WsError, WsReceiver, WsSender};
+
+#[cfg(feature = "std")]
+pub use std_impl::*;
+
+/// The due time and the length which precede each frame in the pipe.
+const STAMP_LEN: usize = 12;
+
+/// What happens to a frame on its way to the receiver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    Deliver,
+    Drop,
+    Duplicate,
+    /// Flip the bits of the payload byte at the given offset (modulo the payload length)
+    Corrupt(usize),
+}
+
+/// Decides the fate of each frame.
+pub trait FaultInjector {
+    fn inject(&mut self, frame: &[u8]) -> Fault;
+}
+
+impl<F> FaultInjector for F
+where
+    F: FnMut(&[u8]) -> Fault,
+{
+    fn inject(&mut self, frame: &[u8]) -> Fault {
+        self(frame)
+    }
+}
+
+/// A `FaultInjector` delivering all frames as-is.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoFaults;
+
+impl FaultInjector for NoFaults {
+    fn inject(&mut self, _frame: &[u8]) -> Fault {
+        Fault::Deliver
+    }
+}
+
+/// A deterministic `FaultInjector`, which drops, duplicates or corrupts every N-th frame
+/// (`0` disables the fault).
+#[derive(Debug, Default, Clone)]
+pub struct Faults {
+    pub drop_every: u32,
+    pub duplicate_every: u32,
+    pub corrupt_every: u32,
+    count: u32,
+}
+
+impl Faults {
+    pub const fn new() -> Self {
+        Self {
+            drop_every: 0,
+            duplicate_every: 0,
+            corrupt_every: 0,
+            count: 0,
+        }
+    }
+}
+
+impl FaultInjector for Faults {
+    fn inject(&mut self, _frame: &[u8]) -> Fault {
+        self.count = self.count.wrapping_add(1);
+
+        let every = |n: u32| n > 0 && self.count % n == 0;
+
+        if every(self.drop_every) {
+            Fault::Drop
+        } else if every(self.duplicate_every) {
+            Fault::Duplicate
+        } else if every(self.corrupt_every) {
+            Fault::Corrupt(self.count as usize)
+        } else {
+            Fault::Deliver
+        }
+    }
+}
+
+/// Holds the frames back on the receiving end, without blocking the sender.
+pub trait Latency {
+    /// The instant at which a frame sent now is due for delivery.
+    fn due(&self) -> u64;
+
+    /// Wait until the given instant, as returned by `due`.
+    async fn wait_until(&self, due: u64);
+}
+
+/// Delivers the frames right away.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoLatency;
+
+impl Latency for NoLatency {
+    fn due(&self) -> u64 {
+        0
+    }
+
+    async fn wait_until(&self, _due: u64) {}
+}
+
+/// Delivers each frame `latency` milliseconds after it was sent.
+///
+/// The frames are delivered in order, so a frame is never delivered before the previous one.
+#[derive(Debug, Clone, Copy)]
+pub struct Delay<C> {
+    pub clock: C,
+    pub latency: u64,
+}
+
+impl<C> Delay<C>
+where
+    C: Clock,
+{
+    pub const fn new(clock: C, latency: u64) -> Self {
+        Self { clock, latency }
+    }
+}
+
+impl<C> Latency for Delay<C>
+where
+    C: Clock,
+{
+    fn due(&self) -> u64 {
+        self.clock.now() + self.latency
+    }
+
+    async fn wait_until(&self, due: u64) {
+        self.clock.wait_until(due).await
+    }
+}
+
+/// The in-memory link. `N` is the capacity of the pipe, which also bounds the size of a frame.
+///
+/// Frames in flight stay in the pipe until they are due, so the sender only blocks
+/// once the pipe is full.
+pub struct Loopback<M, L, const N: usize>
+where
+    M: RawMutex,
+{
+    pipe: Pipe<M, N>,
+    frame: RefCell<heapless::Vec<u8, N>>,
+    latency: L,
+}
+
+impl<M, const N: usize> Loopback<M, NoLatency, N>
+where
+    M: RawMutex,
+{
+    pub const fn new() -> Self {
+        Self::with_latency(NoLatency)
+    }
+}
+
+impl<M, L, const N: usize> Loopback<M, L, N>
+where
+    M: RawMutex,
+{
+    pub const fn with_latency(latency: L) -> Self {
+        Self {
+            pipe: Pipe::new(),
+            frame: RefCell::new(heapless::Vec::new()),
+            latency,
+        }
+    }
+
+    /// Create the connected sender and receiver ends.
+    ///
+    /// As with `WsSender` and `WsReceiver`, the buffers bound the size of a serialized message;
+    /// a `recv_buf` smaller than `send_buf` can be used to provoke `WsError::OversizedFrame`.
+    pub fn pair<'a, D, F>(
+        &'a self,
+        send_buf: &'a mut [u8],
+        recv_buf: &'a mut [u8],
+        faults: F,
+    ) -> LoopbackPair<'a, M, L, N, D, F>
+    where
+        L: Latency,
+        D: SendData + ReceiveData,
+        F: FaultInjector,
+    {
+        (
+            LoopbackSender {
+                sender: WsSender::new(Capture(&self.frame), send_buf, None),
+                link: self,
+                faults,
+            },
+            WsReceiver::new(
+                LoopbackRead {
+                    link: self,
+                    due: None,
+                    remaining: 0,
+                },
+                recv_buf,
+            ),
+        )
+    }
+}
+
+impl<M, const N: usize> Default for Loopback<M, NoLatency, N>
+where
+    M: RawMutex,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The connected ends returned by `Loopback::pair`.
+pub type LoopbackPair<'a, M, L, const N: usize, D, F> = (
+    LoopbackSender<'a, M, L, N, D, F>,
+    LoopbackReceiver<'a, M, L, N, D>,
+);
+
+pub type LoopbackReceiver<'a, M, L, const N: usize, D> =
+    WsReceiver<'a, LoopbackRead<'a, M, L, N>, D>;
+
+pub struct LoopbackSender<'a, M, L, const N: usize, D, F>
+where
+    M: RawMutex,
+{
+    sender: WsSender<'a, Capture<'a, N>, D>,
+    link: &'a Loopback<M, L, N>,
+    faults: F,
+}
+
+impl<M, L, const N: usize, D, F> LoopbackSender<'_, M, L, N, D, F>
+where
+    M: RawMutex,
+    L: Latency,
+    D: SendData,
+    F: FaultInjector,
+{
+    pub async fn send(&mut self, data: D) -> Result<(), WsError<io::Error<ErrorKind>>> {
+        self.link.frame.borrow_mut().clear();
+
+        self.sender.send(data).await?;
+
+        let mut frame = self.link.frame.borrow().clone();
+
+        let due = self.link.latency.due();
+
+        match self.faults.inject(&frame) {
+            Fault::Deliver => self.deliver(due, &frame).await,
+            Fault::Drop => (),
+            Fault::Duplicate => {
+                self.deliver(due, &frame).await;
+                self.deliver(due, &frame).await;
+            }
+            Fault::Corrupt(offset) => {
+                let header_len = header_len(&frame);
+                let payload_len = frame.len() - header_len;
+
+                if payload_len > 0 {
+                    frame[header_len + offset % payload_len] ^= 0xff;
+                }
+
+                self.deliver(due, &frame).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn deliver(&self, due: u64, frame: &[u8]) {
+        let mut stamp = [0; STAMP_LEN];
+        stamp[..8].copy_from_slice(&due.to_le_bytes());
+        stamp[8..].copy_from_slice(&(frame.len() as u32).to_le_bytes());
+
+        let _ = Write::write_all(&mut &self.link.pipe, &stamp).await;
+        let _ = Write::write_all(&mut &self.link.pipe, frame).await;
+    }
+}
+
+impl<M, L, const N: usize, D, F> crate::asynch::Sender for LoopbackSender<'_, M, L, N, D, F>
+where
+    M: RawMutex,
+    L: Latency,
+    D: SendData,
+    F: FaultInjector,
+{
+    type Error = WsError<io::Error<ErrorKind>>;
+
+    type Data = D;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        LoopbackSender::send(self, data).await
+    }
+}
+
+/// Reads the frames out of the pipe, each one once it is due.
+pub struct LoopbackRead<'a, M, L, const N: usize>
+where
+    M: RawMutex,
+{
+    link: &'a Loopback<M, L, N>,
+    due: Option<(u64, usize)>,
+    remaining: usize,
+}
+
+impl<M, L, const N: usize> ErrorType for LoopbackRead<'_, M, L, N>
+where
+    M: RawMutex,
+{
+    type Error = ErrorKind;
+}
+
+impl<M, L, const N: usize> Read for LoopbackRead<'_, M, L, N>
+where
+    M: RawMutex,
+    L: Latency,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.remaining == 0 {
+            let (due, len) = match self.due {
+                Some(due) => due,
+                None => {
+                    let mut stamp = [0; STAMP_LEN];
+                    let _ = Read::read_exact(&mut &self.link.pipe, &mut stamp).await;
+
+                    let due = u64::from_le_bytes(stamp[..8].try_into().unwrap());
+                    let len = u32::from_le_bytes(stamp[8..].try_into().unwrap()) as usize;
+
+                    *self.due.insert((due, len))
+                }
+            };
+
+            self.link.latency.wait_until(due).await;
+
+            self.due = None;
+            self.remaining = len;
+        }
+
+        let len = buf.len().min(self.remaining);
+        let read = self.link.pipe.read(&mut buf[..len]).await;
+
+        self.remaining -= read;
+
+        Ok(read)
+    }
+}
+
+/// Captures the frame written by `WsSender`, so that it can be passed through the `FaultInjector`.
+pub struct Capture<'a, const N: usize>(&'a RefCell<heapless::Vec<u8, N>>);
+
+impl<const N: usize> ErrorType for Capture<'_, N> {
+    type Error = ErrorKind;
+}
+
+impl<const N: usize> Write for Capture<'_, N> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.0
+            .borrow_mut()
+            .extend_from_slice(buf)
+            .map_err(|_| ErrorKind::OutOfMemory)?;
+
+        Ok(buf.len())
+    }
+}
+
+/// The length of the header of an unmasked or masked websocket frame.
+fn header_len(frame: &[u8]) -> usize {
+    let len_len = match frame[1] & 0x7f {
+        126 => 2,
+        127 => 8,
+        _ => 0,
+    };
+
+    let mask_len = if frame[1] & 0x80 != 0 { 4 } else { 0 };
+
+    2 + len_len + mask_len
+}
+
+#[cfg(feature = "std")]
+mod std_impl {
+    use std::boxed::Box;
+    use std::vec;
+
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use crate::asynch::ws::DEFAULT_BUF_SIZE;
+
+    use super::*;
+
+    pub type PairSender<D, L = NoLatency, F = NoFaults> =
+        LoopbackSender<'static, NoopRawMutex, L, DEFAULT_BUF_SIZE, D, F>;
+
+    pub type PairReceiver<D, L = NoLatency> =
+        LoopbackReceiver<'static, NoopRawMutex, L, DEFAULT_BUF_SIZE, D>;
+
+    /// Create a connected sender and receiver pair, which delivers all frames as-is.
+    ///
+    /// The link and the `DEFAULT_BUF_SIZE` buffers are leaked, which is meant for tests;
+    /// use `Loopback::pair` to provide them instead.
+    pub fn pair<D>() -> (PairSender<D>, PairReceiver<D>)
+    where
+        D: SendData + ReceiveData,
+    {
+        pair_with(NoLatency, NoFaults)
+    }
+
+    /// Like `pair`, but with the given latency and fault injection.
+    pub fn pair_with<D, L, F>(latency: L, faults: F) -> (PairSender<D, L, F>, PairReceiver<D, L>)
+    where
+        D: SendData + ReceiveData,
+        L: Latency + 'static,
+        F: FaultInjector,
+    {
+        let link = Box::leak(Box::new(Loopback::with_latency(latency)));
+
+        let send_buf = vec![0; DEFAULT_BUF_SIZE].leak();
+        let recv_buf = vec![0; DEFAULT_BUF_SIZE].leak();
+
+        link.pair(send_buf, recv_buf, faults)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use core::cell::Cell;
+    use core::future::poll_fn;
+    use core::pin::pin;
+    use core::task::Poll;
+
+    use std::vec::Vec;
+
+    use embassy_futures::poll_once;
+
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use crate::adapt::block_on;
+
+    use super::*;
+
+    /// A clock which only moves when set, with `wait_until` pending until then.
+    struct TestClock(Cell<u64>);
+
+    impl Clock for TestClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+
+        async fn wait_until(&self, instant: u64) {
+            poll_fn(|cx| {
+                if self.0.get() >= instant {
+                    Poll::Ready(())
+                } else {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            })
+            .await
+        }
+    }
+
+    fn exchange<F>(faults: F, count: u32) -> Vec<u32>
+    where
+        F: FaultInjector,
+    {
+        let (mut sender, mut receiver) = pair_with::<u32, _, _>(NoLatency, faults);
+
+        block_on(async {
+            for value in 1..=count {
+                sender.send(value).await.unwrap();
+            }
+
+            // Marks the end, as there is no way to close the link
+            sender.send(0).await.unwrap();
+
+            let mut received = Vec::new();
+
+            loop {
+                match receiver.recv().await.unwrap() {
+                    Some(0) => break received,
+                    Some(value) => received.push(value),
+                    None => unreachable!(),
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn delivered() {
+        let (mut sender, mut receiver) = pair::<u32>();
+
+        block_on(async {
+            for value in [1, 2, u32::MAX] {
+                sender.send(value).await.unwrap();
+            }
+
+            for value in [1, 2, u32::MAX] {
+                assert_eq!(receiver.recv().await.unwrap(), Some(value));
+            }
+        });
+    }
+
+    #[test]
+    fn dropped() {
+        let mut faults = Faults::new();
+        faults.drop_every = 2;
+
+        // The end marker is the 7th frame
+        assert_eq!(exchange(faults, 6), [1, 3, 5]);
+    }
+
+    #[test]
+    fn duplicated() {
+        let mut faults = Faults::new();
+        faults.duplicate_every = 3;
+
+        assert_eq!(exchange(faults, 5), [1, 2, 3, 3, 4, 5]);
+    }
+
+    #[test]
+    fn latency() {
+        let clock = TestClock(Cell::new(0));
+
+        let link = Loopback::<NoopRawMutex, _, 64>::with_latency(Delay::new(&clock, 10));
+
+        let mut send_buf = [0; 8];
+        let mut recv_buf = [0; 8];
+
+        let (mut sender, mut receiver) =
+            link.pair::<u32, _>(&mut send_buf, &mut recv_buf, NoFaults);
+
+        // Sending completes right away, while the frames are still in flight
+        assert!(poll_once(sender.send(1)).is_ready());
+
+        clock.0.set(5);
+        assert!(poll_once(sender.send(2)).is_ready());
+
+        {
+            let mut recv = pin!(receiver.recv());
+
+            clock.0.set(9);
+            assert!(poll_once(&mut recv).is_pending());
+
+            clock.0.set(10);
+            assert!(matches!(poll_once(&mut recv), Poll::Ready(Ok(Some(1)))));
+        }
+
+        let mut recv = pin!(receiver.recv());
+
+        assert!(poll_once(&mut recv).is_pending());
+
+        clock.0.set(15);
+        assert!(matches!(poll_once(&mut recv), Poll::Ready(Ok(Some(2)))));
+    }
+}
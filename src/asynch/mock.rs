@@ -0,0 +1,172 @@
+//! Mock senders and receivers for testing code which is generic over `Sender` / `Receiver`.
+//!
+//! Since `Sender` and `Receiver` are implemented for `&mut T`, a mock can be lent to the code
+//! under test and inspected afterwards.
+
+use core::convert::Infallible;
+use core::fmt::Debug;
+use core::future::poll_fn;
+use core::task::Poll;
+
+use std::collections::VecDeque;
+
+use super::{Receiver, Sender};
+
+/// A sender which records every sent item.
+///
+/// By default all sends succeed; failures can be scripted with `fail_next`.
+pub struct MockSender<T, E = Infallible> {
+    sent: Vec<T>,
+    errors: VecDeque<Option<E>>,
+}
+
+impl<T, E> MockSender<T, E> {
+    pub const fn new() -> Self {
+        Self {
+            sent: Vec::new(),
+            errors: VecDeque::new(),
+        }
+    }
+
+    /// Fail the next not yet scripted send with `error`; the failed item is not recorded.
+    ///
+    /// Calls are queued, so `ok_next` followed by `fail_next` fails the second send.
+    pub fn fail_next(&mut self, error: E) -> &mut Self {
+        self.errors.push_back(Some(error));
+        self
+    }
+
+    /// Let the next not yet scripted send succeed.
+    pub fn ok_next(&mut self) -> &mut Self {
+        self.errors.push_back(None);
+        self
+    }
+
+    /// The items sent so far, in order.
+    pub fn sent(&self) -> &[T] {
+        &self.sent
+    }
+
+    /// Return the items sent so far and clear the record.
+    pub fn take_sent(&mut self) -> Vec<T> {
+        core::mem::take(&mut self.sent)
+    }
+}
+
+impl<T, E> Default for MockSender<T, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, E> Sender for MockSender<T, E>
+where
+    E: Debug,
+{
+    type Error = E;
+
+    type Data = T;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        if let Some(error) = self.errors.pop_front().flatten() {
+            return Err(error);
+        }
+
+        self.sent.push(data);
+
+        Ok(())
+    }
+}
+
+/// A scripted `MockReceiver` event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event<T, E> {
+    /// `recv` returns `Ok(Some(data))`
+    Ok(T),
+    /// `recv` returns `Err(error)`
+    Err(E),
+    /// `recv` returns `Poll::Pending` once (waking itself), before moving on to the next event
+    Pending,
+    /// `recv` returns `Ok(None)`, i.e. the channel is closed
+    Close,
+}
+
+/// A receiver which replays a scripted sequence of events.
+///
+/// As with the other receivers in this crate, `Data` is `Option<T>`, with `None` signalling
+/// that the channel is closed. Once the script is exhausted, `recv` never completes.
+pub struct MockReceiver<T, E = Infallible> {
+    events: VecDeque<Event<T, E>>,
+}
+
+impl<T, E> MockReceiver<T, E> {
+    pub const fn new() -> Self {
+        Self {
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Append an event to the script.
+    pub fn push(&mut self, event: Event<T, E>) -> &mut Self {
+        self.events.push_back(event);
+        self
+    }
+
+    /// The number of events which have not been replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Whether all scripted events have been replayed.
+    pub fn is_done(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+impl<T, E> Default for MockReceiver<T, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, E> FromIterator<Event<T, E>> for MockReceiver<T, E> {
+    fn from_iter<I: IntoIterator<Item = Event<T, E>>>(iter: I) -> Self {
+        Self {
+            events: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<T, E> Receiver for MockReceiver<T, E>
+where
+    E: Debug,
+{
+    type Error = E;
+
+    type Data = Option<T>;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        loop {
+            match self.events.pop_front() {
+                Some(Event::Ok(data)) => break Ok(Some(data)),
+                Some(Event::Err(error)) => break Err(error),
+                Some(Event::Close) => break Ok(None),
+                Some(Event::Pending) => {
+                    let mut yielded = false;
+
+                    poll_fn(|cx| {
+                        if yielded {
+                            Poll::Ready(())
+                        } else {
+                            yielded = true;
+                            cx.waker().wake_by_ref();
+                            Poll::Pending
+                        }
+                    })
+                    .await;
+                }
+                None => core::future::pending().await,
+            }
+        }
+    }
+}
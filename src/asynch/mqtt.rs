@@ -0,0 +1,1687 @@
+//! Typed channels over MQTT.
+//!
+//! With the `mqtt` feature, a minimal `no_std` MQTT 3.1.1 client runs over the read and write
+//! halves of an `embedded-io-async` TCP connection. `MqttClient` owns the write half and is shared
+//! by any number of `MqttSender`s, each of which publishes its messages to a topic with QoS 0 or 1.
+//! `MqttReceiver` owns the read half and receives the messages of the client's subscriptions,
+//! optionally only those on the topics matching a topic filter.
+//!
+//! As `MqttReceiver` is the only reader of the connection, it is also the one which processes
+//! the acknowledgements of the broker, so it needs to be polled - even if the client does not
+//! subscribe to anything - for QoS 1 publishes to complete.
+//!
+//! With the `embedded-svc` feature, `MqttSvcSender` and `MqttSvcReceiver` adapt any client
+//! implementing the `embedded-svc` MQTT traits (i.e. the ESP-IDF one) instead.
+
+use core::fmt::{self, Debug, Display};
+
+use crate::codec::CodecError;
+
+#[cfg(feature = "mqtt")]
+pub use client_impl::*;
+
+#[cfg(feature = "embedded-svc")]
+pub use embedded_svc_impl::*;
+
+#[derive(Debug)]
+pub enum MqttError<E> {
+    IoError(E),
+    CodecError(CodecError),
+    /// The broker refused the connection with the given CONNACK return code
+    ConnectionRefused(u8),
+    /// The broker refused a subscription
+    SubscriptionRefused,
+    /// The client reported an error event
+    ConnectionError,
+    /// The client got disconnected from the broker; receiving can continue once it reconnects
+    Disconnected,
+    ProtocolError,
+    UnexpectedEof,
+    OversizedFrame(usize),
+}
+
+impl<E> Display for MqttError<E>
+where
+    E: Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IoError(e) => write!(f, "IO Error: {e}"),
+            Self::CodecError(e) => write!(f, "Codec Error: {e}"),
+            Self::ConnectionRefused(code) => {
+                write!(f, "Connection Refused Error: Return code {code}")
+            }
+            Self::SubscriptionRefused => write!(f, "Subscription Refused Error"),
+            Self::ConnectionError => write!(f, "Connection Error"),
+            Self::Disconnected => write!(f, "Disconnected Error"),
+            Self::ProtocolError => write!(f, "Protocol Error"),
+            Self::UnexpectedEof => write!(f, "Unexpected EOF Error"),
+            Self::OversizedFrame(delta) => write!(
+                f,
+                "Oversized Frame Error: Frame exceeds max size by {delta}"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E> std::error::Error for MqttError<E> where E: Display + Debug {}
+
+impl<E> From<CodecError> for MqttError<E> {
+    fn from(e: CodecError) -> Self {
+        Self::CodecError(e)
+    }
+}
+
+/// Whether `topic` matches `topic_filter`, with its `+` (single level) and `#` (all remaining
+/// levels) wildcards. As per the MQTT specification, wildcards at the first level do not
+/// match topics starting with `$`.
+fn topic_matches(topic_filter: &str, topic: &str) -> bool {
+    if topic.starts_with('$') && (topic_filter.starts_with('+') || topic_filter.starts_with('#')) {
+        return false;
+    }
+
+    let mut filter_levels = topic_filter.split('/');
+    let mut topic_levels = topic.split('/');
+
+    loop {
+        match (filter_levels.next(), topic_levels.next()) {
+            (Some("#"), _) => break true,
+            (Some("+"), Some(_)) => (),
+            (Some(filter_level), Some(topic_level)) if filter_level == topic_level => (),
+            (None, None) => break true,
+            _ => break false,
+        }
+    }
+}
+
+#[cfg(feature = "mqtt")]
+mod client_impl {
+    use core::cell::Cell;
+    use core::marker::PhantomData;
+    use core::ops::Range;
+
+    use embassy_futures::select::{select, Either};
+
+    use embassy_sync::blocking_mutex::{raw::RawMutex, Mutex as BlockingMutex};
+    use embassy_sync::mutex::Mutex;
+    use embassy_sync::signal::Signal;
+
+    use embedded_io_async::{Read, ReadExactError, Write};
+
+    use crate::codec::{self, LenDecoder, ReceiveData, SendData, MAX_LEN_PREFIX_SIZE};
+
+    use crate::asynch::time::Clock;
+
+    use super::*;
+
+    const CONNECT: u8 = 0x10;
+    const CONNACK: u8 = 0x20;
+    const PUBLISH: u8 = 0x30;
+    const PUBACK: u8 = 0x40;
+    const SUBSCRIBE: u8 = 0x82;
+    const SUBACK: u8 = 0x90;
+    const PINGREQ: u8 = 0xc0;
+    const PINGRESP: u8 = 0xd0;
+    const DISCONNECT: u8 = 0xe0;
+
+    const MAX_REMAINING_LEN: usize = 268_435_455;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum QoS {
+        AtMostOnce = 0,
+        AtLeastOnce = 1,
+    }
+
+    impl<E> From<ReadExactError<E>> for MqttError<E> {
+        fn from(e: ReadExactError<E>) -> Self {
+            match e {
+                ReadExactError::UnexpectedEof => Self::UnexpectedEof,
+                ReadExactError::Other(e) => Self::IoError(e),
+            }
+        }
+    }
+
+    /// The parameters of the CONNECT packet.
+    #[derive(Debug, Clone)]
+    pub struct ConnectOptions<'a> {
+        pub client_id: &'a str,
+        /// The keep-alive interval in seconds; `0` disables keep-alive
+        pub keep_alive: u16,
+        pub clean_session: bool,
+        pub username: Option<&'a str>,
+        pub password: Option<&'a [u8]>,
+    }
+
+    impl<'a> ConnectOptions<'a> {
+        pub const fn new(client_id: &'a str) -> Self {
+            Self {
+                client_id,
+                keep_alive: 60,
+                clean_session: true,
+                username: None,
+                password: None,
+            }
+        }
+    }
+
+    struct Writer<'a, W> {
+        write: W,
+        buf: &'a mut [u8],
+    }
+
+    impl<W> Writer<'_, W>
+    where
+        W: Write,
+    {
+        /// Write a packet, whose variable header and payload are already in `buf[offset..end]`.
+        async fn write_packet(
+            &mut self,
+            header: u8,
+            offset: usize,
+            end: usize,
+        ) -> Result<(), MqttError<W::Error>> {
+            let remaining_len = end - offset;
+
+            if remaining_len > MAX_REMAINING_LEN {
+                return Err(MqttError::OversizedFrame(remaining_len - MAX_REMAINING_LEN));
+            }
+
+            let mut len_buf = [0; MAX_LEN_PREFIX_SIZE];
+            let len = codec::encode_len(remaining_len as _, &mut len_buf);
+
+            let start = offset - len.len() - 1;
+
+            self.buf[start] = header;
+            self.buf[start + 1..offset].copy_from_slice(len);
+
+            self.write
+                .write_all(&self.buf[start..end])
+                .await
+                .map_err(MqttError::IoError)?;
+            self.write.flush().await.map_err(MqttError::IoError)
+        }
+
+        /// Write a packet consisting of only a fixed header and `data`.
+        async fn write_short(
+            &mut self,
+            header: u8,
+            data: &[u8],
+        ) -> Result<(), MqttError<W::Error>> {
+            self.write
+                .write_all(&[header, data.len() as u8])
+                .await
+                .map_err(MqttError::IoError)?;
+            self.write
+                .write_all(data)
+                .await
+                .map_err(MqttError::IoError)?;
+            self.write.flush().await.map_err(MqttError::IoError)
+        }
+    }
+
+    /// Appends MQTT fields to the end of a buffer, in reverse order.
+    struct Rev<'b> {
+        buf: &'b mut [u8],
+        offset: usize,
+    }
+
+    impl<'b> Rev<'b> {
+        fn new(buf: &'b mut [u8], offset: usize) -> Self {
+            Self { buf, offset }
+        }
+
+        fn bytes<E>(&mut self, data: &[u8]) -> Result<&mut Self, MqttError<E>> {
+            // Leave room for the fixed header
+            if self.offset < data.len() + 1 + MAX_LEN_PREFIX_SIZE {
+                return Err(MqttError::OversizedFrame(
+                    data.len() + 1 + MAX_LEN_PREFIX_SIZE - self.offset,
+                ));
+            }
+
+            self.offset -= data.len();
+            self.buf[self.offset..self.offset + data.len()].copy_from_slice(data);
+
+            Ok(self)
+        }
+
+        fn u16<E>(&mut self, value: u16) -> Result<&mut Self, MqttError<E>> {
+            self.bytes(&value.to_be_bytes())
+        }
+
+        fn string<E>(&mut self, data: &[u8]) -> Result<&mut Self, MqttError<E>> {
+            if data.len() > u16::MAX as usize {
+                return Err(MqttError::OversizedFrame(data.len() - u16::MAX as usize));
+            }
+
+            self.bytes(data)?.u16(data.len() as _)
+        }
+    }
+
+    /// The write half of an MQTT connection.
+    pub struct MqttClient<'a, M, W, C>
+    where
+        M: RawMutex,
+    {
+        writer: Mutex<M, Writer<'a, W>>,
+        packet_id: BlockingMutex<M, Cell<u16>>,
+        keep_alive: BlockingMutex<M, Cell<u16>>,
+        in_flight: Mutex<M, ()>,
+        acked: Signal<M, u16>,
+        clock: C,
+        timeout: u64,
+    }
+
+    impl<'a, M, W, C> MqttClient<'a, M, W, C>
+    where
+        M: RawMutex,
+        W: Write,
+        C: Clock,
+    {
+        /// Create a new client. `buf` bounds the size of the outgoing packets.
+        ///
+        /// QoS 1 publishes which are not acknowledged within `timeout` milliseconds are
+        /// retransmitted, with the DUP flag set.
+        pub fn new(write: W, buf: &'a mut [u8], clock: C, timeout: u64) -> Self {
+            Self {
+                writer: Mutex::new(Writer { write, buf }),
+                packet_id: BlockingMutex::new(Cell::new(0)),
+                keep_alive: BlockingMutex::new(Cell::new(0)),
+                in_flight: Mutex::new(()),
+                acked: Signal::new(),
+                clock,
+                timeout,
+            }
+        }
+
+        /// Perform the MQTT handshake, returning whether the broker has a session for this client.
+        ///
+        /// `read` is the read half of the connection, which is to be passed to `MqttReceiver` afterwards.
+        pub async fn connect<R>(
+            &self,
+            read: &mut R,
+            options: &ConnectOptions<'_>,
+        ) -> Result<bool, MqttError<W::Error>>
+        where
+            R: Read<Error = W::Error>,
+        {
+            {
+                let mut writer = self.writer.lock().await;
+                let writer = &mut *writer;
+
+                let offset = writer.buf.len();
+                let mut rev = Rev::new(writer.buf, offset);
+
+                let mut flags = 0;
+
+                if let Some(password) = options.password {
+                    rev.string(password)?;
+                    flags |= 0x40;
+                }
+
+                if let Some(username) = options.username {
+                    rev.string(username.as_bytes())?;
+                    flags |= 0x80;
+                }
+
+                if options.clean_session {
+                    flags |= 0x02;
+                }
+
+                rev.string(options.client_id.as_bytes())?
+                    .u16(options.keep_alive)?
+                    .bytes(&[flags])?
+                    .bytes(&[4])?
+                    .string(b"MQTT")?;
+
+                let offset = rev.offset;
+                let end = writer.buf.len();
+
+                writer.write_packet(CONNECT, offset, end).await?;
+            }
+
+            let mut connack = [0; 4];
+            read.read_exact(&mut connack).await?;
+
+            if connack[0] != CONNACK || connack[1] != 2 {
+                return Err(MqttError::ProtocolError);
+            }
+
+            if connack[3] != 0 {
+                return Err(MqttError::ConnectionRefused(connack[3]));
+            }
+
+            self.keep_alive
+                .lock(|keep_alive| keep_alive.set(options.keep_alive));
+
+            Ok(connack[2] & 0x01 != 0)
+        }
+
+        /// Subscribe to `topic_filter`.
+        ///
+        /// Does not wait for the acknowledgement of the broker; a refused subscription is
+        /// reported by `MqttReceiver` instead.
+        pub async fn subscribe(
+            &self,
+            topic_filter: &str,
+            qos: QoS,
+        ) -> Result<(), MqttError<W::Error>> {
+            let packet_id = self.next_packet_id();
+
+            let mut writer = self.writer.lock().await;
+            let writer = &mut *writer;
+
+            let offset = writer.buf.len();
+            let mut rev = Rev::new(writer.buf, offset);
+
+            rev.bytes(&[qos as u8])?
+                .string(topic_filter.as_bytes())?
+                .u16(packet_id)?;
+
+            let offset = rev.offset;
+            let end = writer.buf.len();
+
+            writer.write_packet(SUBSCRIBE, offset, end).await
+        }
+
+        /// Create a sender publishing its messages to `topic`.
+        pub fn sender<'t, D>(&'t self, topic: &'t str, qos: QoS) -> MqttSender<'t, 'a, M, W, C, D>
+        where
+            D: SendData,
+        {
+            MqttSender {
+                client: self,
+                topic,
+                qos,
+                retain: false,
+                _type: PhantomData,
+            }
+        }
+
+        pub async fn ping(&self) -> Result<(), MqttError<W::Error>> {
+            self.writer.lock().await.write_short(PINGREQ, &[]).await
+        }
+
+        pub async fn disconnect(&self) -> Result<(), MqttError<W::Error>> {
+            self.writer.lock().await.write_short(DISCONNECT, &[]).await
+        }
+
+        /// Ping the broker every half of the keep-alive interval negotiated by `connect`.
+        ///
+        /// Needs to be polled for as long as the connection is in use. Returns only if the connection fails.
+        pub async fn run_keep_alive(&self) -> Result<(), MqttError<W::Error>> {
+            let keep_alive = self.keep_alive.lock(|keep_alive| keep_alive.get());
+
+            if keep_alive == 0 {
+                return core::future::pending().await;
+            }
+
+            loop {
+                self.clock
+                    .wait_until(self.clock.now() + keep_alive as u64 * 1000 / 2)
+                    .await;
+
+                self.ping().await?;
+            }
+        }
+
+        async fn publish<D>(
+            &self,
+            topic: &str,
+            qos: QoS,
+            retain: bool,
+            data: &D,
+        ) -> Result<(), MqttError<W::Error>>
+        where
+            D: SendData,
+        {
+            if qos == QoS::AtMostOnce {
+                return self.write_publish(topic, retain, None, data).await;
+            }
+
+            let _in_flight = self.in_flight.lock().await;
+
+            let packet_id = self.next_packet_id();
+            let mut dup = false;
+
+            loop {
+                self.write_publish(topic, retain, Some((packet_id, dup)), data)
+                    .await?;
+
+                let deadline = self.clock.now() + self.timeout;
+
+                let acked = async { while self.acked.wait().await != packet_id {} };
+
+                if let Either::First(_) = select(acked, self.clock.wait_until(deadline)).await {
+                    break Ok(());
+                }
+
+                dup = true;
+            }
+        }
+
+        /// Write a PUBLISH packet; with QoS 1 if `packet_id` - the id and the DUP flag - is provided.
+        async fn write_publish<D>(
+            &self,
+            topic: &str,
+            retain: bool,
+            packet_id: Option<(u16, bool)>,
+            data: &D,
+        ) -> Result<(), MqttError<W::Error>>
+        where
+            D: SendData,
+        {
+            let mut writer = self.writer.lock().await;
+            let writer = &mut *writer;
+
+            let header_len = 2 + topic.len() + if packet_id.is_some() { 2 } else { 0 };
+            let headroom = 1 + MAX_LEN_PREFIX_SIZE + header_len;
+
+            if writer.buf.len() < headroom {
+                return Err(MqttError::OversizedFrame(headroom - writer.buf.len()));
+            }
+
+            let payload_len = codec::encode(data, &mut writer.buf[headroom..])?.len();
+            let end = headroom + payload_len;
+
+            let mut rev = Rev::new(&mut writer.buf[..end], headroom);
+
+            let mut header = PUBLISH | retain as u8;
+
+            if let Some((packet_id, dup)) = packet_id {
+                rev.u16(packet_id)?;
+                header |= (dup as u8) << 3 | (QoS::AtLeastOnce as u8) << 1;
+            }
+
+            rev.string(topic.as_bytes())?;
+
+            let offset = rev.offset;
+
+            writer.write_packet(header, offset, end).await
+        }
+
+        fn next_packet_id(&self) -> u16 {
+            self.packet_id.lock(|packet_id| {
+                let id = packet_id.get().checked_add(1).unwrap_or(1);
+                packet_id.set(id);
+
+                id
+            })
+        }
+    }
+
+    pub struct MqttSender<'t, 'a, M, W, C, D>
+    where
+        M: RawMutex,
+    {
+        client: &'t MqttClient<'a, M, W, C>,
+        topic: &'t str,
+        qos: QoS,
+        retain: bool,
+        _type: PhantomData<fn() -> D>,
+    }
+
+    impl<M, W, C, D> MqttSender<'_, '_, M, W, C, D>
+    where
+        M: RawMutex,
+        W: Write,
+        C: Clock,
+        D: SendData,
+    {
+        /// Set the RETAIN flag of the published messages.
+        pub fn set_retain(&mut self, retain: bool) {
+            self.retain = retain;
+        }
+
+        /// Publish a message. With QoS 1, waits until the broker acknowledges it,
+        /// retransmitting it on every timeout.
+        pub async fn send(&mut self, data: D) -> Result<(), MqttError<W::Error>> {
+            self.client
+                .publish(self.topic, self.qos, self.retain, &data)
+                .await
+        }
+    }
+
+    impl<M, W, C, D> crate::asynch::Sender for MqttSender<'_, '_, M, W, C, D>
+    where
+        M: RawMutex,
+        W: Write,
+        C: Clock,
+        D: SendData,
+    {
+        type Error = MqttError<W::Error>;
+
+        type Data = D;
+
+        async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+            MqttSender::send(self, data).await
+        }
+    }
+
+    /// A received packet, as far as the receiver is concerned.
+    enum Packet {
+        Publish {
+            /// The topic, if it is of interest
+            topic: Option<Range<usize>>,
+            /// The offset of the payload
+            start: usize,
+            /// The packet id to acknowledge, for QoS 1
+            packet_id: Option<[u8; 2]>,
+        },
+        PubAck(u16),
+        SubAck {
+            refused: bool,
+        },
+        PingResp,
+    }
+
+    /// The read half of an MQTT connection, receiving the messages of the subscriptions of the client.
+    pub struct MqttReceiver<'t, 'a, M, W, C, R, D>
+    where
+        M: RawMutex,
+    {
+        client: &'t MqttClient<'a, M, W, C>,
+        read: R,
+        topic_filter: Option<&'t str>,
+        buf: &'t mut [u8],
+        _type: PhantomData<fn() -> D>,
+    }
+
+    impl<'t, 'a, M, W, C, R, D> MqttReceiver<'t, 'a, M, W, C, R, D>
+    where
+        M: RawMutex,
+        W: Write,
+        C: Clock,
+        R: Read<Error = W::Error>,
+    {
+        /// Create a new receiver. `buf` bounds the size of the incoming packets.
+        ///
+        /// If `topic_filter` is provided (with the `+` and `#` wildcards of MQTT), the messages on
+        /// topics not matching it are acknowledged and dropped, so that the receiver only yields
+        /// the messages of that subscription.
+        pub fn new(
+            client: &'t MqttClient<'a, M, W, C>,
+            read: R,
+            topic_filter: Option<&'t str>,
+            buf: &'t mut [u8],
+        ) -> Self {
+            Self {
+                client,
+                read,
+                topic_filter,
+                buf,
+                _type: PhantomData,
+            }
+        }
+
+        /// Receive a message which borrows its `&str` / `&[u8]` fields directly
+        /// from the internal buffer, thus avoiding any allocation or copying.
+        ///
+        /// The returned value keeps `self` borrowed until it is dropped.
+        #[cfg(not(feature = "prost"))]
+        pub async fn recv_borrowed<'b, T>(&'b mut self) -> Result<Option<T>, MqttError<R::Error>>
+        where
+            T: serde::Deserialize<'b>,
+        {
+            match self.recv_frame().await? {
+                Some((_, payload)) => Ok(Some(codec::decode_borrowed(&self.buf[payload])?)),
+                None => Ok(None),
+            }
+        }
+
+        /// Receive the topic and the payload of the next PUBLISH packet of interest, processing
+        /// all other packets on the way.
+        ///
+        /// Returns `None` if the connection was closed on a packet boundary.
+        async fn recv_frame(
+            &mut self,
+        ) -> Result<Option<(Range<usize>, Range<usize>)>, MqttError<R::Error>> {
+            loop {
+                let mut header = [0];
+
+                if self
+                    .read
+                    .read(&mut header)
+                    .await
+                    .map_err(MqttError::IoError)?
+                    == 0
+                {
+                    return Ok(None);
+                }
+
+                let mut decoder = LenDecoder::new();
+
+                let len = loop {
+                    let mut byte = [0];
+                    self.read.read_exact(&mut byte).await?;
+
+                    if let Some(len) = decoder
+                        .push(byte[0])
+                        .map_err(|_| MqttError::ProtocolError)?
+                    {
+                        break len as usize;
+                    }
+                };
+
+                // Oversized packets are skipped, but QoS 1 publishes are still acknowledged,
+                // as long as their variable header fits in the buffer
+                let read_len = len.min(self.buf.len());
+                self.read.read_exact(&mut self.buf[..read_len]).await?;
+
+                // Parsing errors are only reported after skipping the rest of the packet,
+                // so that the connection stays on a packet boundary
+                let packet = self.parse(header[0], &self.buf[..read_len], len);
+
+                codec::skip(&mut self.read, len - read_len).await?;
+
+                match packet? {
+                    Packet::Publish {
+                        topic,
+                        start,
+                        packet_id,
+                    } => {
+                        if let Some(packet_id) = packet_id {
+                            self.client
+                                .writer
+                                .lock()
+                                .await
+                                .write_short(PUBACK, &packet_id)
+                                .await?;
+                        }
+
+                        if let Some(topic) = topic {
+                            if len > read_len {
+                                return Err(MqttError::OversizedFrame(len - read_len));
+                            }
+
+                            return Ok(Some((topic, start..len)));
+                        }
+                    }
+                    Packet::PubAck(packet_id) => self.client.acked.signal(packet_id),
+                    Packet::SubAck { refused } => {
+                        if refused {
+                            return Err(MqttError::SubscriptionRefused);
+                        }
+                    }
+                    Packet::PingResp => (),
+                }
+            }
+        }
+
+        /// Parse a packet of `len` bytes, of which `packet` - the beginning - is in the buffer.
+        fn parse(
+            &self,
+            header: u8,
+            packet: &[u8],
+            len: usize,
+        ) -> Result<Packet, MqttError<R::Error>> {
+            match header & 0xf0 {
+                PUBLISH => {
+                    let qos = (header >> 1) & 0x03;
+
+                    if qos > 1 || len < 2 {
+                        return Err(MqttError::ProtocolError);
+                    }
+
+                    if packet.len() < 2 {
+                        return Err(MqttError::OversizedFrame(len - packet.len()));
+                    }
+
+                    let topic_len = u16::from_be_bytes([packet[0], packet[1]]) as usize;
+                    let topic = 2..2 + topic_len;
+                    let start = topic.end + if qos == 1 { 2 } else { 0 };
+
+                    if start > len {
+                        return Err(MqttError::ProtocolError);
+                    }
+
+                    if start > packet.len() {
+                        return Err(MqttError::OversizedFrame(len - packet.len()));
+                    }
+
+                    let name = core::str::from_utf8(&packet[topic.clone()])
+                        .map_err(|_| MqttError::ProtocolError)?;
+
+                    let matches = self
+                        .topic_filter
+                        .map_or(true, |topic_filter| topic_matches(topic_filter, name));
+
+                    Ok(Packet::Publish {
+                        topic: matches.then_some(topic),
+                        start,
+                        packet_id: (qos == 1).then(|| [packet[start - 2], packet[start - 1]]),
+                    })
+                }
+                PUBACK if len == 2 && packet.len() == 2 => {
+                    Ok(Packet::PubAck(u16::from_be_bytes([packet[0], packet[1]])))
+                }
+                SUBACK if len >= 3 && packet.len() == len => Ok(Packet::SubAck {
+                    refused: packet[2..].iter().any(|code| *code & 0x80 != 0),
+                }),
+                PINGRESP if len == 0 => Ok(Packet::PingResp),
+                _ => Err(MqttError::ProtocolError),
+            }
+        }
+    }
+
+    impl<M, W, C, R, D> MqttReceiver<'_, '_, M, W, C, R, D>
+    where
+        M: RawMutex,
+        W: Write,
+        C: Clock,
+        R: Read<Error = W::Error>,
+        D: ReceiveData,
+    {
+        pub async fn recv(&mut self) -> Result<Option<D>, MqttError<R::Error>> {
+            match self.recv_frame().await? {
+                Some((_, payload)) => Ok(Some(codec::decode(&self.buf[payload])?)),
+                None => Ok(None),
+            }
+        }
+
+        /// Receive a message along with the topic it was published to.
+        pub async fn recv_with_topic(&mut self) -> Result<Option<(&str, D)>, MqttError<R::Error>> {
+            match self.recv_frame().await? {
+                Some((topic, payload)) => {
+                    let data = codec::decode(&self.buf[payload])?;
+
+                    // Already validated when parsing the packet
+                    let topic = core::str::from_utf8(&self.buf[topic])
+                        .map_err(|_| MqttError::ProtocolError)?;
+
+                    Ok(Some((topic, data)))
+                }
+                None => Ok(None),
+            }
+        }
+    }
+
+    impl<M, W, C, R, D> crate::asynch::Receiver for MqttReceiver<'_, '_, M, W, C, R, D>
+    where
+        M: RawMutex,
+        W: Write,
+        C: Clock,
+        R: Read<Error = W::Error>,
+        D: ReceiveData,
+    {
+        type Error = MqttError<R::Error>;
+
+        type Data = Option<D>;
+
+        async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+            MqttReceiver::recv(self).await
+        }
+    }
+
+    #[cfg(all(test, feature = "std", not(feature = "prost")))]
+    mod tests {
+        use core::cell::{Cell, RefCell};
+        use core::convert::Infallible;
+        use core::future::poll_fn;
+        use core::task::Poll;
+
+        use std::vec::Vec;
+
+        use embassy_futures::join::join;
+        use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+        use embassy_sync::pipe::Pipe;
+
+        use crate::adapt::block_on;
+
+        use super::*;
+
+        const TIMEOUT: u64 = 1000;
+
+        type TestPipe = Pipe<NoopRawMutex, 1024>;
+
+        type TestClient<'a> = MqttClient<'a, NoopRawMutex, &'a TestPipe, &'a TestClock>;
+
+        /// A clock which only advances when told to.
+        struct TestClock(Cell<u64>);
+
+        impl Clock for TestClock {
+            fn now(&self) -> u64 {
+                self.0.get()
+            }
+
+            async fn wait_until(&self, instant: u64) {
+                poll_fn(|cx| {
+                    if self.0.get() >= instant {
+                        Poll::Ready(())
+                    } else {
+                        cx.waker().wake_by_ref();
+                        Poll::Pending
+                    }
+                })
+                .await
+            }
+        }
+
+        async fn read_packet(mut read: &TestPipe) -> (u8, Vec<u8>) {
+            let mut header = [0];
+            read.read_exact(&mut header).await.unwrap();
+
+            let mut decoder = LenDecoder::new();
+
+            let len = loop {
+                let mut byte = [0];
+                read.read_exact(&mut byte).await.unwrap();
+
+                if let Some(len) = decoder.push(byte[0]).unwrap() {
+                    break len as usize;
+                }
+            };
+
+            let mut packet = vec![0; len];
+            read.read_exact(&mut packet).await.unwrap();
+
+            (header[0], packet)
+        }
+
+        async fn write_packet(mut write: &TestPipe, header: u8, packet: &[u8]) {
+            let mut len_buf = [0; MAX_LEN_PREFIX_SIZE];
+
+            Write::write_all(&mut write, &[header]).await.unwrap();
+            Write::write_all(
+                &mut write,
+                codec::encode_len(packet.len() as _, &mut len_buf),
+            )
+            .await
+            .unwrap();
+            Write::write_all(&mut write, packet).await.unwrap();
+        }
+
+        fn publish_packet(topic: &str, packet_id: Option<u16>, data: u32) -> Vec<u8> {
+            let mut packet = Vec::new();
+
+            packet.extend_from_slice(&(topic.len() as u16).to_be_bytes());
+            packet.extend_from_slice(topic.as_bytes());
+
+            if let Some(packet_id) = packet_id {
+                packet.extend_from_slice(&packet_id.to_be_bytes());
+            }
+
+            let mut buf = [0; 8];
+            packet.extend_from_slice(codec::encode(&data, &mut buf).unwrap());
+
+            packet
+        }
+
+        /// A PUBLISH received by the stand-in broker.
+        #[derive(Debug, PartialEq, Eq)]
+        struct Published {
+            topic: String,
+            dup: bool,
+            packet_id: Option<u16>,
+            data: u32,
+        }
+
+        /// A minimal stand-in broker, which routes the publishes of the client back to it
+        /// according to its subscriptions.
+        ///
+        /// Returns once the client disconnects.
+        struct Broker<'a> {
+            read: &'a TestPipe,
+            write: &'a TestPipe,
+            clock: &'a TestClock,
+            /// The number of PUBACKs not to send, as if they were lost
+            lost_acks: Cell<usize>,
+            published: RefCell<Vec<Published>>,
+            acked: RefCell<Vec<u16>>,
+        }
+
+        impl<'a> Broker<'a> {
+            fn new(read: &'a TestPipe, write: &'a TestPipe, clock: &'a TestClock) -> Self {
+                Self {
+                    read,
+                    write,
+                    clock,
+                    lost_acks: Cell::new(0),
+                    published: RefCell::new(Vec::new()),
+                    acked: RefCell::new(Vec::new()),
+                }
+            }
+
+            async fn run(&self) {
+                let mut subscriptions = Vec::<String>::new();
+                let mut next_packet_id = 100;
+
+                loop {
+                    let (header, packet) = read_packet(self.read).await;
+
+                    match header {
+                        CONNECT => write_packet(self.write, CONNACK, &[0, 0]).await,
+                        SUBSCRIBE => {
+                            let topic_len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+                            let topic_filter = core::str::from_utf8(&packet[4..4 + topic_len]);
+
+                            subscriptions.push(topic_filter.unwrap().into());
+
+                            write_packet(self.write, SUBACK, &[packet[0], packet[1], 1]).await;
+                        }
+                        header if header & 0xf0 == PUBLISH => {
+                            let qos = (header >> 1) & 0x03;
+
+                            let topic_len = u16::from_be_bytes([packet[0], packet[1]]) as usize;
+                            let topic = core::str::from_utf8(&packet[2..2 + topic_len]).unwrap();
+
+                            let mut start = 2 + topic_len;
+
+                            let packet_id = (qos == 1).then(|| {
+                                start += 2;
+                                u16::from_be_bytes([packet[start - 2], packet[start - 1]])
+                            });
+
+                            let data = codec::decode::<u32>(&packet[start..]).unwrap();
+
+                            self.published.borrow_mut().push(Published {
+                                topic: topic.into(),
+                                dup: header & 0x08 != 0,
+                                packet_id,
+                                data,
+                            });
+
+                            if subscriptions
+                                .iter()
+                                .any(|topic_filter| topic_matches(topic_filter, topic))
+                            {
+                                let (header, packet_id) = if qos == 1 {
+                                    next_packet_id += 1;
+                                    (PUBLISH | 0x02, Some(next_packet_id))
+                                } else {
+                                    (PUBLISH, None)
+                                };
+
+                                write_packet(
+                                    self.write,
+                                    header,
+                                    &publish_packet(topic, packet_id, data),
+                                )
+                                .await;
+                            }
+
+                            if let Some(packet_id) = packet_id {
+                                if self.lost_acks.get() > 0 {
+                                    self.lost_acks.set(self.lost_acks.get() - 1);
+                                    self.clock.0.set(self.clock.0.get() + TIMEOUT);
+                                } else {
+                                    write_packet(self.write, PUBACK, &packet_id.to_be_bytes())
+                                        .await;
+                                }
+                            }
+                        }
+                        PUBACK => self
+                            .acked
+                            .borrow_mut()
+                            .push(u16::from_be_bytes([packet[0], packet[1]])),
+                        PINGREQ => write_packet(self.write, PINGRESP, &[]).await,
+                        DISCONNECT => break,
+                        _ => panic!("Unexpected packet {header:02x}"),
+                    }
+                }
+            }
+        }
+
+        /// Connect, subscribe to `topic_filters` and run `client_ops`, while receiving with a
+        /// receiver filtering on `topic_filter`, until `client_ops` completes.
+        fn session<F>(
+            broker: &Broker<'_>,
+            client: &TestClient<'_>,
+            read: &TestPipe,
+            topic_filters: &[&str],
+            topic_filter: Option<&str>,
+            client_ops: F,
+        ) -> Vec<Result<(String, u32), MqttError<Infallible>>>
+        where
+            F: core::future::Future<Output = ()>,
+        {
+            let received = RefCell::new(Vec::new());
+
+            let mut buf = [0; 64];
+            let mut receiver =
+                MqttReceiver::<_, _, _, _, u32>::new(client, read, topic_filter, &mut buf);
+
+            block_on(join(broker.run(), async {
+                let mut read = read;
+
+                client
+                    .connect(&mut read, &ConnectOptions::new("test"))
+                    .await
+                    .unwrap();
+
+                for topic_filter in topic_filters {
+                    client
+                        .subscribe(topic_filter, QoS::AtLeastOnce)
+                        .await
+                        .unwrap();
+                }
+
+                select(client_ops, async {
+                    loop {
+                        let result = receiver.recv_with_topic().await;
+
+                        received.borrow_mut().push(result.map(|message| {
+                            message.map(|(topic, data)| (topic.into(), data)).unwrap()
+                        }));
+                    }
+                })
+                .await;
+
+                client.disconnect().await.unwrap();
+            }));
+
+            received.into_inner()
+        }
+
+        #[test]
+        fn pubsub() {
+            let to_broker = TestPipe::new();
+            let to_client = TestPipe::new();
+            let clock = TestClock(Cell::new(0));
+
+            let broker = Broker::new(&to_broker, &to_client, &clock);
+
+            let mut buf = [0; 64];
+            let client = TestClient::new(&to_broker, &mut buf, &clock, TIMEOUT);
+
+            let received = session(&broker, &client, &to_client, &["a/+", "b/#"], None, async {
+                client
+                    .sender("a/1", QoS::AtLeastOnce)
+                    .send(1_u32)
+                    .await
+                    .unwrap();
+                client
+                    .sender("b", QoS::AtMostOnce)
+                    .send(2_u32)
+                    .await
+                    .unwrap();
+                client
+                    .sender("a/1/x", QoS::AtMostOnce)
+                    .send(3_u32)
+                    .await
+                    .unwrap();
+                client
+                    .sender("b/1/x", QoS::AtLeastOnce)
+                    .send(4_u32)
+                    .await
+                    .unwrap();
+                // Completes only after the receiver processed all of the above
+                client
+                    .sender("c", QoS::AtLeastOnce)
+                    .send(5_u32)
+                    .await
+                    .unwrap();
+            });
+
+            assert_eq!(
+                received.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+                [("a/1".into(), 1), ("b".into(), 2), ("b/1/x".into(), 4)]
+            );
+
+            // All QoS 1 publishes of the broker were acknowledged
+            assert_eq!(*broker.acked.borrow(), [101, 102]);
+        }
+
+        #[test]
+        fn topic_filter() {
+            let to_broker = TestPipe::new();
+            let to_client = TestPipe::new();
+            let clock = TestClock(Cell::new(0));
+
+            let broker = Broker::new(&to_broker, &to_client, &clock);
+
+            let mut buf = [0; 64];
+            let client = TestClient::new(&to_broker, &mut buf, &clock, TIMEOUT);
+
+            let received = session(
+                &broker,
+                &client,
+                &to_client,
+                &["a/+", "b"],
+                Some("b"),
+                async {
+                    client
+                        .sender("a/1", QoS::AtLeastOnce)
+                        .send(1_u32)
+                        .await
+                        .unwrap();
+                    client
+                        .sender("b", QoS::AtMostOnce)
+                        .send(2_u32)
+                        .await
+                        .unwrap();
+                    client
+                        .sender("c", QoS::AtLeastOnce)
+                        .send(3_u32)
+                        .await
+                        .unwrap();
+                },
+            );
+
+            assert_eq!(
+                received.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+                [("b".into(), 2)]
+            );
+
+            // Messages on other topics are acknowledged nonetheless
+            assert_eq!(*broker.acked.borrow(), [101]);
+        }
+
+        #[test]
+        fn retransmit() {
+            let to_broker = TestPipe::new();
+            let to_client = TestPipe::new();
+            let clock = TestClock(Cell::new(0));
+
+            let broker = Broker::new(&to_broker, &to_client, &clock);
+            broker.lost_acks.set(2);
+
+            let mut buf = [0; 64];
+            let client = TestClient::new(&to_broker, &mut buf, &clock, TIMEOUT);
+
+            session(&broker, &client, &to_client, &[], None, async {
+                client
+                    .sender("a", QoS::AtLeastOnce)
+                    .send(1_u32)
+                    .await
+                    .unwrap();
+                client
+                    .sender("a", QoS::AtLeastOnce)
+                    .send(2_u32)
+                    .await
+                    .unwrap();
+            });
+
+            let published = |dup, packet_id, data| Published {
+                topic: "a".into(),
+                dup,
+                packet_id: Some(packet_id),
+                data,
+            };
+
+            assert_eq!(
+                *broker.published.borrow(),
+                [
+                    published(false, 1, 1),
+                    published(true, 1, 1),
+                    published(true, 1, 1),
+                    published(false, 2, 2),
+                ]
+            );
+        }
+
+        #[test]
+        fn resync() {
+            let to_broker = TestPipe::new();
+            let to_client = TestPipe::new();
+            let clock = TestClock(Cell::new(0));
+
+            let mut buf = [0; 64];
+            let client = TestClient::new(&to_broker, &mut buf, &clock, TIMEOUT);
+
+            let mut buf = [0; 16];
+            let mut receiver =
+                MqttReceiver::<_, _, _, _, u32>::new(&client, &to_client, None, &mut buf);
+
+            block_on(async {
+                // QoS 3
+                write_packet(&to_client, PUBLISH | 0x06, &publish_packet("a", None, 1)).await;
+                // Topic longer than the packet
+                write_packet(&to_client, PUBLISH, &[0, 10, b'a']).await;
+                // Truncated topic length
+                write_packet(&to_client, PUBLISH, &[0]).await;
+                // Invalid UTF-8 topic
+                write_packet(&to_client, PUBLISH, &[0, 1, 0xff, 3]).await;
+                // Oversized payload, with QoS 1
+                let mut packet = publish_packet("a", Some(7), 4);
+                packet.extend_from_slice(&[0; 40]);
+                write_packet(&to_client, PUBLISH | 0x02, &packet).await;
+                // Unexpected packet type
+                write_packet(&to_client, 0x50, &[0, 1]).await;
+
+                write_packet(&to_client, PUBLISH, &publish_packet("a", None, 5)).await;
+
+                for _ in 0..4 {
+                    assert!(matches!(
+                        receiver.recv().await,
+                        Err(MqttError::ProtocolError)
+                    ));
+                }
+
+                assert!(matches!(
+                    receiver.recv().await,
+                    Err(MqttError::OversizedFrame(_))
+                ));
+                assert!(matches!(
+                    receiver.recv().await,
+                    Err(MqttError::ProtocolError)
+                ));
+                assert_eq!(receiver.recv().await.unwrap(), Some(5));
+
+                // The oversized QoS 1 publish was acknowledged
+                assert_eq!(read_packet(&to_broker).await, (PUBACK, vec![0, 7]));
+            });
+        }
+
+        #[test]
+        fn topic_filters() {
+            assert!(topic_matches("a/b", "a/b"));
+            assert!(!topic_matches("a/b", "a/c"));
+            assert!(!topic_matches("a/b", "a/b/c"));
+            assert!(topic_matches("a/+", "a/b"));
+            assert!(topic_matches("a/+", "a/"));
+            assert!(!topic_matches("a/+", "a"));
+            assert!(!topic_matches("a/+", "a/b/c"));
+            assert!(topic_matches("+/+/c", "a/b/c"));
+            assert!(topic_matches("a/#", "a"));
+            assert!(topic_matches("a/#", "a/b/c"));
+            assert!(!topic_matches("a/#", "b/c"));
+            assert!(topic_matches("#", "a/b"));
+            assert!(!topic_matches("#", "$SYS/a"));
+            assert!(!topic_matches("+/a", "$SYS/a"));
+            assert!(topic_matches("$SYS/#", "$SYS/a"));
+        }
+    }
+}
+
+#[cfg(feature = "embedded-svc")]
+pub mod embedded_svc_impl {
+    use core::marker::PhantomData;
+
+    use embedded_svc::mqtt::client::asynch::{
+        Connection, Details, Event, EventPayload, Publish, QoS,
+    };
+    use embedded_svc::mqtt::client::{InitialChunkData, SubsequentChunkData};
+
+    use log::warn;
+
+    use crate::codec::{self, ReceiveData, SendData};
+
+    use super::*;
+
+    pub struct MqttSvcSender<'a, P, D> {
+        publish: P,
+        topic: &'a str,
+        qos: QoS,
+        retain: bool,
+        buf: &'a mut [u8],
+        _type: PhantomData<fn() -> D>,
+    }
+
+    impl<'a, P, D> MqttSvcSender<'a, P, D>
+    where
+        P: Publish,
+        D: SendData,
+    {
+        pub fn new(publish: P, topic: &'a str, qos: QoS, retain: bool, buf: &'a mut [u8]) -> Self {
+            Self {
+                publish,
+                topic,
+                qos,
+                retain,
+                buf,
+                _type: PhantomData,
+            }
+        }
+
+        pub async fn send(&mut self, data: &D) -> Result<(), MqttError<P::Error>> {
+            let frame_data = codec::encode(data, self.buf)?;
+
+            self.publish
+                .publish(self.topic, self.qos, self.retain, frame_data)
+                .await
+                .map_err(MqttError::IoError)?;
+
+            Ok(())
+        }
+    }
+
+    impl<P, D> crate::asynch::Sender for MqttSvcSender<'_, P, D>
+    where
+        P: Publish,
+        D: SendData,
+    {
+        type Error = MqttError<P::Error>;
+
+        type Data = D;
+
+        async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+            MqttSvcSender::send(self, &data).await
+        }
+    }
+
+    pub struct MqttSvcReceiver<'a, C, D> {
+        connection: C,
+        topic: Option<&'a str>,
+        buf: &'a mut [u8],
+        chunked: bool,
+        _type: PhantomData<fn() -> D>,
+    }
+
+    impl<'a, C, D> MqttSvcReceiver<'a, C, D>
+    where
+        C: Connection,
+    {
+        /// Create a new receiver.
+        ///
+        /// If `topic` is provided, only the messages on topics matching this topic filter
+        /// (with `+` and `#` wildcards) are received.
+        pub fn new(connection: C, topic: Option<&'a str>, buf: &'a mut [u8]) -> Self {
+            Self {
+                connection,
+                topic,
+                buf,
+                chunked: false,
+                _type: PhantomData,
+            }
+        }
+
+        /// Receive a message which borrows its `&str` / `&[u8]` fields directly
+        /// from the internal buffer, thus avoiding any allocation or copying.
+        ///
+        /// The returned value keeps `self` borrowed until it is dropped.
+        #[cfg(not(feature = "prost"))]
+        pub async fn recv_borrowed<'b, T>(&'b mut self) -> Result<Option<T>, MqttError<C::Error>>
+        where
+            T: serde::Deserialize<'b>,
+        {
+            match self.recv_frame().await? {
+                Some(len) => Ok(Some(codec::decode_borrowed(&self.buf[..len])?)),
+                None => Ok(None),
+            }
+        }
+
+        /// Receive the next message into the buffer, reassembling it if the client delivers it in chunks.
+        ///
+        /// A disconnection is reported as `MqttError::Disconnected`; receiving can then
+        /// continue with the messages delivered once the client has reconnected.
+        async fn recv_frame(&mut self) -> Result<Option<usize>, MqttError<C::Error>> {
+            loop {
+                let event = self.connection.next().await.map_err(MqttError::IoError)?;
+
+                match event.payload() {
+                    EventPayload::Received {
+                        topic,
+                        data,
+                        details,
+                        ..
+                    } => match details {
+                        Details::Complete => {
+                            if !matches(self.topic, topic) {
+                                continue;
+                            }
+
+                            if data.len() > self.buf.len() {
+                                return Err(MqttError::OversizedFrame(data.len() - self.buf.len()));
+                            }
+
+                            self.buf[..data.len()].copy_from_slice(data);
+
+                            return Ok(Some(data.len()));
+                        }
+                        Details::InitialChunk(InitialChunkData { total_data_size }) => {
+                            self.chunked = matches(self.topic, topic);
+
+                            if self.chunked && total_data_size <= self.buf.len() {
+                                self.buf[..data.len()].copy_from_slice(data);
+                            }
+                        }
+                        Details::SubsequentChunk(SubsequentChunkData {
+                            current_data_offset,
+                            total_data_size,
+                        }) => {
+                            if !self.chunked {
+                                continue;
+                            }
+
+                            let end = current_data_offset + data.len();
+
+                            if total_data_size <= self.buf.len() {
+                                self.buf[current_data_offset..end].copy_from_slice(data);
+                            }
+
+                            if end == total_data_size {
+                                self.chunked = false;
+
+                                if total_data_size > self.buf.len() {
+                                    return Err(MqttError::OversizedFrame(
+                                        total_data_size - self.buf.len(),
+                                    ));
+                                }
+
+                                return Ok(Some(total_data_size));
+                            }
+                        }
+                    },
+                    EventPayload::Disconnected => {
+                        // The rest of a chunked message is not going to arrive
+                        self.chunked = false;
+
+                        return Err(MqttError::Disconnected);
+                    }
+                    EventPayload::Error(e) => {
+                        warn!("MQTT connection error: {e:?}");
+
+                        return Err(MqttError::ConnectionError);
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    impl<C, D> MqttSvcReceiver<'_, C, D>
+    where
+        C: Connection,
+        D: ReceiveData,
+    {
+        pub async fn recv(&mut self) -> Result<Option<D>, MqttError<C::Error>> {
+            match self.recv_frame().await? {
+                Some(len) => Ok(Some(codec::decode(&self.buf[..len])?)),
+                None => Ok(None),
+            }
+        }
+    }
+
+    impl<C, D> crate::asynch::Receiver for MqttSvcReceiver<'_, C, D>
+    where
+        C: Connection,
+        D: ReceiveData,
+    {
+        type Error = MqttError<C::Error>;
+
+        type Data = Option<D>;
+
+        async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+            MqttSvcReceiver::recv(self).await
+        }
+    }
+
+    /// Whether a message on `topic` is of interest, with `filter` being a topic filter.
+    fn matches(filter: Option<&str>, topic: Option<&str>) -> bool {
+        match (filter, topic) {
+            (Some(filter), Some(topic)) => topic_matches(filter, topic),
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    mod tests {
+        use std::collections::VecDeque;
+        use std::vec::Vec;
+
+        use embedded_svc::mqtt::client::asynch::ErrorType;
+
+        use crate::adapt::block_on;
+
+        use super::*;
+
+        enum MockEvent {
+            Received {
+                topic: Option<&'static str>,
+                data: Vec<u8>,
+                details: Details,
+            },
+            Disconnected,
+        }
+
+        impl ErrorType for MockEvent {
+            type Error = &'static str;
+        }
+
+        impl Event for MockEvent {
+            fn payload(&self) -> EventPayload<'_, Self::Error> {
+                match self {
+                    Self::Received {
+                        topic,
+                        data,
+                        details,
+                    } => EventPayload::Received {
+                        id: 0,
+                        topic: *topic,
+                        data,
+                        details: *details,
+                    },
+                    Self::Disconnected => EventPayload::Disconnected,
+                }
+            }
+        }
+
+        /// Replays its events, then fails once they are exhausted
+        struct MockConnection {
+            events: VecDeque<MockEvent>,
+            current: Option<MockEvent>,
+        }
+
+        impl ErrorType for MockConnection {
+            type Error = &'static str;
+        }
+
+        impl Connection for MockConnection {
+            type Event<'a> = &'a MockEvent;
+
+            async fn next(&mut self) -> Result<Self::Event<'_>, Self::Error> {
+                self.current = self.events.pop_front();
+                self.current.as_ref().ok_or("closed")
+            }
+        }
+
+        fn message(topic: Option<&'static str>, value: u32) -> MockEvent {
+            let mut buf = [0; 16];
+
+            MockEvent::Received {
+                topic,
+                data: codec::encode(&value, &mut buf).unwrap().to_vec(),
+                details: Details::Complete,
+            }
+        }
+
+        fn chunks(topic: &'static str, value: u32) -> [MockEvent; 2] {
+            let mut buf = [0; 16];
+            let data = codec::encode(&value, &mut buf).unwrap();
+
+            [
+                MockEvent::Received {
+                    topic: Some(topic),
+                    data: data[..1].to_vec(),
+                    details: Details::InitialChunk(InitialChunkData {
+                        total_data_size: data.len(),
+                    }),
+                },
+                MockEvent::Received {
+                    topic: None,
+                    data: data[1..].to_vec(),
+                    details: Details::SubsequentChunk(SubsequentChunkData {
+                        current_data_offset: 1,
+                        total_data_size: data.len(),
+                    }),
+                },
+            ]
+        }
+
+        fn receiver<'a>(
+            events: impl IntoIterator<Item = MockEvent>,
+            topic: Option<&'a str>,
+            buf: &'a mut [u8],
+        ) -> MqttSvcReceiver<'a, MockConnection, u32> {
+            let connection = MockConnection {
+                events: events.into_iter().collect(),
+                current: None,
+            };
+
+            MqttSvcReceiver::new(connection, topic, buf)
+        }
+
+        #[test]
+        fn filter() {
+            let mut events = vec![
+                message(Some("sensors/kitchen/humidity"), 1),
+                message(Some("sensors/kitchen/temp"), 2),
+                message(None, 3),
+                message(Some("sensors/temp"), 4),
+            ];
+            events.extend(chunks("sensors/garage/humidity", 5));
+            events.extend(chunks("sensors/garage/temp", u32::MAX));
+            events.push(message(Some("sensors/attic/temp"), 6));
+
+            let mut buf = [0; 16];
+            let mut receiver = receiver(events, Some("sensors/+/temp"), &mut buf);
+
+            block_on(async {
+                assert_eq!(receiver.recv().await.unwrap(), Some(2));
+                assert_eq!(receiver.recv().await.unwrap(), Some(u32::MAX));
+                assert_eq!(receiver.recv().await.unwrap(), Some(6));
+                assert!(matches!(
+                    receiver.recv().await,
+                    Err(MqttError::IoError("closed"))
+                ));
+            });
+        }
+
+        #[test]
+        fn no_filter() {
+            let events = [
+                message(Some("a"), 1),
+                message(None, 2),
+                message(Some("b/c"), 3),
+            ];
+
+            let mut buf = [0; 16];
+            let mut receiver = receiver(events, None, &mut buf);
+
+            block_on(async {
+                for value in 1..=3 {
+                    assert_eq!(receiver.recv().await.unwrap(), Some(value));
+                }
+            });
+        }
+
+        #[test]
+        fn disconnected() {
+            let [first, _] = chunks("sensors/kitchen/temp", u32::MAX);
+            let [_, rest] = chunks("sensors/garage/temp", u32::MAX);
+
+            let events = [
+                message(Some("sensors/kitchen/temp"), 1),
+                first,
+                MockEvent::Disconnected,
+                // The rest of the interrupted message is ignored after the reconnection
+                rest,
+                message(Some("sensors/kitchen/temp"), 2),
+            ];
+
+            let mut buf = [0; 16];
+            let mut receiver = receiver(events, Some("sensors/#"), &mut buf);
+
+            block_on(async {
+                assert_eq!(receiver.recv().await.unwrap(), Some(1));
+                assert!(matches!(
+                    receiver.recv().await,
+                    Err(MqttError::Disconnected)
+                ));
+                assert_eq!(receiver.recv().await.unwrap(), Some(2));
+            });
+        }
+    }
+}
@@ -1,6 +1,10 @@
 use core::convert::Infallible;
 
-use crate::notification::Notification;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+
+use crate::notification::{
+    AtomicSignal, AtomicValue, CountingNotification, EventGroup, MultiNotification, Notification,
+};
 
 use super::{Receiver, Sender};
 
@@ -26,3 +30,105 @@ impl Receiver for &Notification {
         Ok(())
     }
 }
+
+impl<M, const N: usize> Sender for &MultiNotification<M, N>
+where
+    M: RawMutex,
+{
+    type Error = Infallible;
+
+    type Data = ();
+
+    async fn send(&mut self, _data: Self::Data) -> Result<Self::Data, Self::Error> {
+        MultiNotification::notify(self);
+
+        Ok(())
+    }
+}
+
+impl<M, const N: usize> Receiver for &MultiNotification<M, N>
+where
+    M: RawMutex,
+{
+    type Error = Infallible;
+
+    type Data = ();
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        MultiNotification::wait(self).await;
+        Ok(())
+    }
+}
+
+impl Sender for &CountingNotification {
+    type Error = Infallible;
+
+    type Data = ();
+
+    async fn send(&mut self, _data: Self::Data) -> Result<Self::Data, Self::Error> {
+        CountingNotification::notify(self);
+
+        Ok(())
+    }
+}
+
+impl Receiver for &CountingNotification {
+    type Error = Infallible;
+
+    type Data = u32;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        Ok(CountingNotification::wait(self).await)
+    }
+}
+
+impl<T> Sender for &AtomicSignal<T>
+where
+    T: AtomicValue,
+{
+    type Error = Infallible;
+
+    type Data = T;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        AtomicSignal::signal(self, data);
+
+        Ok(())
+    }
+}
+
+impl<T> Receiver for &AtomicSignal<T>
+where
+    T: AtomicValue,
+{
+    type Error = Infallible;
+
+    type Data = T;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        Ok(AtomicSignal::wait(self).await)
+    }
+}
+
+impl Sender for &EventGroup {
+    type Error = Infallible;
+
+    type Data = u32;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        EventGroup::set(self, data);
+
+        Ok(())
+    }
+}
+
+/// Receives all set bits as soon as any bit is set, and clears them.
+impl Receiver for &EventGroup {
+    type Error = Infallible;
+
+    type Data = u32;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        Ok(EventGroup::wait_any(self, u32::MAX, true).await)
+    }
+}
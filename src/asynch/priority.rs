@@ -0,0 +1,289 @@
+//! Multiplexing of several logical channels of different priority onto one `Sender`
+//! (i.e. so that control messages overtake bulk data on the same websocket).
+//!
+//! Each logical channel is a priority class with its own bounded queue. `PriorityMux::run`
+//! drains the queues into the underlying sender, either in strict priority order, or in
+//! weighted fair order, where each class gets a share of the messages proportional to its
+//! weight.
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+
+use super::Sender;
+
+/// The order in which `PriorityMux` drains the queues of its classes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule<const P: usize> {
+    /// Class 0 first, then class 1 and so on; a class is served only if all classes
+    /// before it are empty
+    Strict,
+    /// Each class gets a share of the messages proportional to its weight, interleaved as
+    /// smoothly as possible; classes with a weight of zero are served only if all weighted
+    /// classes are empty
+    Weighted([u32; P]),
+}
+
+/// `P` priority classes, each with a queue of up to `Q` messages.
+pub struct PriorityMux<M, D, const P: usize, const Q: usize>
+where
+    M: RawMutex,
+{
+    queues: [Channel<M, D, Q>; P],
+    schedule: Schedule<P>,
+    ready: Signal<M, ()>,
+}
+
+impl<M, D, const P: usize, const Q: usize> PriorityMux<M, D, P, Q>
+where
+    M: RawMutex,
+{
+    // Only used to initialize `queues`, as `Channel` is not `Copy`
+    #[allow(clippy::declare_interior_mutable_const)]
+    const QUEUE: Channel<M, D, Q> = Channel::new();
+
+    pub const fn new(schedule: Schedule<P>) -> Self {
+        Self {
+            queues: [Self::QUEUE; P],
+            schedule,
+            ready: Signal::new(),
+        }
+    }
+
+    /// The sender of priority class `class`, which waits while the queue of the class is full.
+    ///
+    /// # Panics
+    ///
+    /// If `class` is not less than `P`.
+    pub fn sender(&self, class: usize) -> PrioritySender<'_, M, D, P, Q> {
+        assert!(class < P);
+
+        PrioritySender { mux: self, class }
+    }
+
+    /// Drain the queues into `sender`.
+    ///
+    /// Needs to be polled for as long as the class senders are in use.
+    /// Returns only if `sender` fails.
+    pub async fn run<S>(&self, mut sender: S) -> Result<(), S::Error>
+    where
+        S: Sender<Data = D>,
+    {
+        let mut current = [0_i64; P];
+
+        loop {
+            match self.next(&mut current) {
+                Some(data) => sender.send(data).await?,
+                None => self.ready.wait().await,
+            }
+        }
+    }
+
+    fn next(&self, current: &mut [i64; P]) -> Option<D> {
+        let class = match &self.schedule {
+            Schedule::Strict => None,
+            Schedule::Weighted(weights) => {
+                // Smooth weighted round-robin over the non-empty weighted classes
+                let mut total = 0;
+                let mut next: Option<usize> = None;
+
+                for (class, weight) in weights.iter().enumerate() {
+                    if *weight > 0 && !self.queues[class].is_empty() {
+                        current[class] += *weight as i64;
+                        total += *weight as i64;
+
+                        if next.map_or(true, |next| current[class] > current[next]) {
+                            next = Some(class);
+                        }
+                    }
+                }
+
+                if let Some(next) = next {
+                    current[next] -= total;
+                }
+
+                next
+            }
+        };
+
+        match class {
+            Some(class) => self.queues[class].try_receive().ok(),
+            None => self
+                .queues
+                .iter()
+                .find_map(|queue| queue.try_receive().ok()),
+        }
+    }
+}
+
+pub struct PrioritySender<'a, M, D, const P: usize, const Q: usize>
+where
+    M: RawMutex,
+{
+    mux: &'a PriorityMux<M, D, P, Q>,
+    class: usize,
+}
+
+impl<M, D, const P: usize, const Q: usize> PrioritySender<'_, M, D, P, Q>
+where
+    M: RawMutex,
+{
+    /// The priority class of this sender.
+    pub fn class(&self) -> usize {
+        self.class
+    }
+
+    pub async fn send(&mut self, data: D) {
+        self.mux.queues[self.class].send(data).await;
+        self.mux.ready.signal(());
+    }
+}
+
+impl<M, D, const P: usize, const Q: usize> Sender for PrioritySender<'_, M, D, P, Q>
+where
+    M: RawMutex,
+{
+    type Error = core::convert::Infallible;
+
+    type Data = D;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        PrioritySender::send(self, data).await;
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::vec::Vec;
+
+    use embassy_futures::join::join;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use crate::adapt::block_on;
+
+    use super::*;
+
+    /// Records the classes of the sent messages, failing once `limit` messages are recorded
+    /// so that `run` returns.
+    struct Recorder {
+        limit: usize,
+        classes: Vec<usize>,
+    }
+
+    impl Sender for Recorder {
+        type Error = ();
+
+        type Data = (usize, u32);
+
+        async fn send(&mut self, (class, _): Self::Data) -> Result<(), ()> {
+            self.classes.push(class);
+
+            if self.classes.len() < self.limit {
+                Ok(())
+            } else {
+                Err(())
+            }
+        }
+    }
+
+    type Mux<const P: usize> = PriorityMux<NoopRawMutex, (usize, u32), P, 256>;
+
+    /// Queue `counts[class]` messages for each class, then run the mux for `limit` messages.
+    fn run<const P: usize>(mux: &Mux<P>, counts: [u32; P], limit: usize) -> Vec<usize> {
+        let mut recorder = Recorder {
+            limit,
+            classes: Vec::new(),
+        };
+
+        block_on(async {
+            for (class, count) in counts.into_iter().enumerate() {
+                let mut sender = mux.sender(class);
+
+                for seq in 0..count {
+                    sender.send((class, seq)).await;
+                }
+            }
+
+            mux.run(&mut recorder).await.unwrap_err();
+        });
+
+        recorder.classes
+    }
+
+    fn count(classes: &[usize], class: usize) -> usize {
+        classes.iter().filter(|c| **c == class).count()
+    }
+
+    #[test]
+    fn strict() {
+        let mux = Mux::<3>::new(Schedule::Strict);
+
+        let classes = run(&mux, [2, 3, 1], 6);
+
+        assert_eq!(classes, [0, 0, 1, 1, 1, 2]);
+    }
+
+    #[test]
+    fn weighted() {
+        let mux = Mux::<4>::new(Schedule::Weighted([5, 3, 1, 0]));
+
+        let classes = run(&mux, [100, 100, 100, 10], 90);
+
+        // Each round of 9 messages is split as per the weights, and the class with
+        // a weight of zero is not served while the others are not empty
+        for round in classes.chunks(9) {
+            assert_eq!(
+                [0, 1, 2, 3].map(|class| count(round, class)),
+                [5, 3, 1, 0],
+                "{classes:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn weighted_empty() {
+        let mux = Mux::<3>::new(Schedule::Weighted([2, 1, 0]));
+
+        let classes = run(&mux, [2, 4, 2], 8);
+
+        // Once a class is empty, the rest is shared among the others
+        assert_eq!(count(&classes[..3], 0), 2);
+        assert_eq!(&classes[3..], [1, 1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn no_starvation() {
+        let mux = Mux::<2>::new(Schedule::Weighted([50, 1]));
+
+        let classes = run(&mux, [250, 5], 255);
+
+        // The low priority class is served once in every round of 51 messages,
+        // even though the high priority class never runs out of messages
+        for round in classes.chunks(51) {
+            assert_eq!(count(round, 1), 1, "{classes:?}");
+        }
+    }
+
+    #[test]
+    fn wakeup() {
+        let mux = Mux::<2>::new(Schedule::Weighted([1, 1]));
+
+        let mut recorder = Recorder {
+            limit: 4,
+            classes: Vec::new(),
+        };
+
+        let (result, ()) = block_on(join(mux.run(&mut recorder), async {
+            // `run` is waiting for messages by now
+            for class in [1, 0, 1, 0] {
+                embassy_futures::yield_now().await;
+                mux.sender(class).send((class, 0)).await;
+            }
+        }));
+
+        assert_eq!(result, Err(()));
+        assert_eq!(recorder.classes, [1, 0, 1, 0]);
+    }
+}
@@ -0,0 +1,481 @@
+//! Typed channels over serial (UART) links.
+//!
+//! Each serialized message is followed by its CRC-32 and then COBS-encoded, so that a zero byte
+//! only ever appears as a frame delimiter. A receiver which observes a corrupted frame reports
+//! the corruption as an error and resynchronizes on the next delimiter, so the link stays usable.
+
+use core::fmt::{self, Debug, Display};
+use core::marker::PhantomData;
+
+use embedded_io_async::{Read, Write};
+
+use crate::codec::{self, CodecError, ReceiveData, SendData};
+
+const CRC_SIZE: usize = 4;
+const DELIMITER: u8 = 0;
+
+#[derive(Debug)]
+pub enum SerialError<E> {
+    IoError(E),
+    CodecError(CodecError),
+    InvalidFrame,
+    CrcMismatch,
+    UnexpectedEof,
+    OversizedFrame(usize),
+}
+
+impl<E> Display for SerialError<E>
+where
+    E: Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IoError(e) => write!(f, "IO Error: {e}"),
+            Self::CodecError(e) => write!(f, "Codec Error: {e}"),
+            Self::InvalidFrame => write!(f, "Invalid Frame Error"),
+            Self::CrcMismatch => write!(f, "CRC Mismatch Error"),
+            Self::UnexpectedEof => write!(f, "Unexpected EOF Error"),
+            Self::OversizedFrame(delta) => write!(
+                f,
+                "Oversized Frame Error: Frame exceeds max size by {delta}"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E> std::error::Error for SerialError<E> where E: Display + Debug {}
+
+impl<E> From<CodecError> for SerialError<E> {
+    fn from(e: CodecError) -> Self {
+        Self::CodecError(e)
+    }
+}
+
+pub struct SerialSender<'a, W, D> {
+    write: W,
+    buf: &'a mut [u8],
+    _type: PhantomData<fn() -> D>,
+}
+
+impl<'a, W, D> SerialSender<'a, W, D>
+where
+    W: Write,
+    D: SendData,
+{
+    /// Create a new sender.
+    ///
+    /// The last 4 bytes of `buf` are reserved for the CRC.
+    pub fn new(write: W, buf: &'a mut [u8]) -> Self {
+        Self {
+            write,
+            buf,
+            _type: PhantomData,
+        }
+    }
+
+    pub async fn send(&mut self, data: D) -> Result<(), SerialError<W::Error>> {
+        let max_len = self.buf.len().saturating_sub(CRC_SIZE);
+        let len = codec::encode(&data, &mut self.buf[..max_len])?.len();
+
+        let crc = crc32(&self.buf[..len]);
+        self.buf[len..len + CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+
+        cobs_write(&mut self.write, &self.buf[..len + CRC_SIZE])
+            .await
+            .map_err(SerialError::IoError)?;
+
+        self.write.flush().await.map_err(SerialError::IoError)?;
+
+        Ok(())
+    }
+}
+
+impl<W, D> crate::asynch::Sender for SerialSender<'_, W, D>
+where
+    W: Write,
+    D: SendData,
+{
+    type Error = SerialError<W::Error>;
+
+    type Data = D;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        SerialSender::send(self, data).await
+    }
+}
+
+pub struct SerialReceiver<'a, R, D> {
+    read: R,
+    buf: &'a mut [u8],
+    len: usize,
+    consumed: usize,
+    _type: PhantomData<fn() -> D>,
+}
+
+impl<'a, R, D> SerialReceiver<'a, R, D>
+where
+    R: Read,
+{
+    pub fn new(read: R, buf: &'a mut [u8]) -> Self {
+        Self {
+            read,
+            buf,
+            len: 0,
+            consumed: 0,
+            _type: PhantomData,
+        }
+    }
+
+    /// Receive a message which borrows its `&str` / `&[u8]` fields directly
+    /// from the internal buffer, thus avoiding any allocation or copying.
+    ///
+    /// The returned value keeps `self` borrowed until it is dropped.
+    #[cfg(not(feature = "prost"))]
+    pub async fn recv_borrowed<'b, T>(&'b mut self) -> Result<Option<T>, SerialError<R::Error>>
+    where
+        T: serde::Deserialize<'b>,
+    {
+        match self.recv_frame().await? {
+            Some(frame_buf) => Ok(Some(codec::decode_borrowed(frame_buf)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Receive the next frame with a valid CRC, or `None` if the link was closed
+    /// on a frame boundary.
+    async fn recv_frame(&mut self) -> Result<Option<&[u8]>, SerialError<R::Error>> {
+        // Drop the frame returned by the previous call
+        self.buf.copy_within(self.consumed..self.len, 0);
+        self.len -= self.consumed;
+        self.consumed = 0;
+
+        let mut discarded = 0;
+
+        let size = loop {
+            if let Some(pos) = self.buf[..self.len].iter().position(|b| *b == DELIMITER) {
+                self.consumed = pos + 1;
+
+                if discarded > 0 {
+                    return Err(SerialError::OversizedFrame(
+                        discarded + pos - self.buf.len(),
+                    ));
+                }
+
+                if pos > 0 {
+                    break pos;
+                }
+
+                // Empty frame, skip
+                self.buf.copy_within(self.consumed..self.len, 0);
+                self.len -= self.consumed;
+                self.consumed = 0;
+
+                continue;
+            }
+
+            if self.len == self.buf.len() {
+                // No delimiter in a full buffer; discard until the next delimiter
+                discarded += self.len;
+                self.len = 0;
+            }
+
+            let read = self
+                .read
+                .read(&mut self.buf[self.len..])
+                .await
+                .map_err(SerialError::IoError)?;
+
+            if read == 0 {
+                return if self.len == 0 && discarded == 0 {
+                    Ok(None)
+                } else {
+                    Err(SerialError::UnexpectedEof)
+                };
+            }
+
+            self.len += read;
+        };
+
+        let size = cobs_decode_in_place(&mut self.buf[..size]).ok_or(SerialError::InvalidFrame)?;
+        if size < CRC_SIZE {
+            return Err(SerialError::InvalidFrame);
+        }
+
+        let (frame_buf, crc) = self.buf[..size].split_at(size - CRC_SIZE);
+
+        if crc32(frame_buf).to_le_bytes() != crc {
+            return Err(SerialError::CrcMismatch);
+        }
+
+        Ok(Some(frame_buf))
+    }
+}
+
+impl<R, D> SerialReceiver<'_, R, D>
+where
+    R: Read,
+    D: ReceiveData,
+{
+    pub async fn recv(&mut self) -> Result<Option<D>, SerialError<R::Error>> {
+        match self.recv_frame().await? {
+            Some(frame_buf) => Ok(Some(codec::decode(frame_buf)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<R, D> crate::asynch::Receiver for SerialReceiver<'_, R, D>
+where
+    R: Read,
+    D: ReceiveData,
+{
+    type Error = SerialError<R::Error>;
+
+    type Data = Option<D>;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        SerialReceiver::recv(self).await
+    }
+}
+
+/// COBS-encode `data` directly into `write`, followed by a delimiter.
+async fn cobs_write<W>(mut write: W, data: &[u8]) -> Result<(), W::Error>
+where
+    W: Write,
+{
+    let mut rest = data;
+
+    loop {
+        let limit = rest.len().min(254);
+        let run = rest[..limit]
+            .iter()
+            .position(|b| *b == DELIMITER)
+            .unwrap_or(limit);
+
+        write.write_all(&[run as u8 + 1]).await?;
+        write.write_all(&rest[..run]).await?;
+
+        // A full run of 254 bytes (code 0xff) is not followed by an implicit zero,
+        // so a zero right after it starts the next run instead
+        if run < limit && rest[run] == DELIMITER {
+            rest = &rest[run + 1..];
+        } else {
+            rest = &rest[run..];
+
+            if rest.is_empty() {
+                break;
+            }
+        }
+    }
+
+    write.write_all(&[DELIMITER]).await
+}
+
+/// Decode a COBS-encoded frame (without its delimiter) in place,
+/// returning the decoded size or `None` if the frame is malformed.
+fn cobs_decode_in_place(buf: &mut [u8]) -> Option<usize> {
+    let mut read = 0;
+    let mut write = 0;
+
+    while read < buf.len() {
+        let code = buf[read] as usize;
+        if code == 0 || read + code > buf.len() {
+            return None;
+        }
+
+        read += 1;
+
+        buf.copy_within(read..read + code - 1, write);
+        read += code - 1;
+        write += code - 1;
+
+        if code < 0xff && read < buf.len() {
+            buf[write] = 0;
+            write += 1;
+        }
+    }
+
+    Some(write)
+}
+
+/// CRC-32 (IEEE 802.3)
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adapt::block_on;
+
+    use super::*;
+
+    fn cobs_roundtrip(data: &[u8]) {
+        let mut encoded = [0; 1024];
+
+        let len = {
+            let mut write = &mut encoded[..];
+            block_on(cobs_write(&mut write, data)).unwrap();
+
+            1024 - write.len()
+        };
+
+        assert_eq!(encoded[len - 1], DELIMITER);
+        assert!(!encoded[..len - 1].contains(&DELIMITER));
+
+        let size = cobs_decode_in_place(&mut encoded[..len - 1]).unwrap();
+        assert_eq!(&encoded[..size], data);
+    }
+
+    #[test]
+    fn cobs_runs() {
+        for run in [0, 1, 253, 254, 255, 508, 509] {
+            let data = [0xaa; 600];
+
+            // A run without zeroes, followed by nothing, by a zero, or by a zero and more data
+            cobs_roundtrip(&data[..run]);
+
+            let mut data = [0x55; 600];
+            data[run] = 0;
+
+            cobs_roundtrip(&data[..run + 1]);
+            cobs_roundtrip(&data[..run + 2]);
+            cobs_roundtrip(&data[..run + 3]);
+        }
+    }
+
+    #[test]
+    fn cobs_zeroes() {
+        cobs_roundtrip(&[0]);
+        cobs_roundtrip(&[0; 300]);
+        cobs_roundtrip(&[1, 0, 2, 0, 0, 3]);
+    }
+
+    #[test]
+    fn cobs_malformed() {
+        // Code pointing past the end of the frame
+        assert_eq!(cobs_decode_in_place(&mut [5, 1, 2]), None);
+        // Zero code
+        assert_eq!(cobs_decode_in_place(&mut [0, 1]), None);
+    }
+
+    #[cfg(not(feature = "prost"))]
+    /// Send all `messages` through an in-memory pipe, returning the bytes on the wire.
+    fn send<'a>(messages: &[&[u8]], wire: &'a mut [u8]) -> &'a mut [u8] {
+        let mut buf = [0; 700];
+
+        let len = {
+            let mut write = &mut *wire;
+            let mut sender = SerialSender::new(&mut write, &mut buf);
+
+            for message in messages {
+                block_on(sender.send(*message)).unwrap();
+            }
+
+            let rest = write.len();
+            wire.len() - rest
+        };
+
+        &mut wire[..len]
+    }
+
+    #[cfg(not(feature = "prost"))]
+    #[test]
+    fn roundtrip() {
+        // With the 2-byte length prefix of the longer messages, the first zero comes right
+        // after a full run of 254 bytes
+        let mut data = [0x55; 600];
+        data[252] = 0;
+        data[400] = 0;
+
+        for len in (0..20).chain(240..270).chain(500..520) {
+            let mut wire = [0; 1024];
+            let wire = send(&[&data[..len], &[0xaa; 254]], &mut wire);
+
+            let mut buf = [0; 700];
+            let mut receiver = SerialReceiver::<_, &[u8]>::new(&*wire, &mut buf);
+
+            assert_eq!(
+                block_on(receiver.recv_borrowed::<&[u8]>()).unwrap(),
+                Some(&data[..len])
+            );
+            assert_eq!(
+                block_on(receiver.recv_borrowed::<&[u8]>()).unwrap(),
+                Some(&[0xaa; 254][..])
+            );
+            assert_eq!(block_on(receiver.recv_borrowed::<&[u8]>()).unwrap(), None);
+        }
+    }
+
+    #[cfg(not(feature = "prost"))]
+    #[test]
+    fn resync() {
+        let mut wire = [0; 1024];
+        let wire = send(&[b"first", &[0x33; 300], b"third"], &mut wire);
+
+        // Corrupt a byte in the middle of the second frame, without introducing a delimiter
+        let pos = wire.iter().position(|b| *b == DELIMITER).unwrap() + 100;
+        wire[pos] = if wire[pos] == 0x55 { 0xaa } else { 0x55 };
+
+        let mut buf = [0; 700];
+        let mut receiver = SerialReceiver::<_, &[u8]>::new(&*wire, &mut buf);
+
+        assert_eq!(
+            block_on(receiver.recv_borrowed::<&[u8]>()).unwrap(),
+            Some(&b"first"[..])
+        );
+        assert!(matches!(
+            block_on(receiver.recv_borrowed::<&[u8]>()),
+            Err(SerialError::CrcMismatch | SerialError::InvalidFrame)
+        ));
+        assert_eq!(
+            block_on(receiver.recv_borrowed::<&[u8]>()).unwrap(),
+            Some(&b"third"[..])
+        );
+        assert_eq!(block_on(receiver.recv_borrowed::<&[u8]>()).unwrap(), None);
+    }
+
+    #[cfg(not(feature = "prost"))]
+    #[test]
+    fn borrowed() {
+        // Zeroes get COBS-decoded in place, right in the buffer the fields borrow from
+        let message = ("id\0name", &[0, 0, 1, 0][..]);
+
+        let mut wire = [0; 256];
+
+        let len = {
+            let mut write = &mut wire[..];
+            let mut buf = [0; 64];
+
+            block_on(SerialSender::new(&mut write, &mut buf).send(&message)).unwrap();
+
+            256 - write.len()
+        };
+
+        let mut buf = [0; 64];
+        let range = buf.as_ptr_range();
+        let mut receiver = SerialReceiver::<_, (&str, &[u8])>::new(&wire[..len], &mut buf);
+
+        let (name, data) = block_on(receiver.recv_borrowed::<(&str, &[u8])>())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!((name, data), message);
+        assert!(range.contains(&name.as_ptr()));
+        assert!(range.contains(&data.as_ptr()));
+    }
+}
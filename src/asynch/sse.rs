@@ -0,0 +1,897 @@
+//! Typed channels over Server-Sent Events (SSE), for clients which cannot use websockets
+//! (i.e. because they sit behind proxies which do not support them).
+//!
+//! `SseSender` writes each message as an SSE record (`id:`, optional `event:` and `retry:`
+//! fields and a single `data:` line) to the body of an HTTP response, which needs to be sent
+//! with a `Content-Type` of `CONTENT_TYPE`. `SseReceiver` parses such a body on the client side.
+//!
+//! The payload is either JSON (for consumption by browsers' `EventSource`), or the binary
+//! encoding of the crate (postcard, or prost) in base64. As either format can be chosen
+//! at runtime, the messages need to implement `serde` even with the `prost` feature
+//! (see `SseSendData` and `SseReceiveData`).
+
+use core::fmt::{self, Debug, Display};
+use core::marker::PhantomData;
+
+use embedded_io_async::{Read, Write};
+
+use crate::codec::{self, CodecError, ReceiveData, SendData};
+
+/// The content type of an SSE response body.
+pub const CONTENT_TYPE: &str = "text/event-stream";
+
+/// The encoding of the `data:` field of each record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SseFormat {
+    Json,
+    Base64,
+}
+
+/// The messages which can be sent in either `SseFormat`.
+pub trait SseSendData: SendData + serde::Serialize {}
+
+impl<T> SseSendData for T where T: SendData + serde::Serialize {}
+
+/// The messages which can be received in either `SseFormat`.
+pub trait SseReceiveData: ReceiveData + serde::de::DeserializeOwned {}
+
+impl<T> SseReceiveData for T where T: ReceiveData + serde::de::DeserializeOwned {}
+
+#[derive(Debug)]
+pub enum SseError<E> {
+    IoError(E),
+    CodecError(CodecError),
+    JsonSerError(serde_json_core::ser::Error),
+    JsonDeError(serde_json_core::de::Error),
+    InvalidBase64,
+    OversizedFrame(usize),
+}
+
+impl<E> Display for SseError<E>
+where
+    E: Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IoError(e) => write!(f, "IO Error: {e}"),
+            Self::CodecError(e) => write!(f, "Codec Error: {e}"),
+            Self::JsonSerError(e) => write!(f, "JSON Serialization Error: {e}"),
+            Self::JsonDeError(e) => write!(f, "JSON Deserialization Error: {e}"),
+            Self::InvalidBase64 => write!(f, "Invalid Base64 Error"),
+            Self::OversizedFrame(delta) => write!(
+                f,
+                "Oversized Frame Error: Frame exceeds max size by {delta}"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E> std::error::Error for SseError<E> where E: Display + Debug {}
+
+impl<E> From<CodecError> for SseError<E> {
+    fn from(e: CodecError) -> Self {
+        Self::CodecError(e)
+    }
+}
+
+impl<E> From<serde_json_core::ser::Error> for SseError<E> {
+    fn from(e: serde_json_core::ser::Error) -> Self {
+        Self::JsonSerError(e)
+    }
+}
+
+impl<E> From<serde_json_core::de::Error> for SseError<E> {
+    fn from(e: serde_json_core::de::Error) -> Self {
+        Self::JsonDeError(e)
+    }
+}
+
+pub struct SseSender<'a, W, D> {
+    write: W,
+    buf: &'a mut [u8],
+    format: SseFormat,
+    event: Option<&'a str>,
+    next_id: u32,
+    retry: Option<u32>,
+    _type: PhantomData<fn() -> D>,
+}
+
+impl<'a, W, D> SseSender<'a, W, D>
+where
+    W: Write,
+    D: SseSendData,
+{
+    /// Create a new sender.
+    ///
+    /// If `event` is provided, it is sent as the `event:` field of each record and must not
+    /// contain line breaks; otherwise browsers dispatch the records as `message` events.
+    pub fn new(write: W, buf: &'a mut [u8], format: SseFormat, event: Option<&'a str>) -> Self {
+        Self {
+            write,
+            buf,
+            format,
+            event,
+            next_id: 0,
+            retry: None,
+            _type: PhantomData,
+        }
+    }
+
+    /// The `id:` of the next record. Ids start at 0 and are incremented with each record.
+    pub fn next_id(&self) -> u32 {
+        self.next_id
+    }
+
+    /// Continue numbering the records from `id`, i.e. after the `Last-Event-ID` reported
+    /// by a reconnecting client.
+    pub fn set_next_id(&mut self, id: u32) {
+        self.next_id = id;
+    }
+
+    /// Send a `retry:` hint - the reconnection delay in milliseconds - with the next record.
+    pub fn set_retry(&mut self, retry_ms: u32) {
+        self.retry = Some(retry_ms);
+    }
+
+    pub async fn send(&mut self, data: D) -> Result<(), SseError<W::Error>> {
+        let payload_len = match self.format {
+            SseFormat::Json => serde_json_core::to_slice(&data, self.buf)?,
+            SseFormat::Base64 => codec::encode(&data, self.buf)?.len(),
+        };
+
+        let mut num_buf = [0; MAX_U32_DIGITS];
+
+        self.write_all(b"id: ").await?;
+        self.write_all(fmt_u32(self.next_id, &mut num_buf)).await?;
+        self.write_all(b"\n").await?;
+
+        if let Some(event) = self.event {
+            self.write_all(b"event: ").await?;
+            self.write_all(event.as_bytes()).await?;
+            self.write_all(b"\n").await?;
+        }
+
+        if let Some(retry) = self.retry.take() {
+            self.write_all(b"retry: ").await?;
+            self.write_all(fmt_u32(retry, &mut num_buf)).await?;
+            self.write_all(b"\n").await?;
+        }
+
+        self.write_all(b"data: ").await?;
+
+        match self.format {
+            SseFormat::Json => {
+                self.write
+                    .write_all(&self.buf[..payload_len])
+                    .await
+                    .map_err(SseError::IoError)?;
+            }
+            SseFormat::Base64 => {
+                let mut encoded = [0; BASE64_CHUNK_SIZE / 3 * 4];
+
+                for chunk in self.buf[..payload_len].chunks(BASE64_CHUNK_SIZE) {
+                    let len = base64_encode(chunk, &mut encoded);
+
+                    self.write
+                        .write_all(&encoded[..len])
+                        .await
+                        .map_err(SseError::IoError)?;
+                }
+            }
+        }
+
+        self.write_all(b"\n\n").await?;
+        self.write.flush().await.map_err(SseError::IoError)?;
+
+        self.next_id = self.next_id.wrapping_add(1);
+
+        Ok(())
+    }
+
+    /// Send a comment record, which is ignored by clients but keeps idle connections
+    /// from being closed by proxies.
+    pub async fn keep_alive(&mut self) -> Result<(), SseError<W::Error>> {
+        self.write_all(b":\n\n").await?;
+        self.write.flush().await.map_err(SseError::IoError)
+    }
+
+    async fn write_all(&mut self, data: &[u8]) -> Result<(), SseError<W::Error>> {
+        self.write.write_all(data).await.map_err(SseError::IoError)
+    }
+}
+
+impl<W, D> crate::asynch::Sender for SseSender<'_, W, D>
+where
+    W: Write,
+    D: SseSendData,
+{
+    type Error = SseError<W::Error>;
+
+    type Data = D;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        SseSender::send(self, data).await
+    }
+}
+
+/// A parser of SSE response bodies.
+///
+/// The buffer holds the data of the record being received, as well as the bytes read ahead;
+/// records with a longer `data:` field (or lines longer than the buffer) are skipped and
+/// reported as `SseError::OversizedFrame`. With the JSON format, the free part of the buffer
+/// is also used to unescape strings.
+pub struct SseReceiver<'a, R, D> {
+    read: R,
+    buf: &'a mut [u8],
+    format: SseFormat,
+    event: Option<&'a str>,
+    last_event_id: Option<u32>,
+    retry: Option<u32>,
+    data_len: usize,
+    has_data: bool,
+    event_matches: bool,
+    start: usize,
+    end: usize,
+    overflow: usize,
+    skip_line: bool,
+    _type: PhantomData<fn() -> D>,
+}
+
+impl<'a, R, D> SseReceiver<'a, R, D>
+where
+    R: Read,
+{
+    /// Create a new receiver.
+    ///
+    /// If `event` is provided, only records of that event type are received; otherwise
+    /// all records are received, regardless of their `event:` field.
+    pub fn new(read: R, buf: &'a mut [u8], format: SseFormat, event: Option<&'a str>) -> Self {
+        Self {
+            read,
+            buf,
+            format,
+            event,
+            last_event_id: None,
+            retry: None,
+            data_len: 0,
+            has_data: false,
+            event_matches: Self::matches(event, b"message"),
+            start: 0,
+            end: 0,
+            overflow: 0,
+            skip_line: false,
+            _type: PhantomData,
+        }
+    }
+
+    /// The last numeric `id:` received, to be sent as `Last-Event-ID` when reconnecting.
+    pub fn last_event_id(&self) -> Option<u32> {
+        self.last_event_id
+    }
+
+    /// The last `retry:` hint received - the reconnection delay in milliseconds.
+    pub fn retry(&self) -> Option<u32> {
+        self.retry
+    }
+
+    /// Receive a message which borrows its `&str` / `&[u8]` fields directly
+    /// from the internal buffer, thus avoiding any allocation or copying.
+    ///
+    /// The returned value keeps `self` borrowed until it is dropped.
+    #[cfg(not(feature = "prost"))]
+    pub async fn recv_borrowed<'b, T>(&'b mut self) -> Result<Option<T>, SseError<R::Error>>
+    where
+        T: serde::Deserialize<'b>,
+    {
+        match self.recv_frame().await? {
+            Some(len) => match self.format {
+                SseFormat::Json => Ok(Some(self.decode_json(len)?)),
+                SseFormat::Base64 => Ok(Some(codec::decode_borrowed(&self.buf[..len])?)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Decode the JSON payload of the received record, which is at the start of the buffer.
+    fn decode_json<'b, T>(&'b mut self, len: usize) -> Result<T, SseError<R::Error>>
+    where
+        T: serde::Deserialize<'b>,
+    {
+        // Move the bytes read ahead to the end of the buffer, so that all
+        // the space between them and the payload can be used to unescape strings
+        let ahead = self.end - self.start;
+        let start = self.buf.len() - ahead;
+
+        self.buf.copy_within(self.start..self.end, start);
+        self.start = start;
+        self.end = self.buf.len();
+
+        let (data, scratch) = self.buf.split_at_mut(len);
+
+        Ok(serde_json_core::from_slice_escaped(data, &mut scratch[..start - len])?.0)
+    }
+
+    /// Receive the next record of interest, returning the length of its payload,
+    /// or `None` if the stream was closed.
+    ///
+    /// For the base64 format, the payload is already decoded.
+    async fn recv_frame(&mut self) -> Result<Option<usize>, SseError<R::Error>> {
+        loop {
+            if let Some(pos) = self.buf[self.start..self.end]
+                .iter()
+                .position(|byte| *byte == b'\n')
+            {
+                let line_end = self.start + pos;
+                let next = line_end + 1;
+
+                if self.skip_line {
+                    self.skip_line = false;
+                } else if line_end == self.start
+                    || line_end == self.start + 1 && self.buf[self.start] == b'\r'
+                {
+                    self.start = next;
+
+                    if let Some(len) = self.dispatch()? {
+                        return Ok(Some(len));
+                    }
+
+                    continue;
+                } else {
+                    self.process_line(line_end);
+                }
+
+                self.start = next;
+
+                continue;
+            }
+
+            if self.skip_line {
+                self.overflow += self.end - self.start;
+                self.end = self.start;
+            }
+
+            // Move the incomplete line right after the data received so far
+            self.buf.copy_within(self.start..self.end, self.data_len);
+            self.end = self.data_len + self.end - self.start;
+            self.start = self.data_len;
+
+            if self.end == self.buf.len() {
+                self.overflow += self.end;
+                self.data_len = 0;
+                self.start = 0;
+                self.end = 0;
+                self.skip_line = true;
+            }
+
+            let len = self
+                .read
+                .read(&mut self.buf[self.end..])
+                .await
+                .map_err(SseError::IoError)?;
+
+            if len == 0 {
+                // An incomplete record at the end of the stream is discarded
+                self.reset();
+                self.start = 0;
+                self.end = 0;
+
+                return Ok(None);
+            }
+
+            self.end += len;
+        }
+    }
+
+    fn process_line(&mut self, line_end: usize) {
+        let mut line_end = line_end;
+
+        if self.buf[line_end - 1] == b'\r' {
+            line_end -= 1;
+        }
+
+        let line = &self.buf[self.start..line_end];
+
+        // Comment
+        if line[0] == b':' {
+            return;
+        }
+
+        let (name_end, value_start) = match line.iter().position(|byte| *byte == b':') {
+            Some(pos) if line.get(pos + 1) == Some(&b' ') => (pos, pos + 2),
+            Some(pos) => (pos, pos + 1),
+            None => (line.len(), line.len()),
+        };
+
+        let value = &line[value_start..];
+
+        match &line[..name_end] {
+            b"data" => {
+                let value_start = self.start + value_start;
+
+                if self.has_data {
+                    self.buf[self.data_len] = b'\n';
+                    self.data_len += 1;
+                }
+
+                self.buf.copy_within(value_start..line_end, self.data_len);
+                self.data_len += line_end - value_start;
+                self.has_data = true;
+            }
+            b"event" => self.event_matches = Self::matches(self.event, value),
+            b"id" => self.last_event_id = parse_u32(value),
+            b"retry" => {
+                if let Some(retry) = parse_u32(value) {
+                    self.retry = Some(retry);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn dispatch(&mut self) -> Result<Option<usize>, SseError<R::Error>> {
+        let overflow = self.overflow;
+        let len = self.data_len;
+        let dispatch = self.has_data && self.event_matches;
+
+        self.reset();
+
+        if overflow > 0 {
+            return Err(SseError::OversizedFrame(overflow));
+        }
+
+        if !dispatch {
+            return Ok(None);
+        }
+
+        match self.format {
+            SseFormat::Json => Ok(Some(len)),
+            SseFormat::Base64 => Ok(Some(
+                base64_decode_in_place(&mut self.buf[..len]).ok_or(SseError::InvalidBase64)?,
+            )),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.data_len = 0;
+        self.has_data = false;
+        self.event_matches = Self::matches(self.event, b"message");
+        self.overflow = 0;
+    }
+
+    fn matches(event: Option<&str>, value: &[u8]) -> bool {
+        event.map_or(true, |event| event.as_bytes() == value)
+    }
+}
+
+impl<R, D> SseReceiver<'_, R, D>
+where
+    R: Read,
+    D: SseReceiveData,
+{
+    pub async fn recv(&mut self) -> Result<Option<D>, SseError<R::Error>> {
+        match self.recv_frame().await? {
+            Some(len) => match self.format {
+                SseFormat::Json => Ok(Some(self.decode_json(len)?)),
+                SseFormat::Base64 => Ok(Some(codec::decode(&self.buf[..len])?)),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+impl<R, D> crate::asynch::Receiver for SseReceiver<'_, R, D>
+where
+    R: Read,
+    D: SseReceiveData,
+{
+    type Error = SseError<R::Error>;
+
+    type Data = Option<D>;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        SseReceiver::recv(self).await
+    }
+}
+
+const MAX_U32_DIGITS: usize = 10;
+
+fn fmt_u32(value: u32, buf: &mut [u8; MAX_U32_DIGITS]) -> &[u8] {
+    let mut value = value;
+    let mut offset = buf.len();
+
+    loop {
+        offset -= 1;
+        buf[offset] = b'0' + (value % 10) as u8;
+        value /= 10;
+
+        if value == 0 {
+            break &buf[offset..];
+        }
+    }
+}
+
+fn parse_u32(value: &[u8]) -> Option<u32> {
+    if value.is_empty() {
+        return None;
+    }
+
+    value.iter().try_fold(0_u32, |acc, byte| {
+        if byte.is_ascii_digit() {
+            acc.checked_mul(10)?.checked_add((byte - b'0') as u32)
+        } else {
+            None
+        }
+    })
+}
+
+/// The input chunk size for streaming base64 encoding; a multiple of 3, so that only
+/// the last chunk is padded.
+const BASE64_CHUNK_SIZE: usize = 48;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(input: &[u8], output: &mut [u8]) -> usize {
+    let mut len = 0;
+
+    for chunk in input.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+
+        let indices = [
+            bytes[0] >> 2,
+            (bytes[0] & 0x03) << 4 | bytes[1] >> 4,
+            (bytes[1] & 0x0f) << 2 | bytes[2] >> 6,
+            bytes[2] & 0x3f,
+        ];
+
+        for (index, sextet) in indices.iter().enumerate() {
+            output[len + index] = if index <= chunk.len() {
+                BASE64_ALPHABET[*sextet as usize]
+            } else {
+                b'='
+            };
+        }
+
+        len += 4;
+    }
+
+    len
+}
+
+/// Decode padded base64 in place, returning the length of the decoded data.
+fn base64_decode_in_place(buf: &mut [u8]) -> Option<usize> {
+    if buf.len() % 4 != 0 {
+        return None;
+    }
+
+    let quads = buf.len() / 4;
+    let mut len = 0;
+
+    for quad in 0..quads {
+        let offset = quad * 4;
+        let last = quad + 1 == quads;
+
+        let mut sextets = [0_u8; 4];
+        let mut padding = 0;
+
+        for (index, sextet) in sextets.iter_mut().enumerate() {
+            let byte = buf[offset + index];
+
+            if byte == b'=' && last && index >= 2 {
+                padding += 1;
+            } else if padding > 0 {
+                return None;
+            } else {
+                *sextet = match byte {
+                    b'A'..=b'Z' => byte - b'A',
+                    b'a'..=b'z' => byte - b'a' + 26,
+                    b'0'..=b'9' => byte - b'0' + 52,
+                    b'+' => 62,
+                    b'/' => 63,
+                    _ => return None,
+                };
+            }
+        }
+
+        let bytes = [
+            sextets[0] << 2 | sextets[1] >> 4,
+            sextets[1] << 4 | sextets[2] >> 2,
+            sextets[2] << 6 | sextets[3],
+        ];
+
+        // The output never overtakes the input, as 4 input bytes produce at most 3 output bytes
+        for byte in &bytes[..3 - padding] {
+            buf[len] = *byte;
+            len += 1;
+        }
+    }
+
+    Some(len)
+}
+
+#[cfg(all(test, not(feature = "prost")))]
+mod tests {
+    use core::convert::Infallible;
+
+    use std::string::{String, ToString};
+    use std::vec::Vec;
+
+    use embedded_io_async::ErrorType;
+
+    use crate::adapt::block_on;
+
+    use super::*;
+
+    type Message = (u32, String);
+
+    /// Reads the body a few bytes at a time, so that lines and records are split across reads.
+    struct Chunked<'a>(&'a [u8]);
+
+    impl ErrorType for Chunked<'_> {
+        type Error = Infallible;
+    }
+
+    impl Read for Chunked<'_> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            let len = buf.len().min(self.0.len()).min(3);
+
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+
+            Ok(len)
+        }
+    }
+
+    fn message(id: u32) -> Message {
+        (id, "line \"one\"\nline two".to_string())
+    }
+
+    fn send(messages: &[Message], format: SseFormat, event: Option<&str>) -> Vec<u8> {
+        let mut wire = [0; 4096];
+        let mut buf = [0; 64];
+
+        let len = {
+            let mut write = &mut wire[..];
+            let mut sender = SseSender::new(&mut write, &mut buf, format, event);
+
+            block_on(async {
+                sender.keep_alive().await.unwrap();
+
+                for (index, message) in messages.iter().enumerate() {
+                    if index == 1 {
+                        sender.set_retry(1500);
+                    }
+
+                    sender.send(message.clone()).await.unwrap();
+                }
+            });
+
+            4096 - write.len()
+        };
+
+        wire[..len].to_vec()
+    }
+
+    fn recv<D>(
+        body: &[u8],
+        buf_size: usize,
+        format: SseFormat,
+        event: Option<&str>,
+    ) -> Vec<Result<D, SseError<Infallible>>>
+    where
+        D: SseReceiveData,
+    {
+        let mut buf = std::vec![0; buf_size];
+        let mut receiver = SseReceiver::new(Chunked(body), &mut buf, format, event);
+
+        let mut received = Vec::new();
+
+        block_on(async {
+            loop {
+                match receiver.recv().await {
+                    Ok(Some(data)) => received.push(Ok(data)),
+                    Ok(None) => break,
+                    Err(e) => received.push(Err(e)),
+                }
+            }
+        });
+
+        received
+    }
+
+    fn values<D>(received: Vec<Result<D, SseError<Infallible>>>) -> Vec<D> {
+        received.into_iter().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let messages = [message(1), message(2), message(u32::MAX)];
+
+        for format in [SseFormat::Json, SseFormat::Base64] {
+            let body = send(&messages, format, None);
+
+            assert!(body.starts_with(b":\n\nid: 0\ndata: "));
+
+            let mut buf = [0; 128];
+            let mut receiver = SseReceiver::new(Chunked(&body), &mut buf, format, None);
+
+            block_on(async {
+                for (id, message) in messages.iter().enumerate() {
+                    assert_eq!(receiver.recv().await.unwrap().as_ref(), Some(message));
+                    assert_eq!(receiver.last_event_id(), Some(id as u32));
+                    assert_eq!(receiver.retry(), (id > 0).then_some(1500));
+                }
+
+                assert_eq!(receiver.recv().await.unwrap(), None::<Message>);
+            });
+        }
+    }
+
+    #[test]
+    fn recv_borrowed() {
+        // Borrowing from JSON needs strings without escapes
+        let messages = [(1, "plain".to_string())];
+
+        for format in [SseFormat::Json, SseFormat::Base64] {
+            let body = send(&messages, format, None);
+
+            let mut buf = [0; 128];
+            let mut receiver = SseReceiver::<_, Message>::new(&body[..], &mut buf, format, None);
+
+            block_on(async {
+                assert_eq!(
+                    receiver.recv_borrowed::<(u32, &str)>().await.unwrap(),
+                    Some((1, "plain"))
+                );
+            });
+        }
+    }
+
+    #[test]
+    fn fields() {
+        let body = b": a comment\r\n\
+            retry: 3000\n\
+            id: 7\n\
+            data: [1,\n\
+            data:2]\n\
+            \n\
+            id:8\r\n\
+            unknown: field\r\n\
+            data: [3,4]\r\n\
+            \r\n\
+            id: not a number\n\
+            :data: [0,0]\n\
+            data\n\
+            data: [5,\n\
+            data\n\
+            data: 6]\n\
+            \n\
+            data: [7,8]\n";
+
+        let mut buf = [0; 64];
+        let mut receiver = SseReceiver::new(Chunked(body), &mut buf, SseFormat::Json, None);
+
+        block_on(async {
+            assert_eq!(receiver.recv().await.unwrap(), Some((1, 2)));
+            assert_eq!(receiver.last_event_id(), Some(7));
+            assert_eq!(receiver.retry(), Some(3000));
+
+            assert_eq!(receiver.recv().await.unwrap(), Some((3, 4)));
+            assert_eq!(receiver.last_event_id(), Some(8));
+
+            // Empty `data` fields add empty lines, and the comment is ignored
+            assert_eq!(receiver.recv().await.unwrap(), Some((5, 6)));
+            assert_eq!(receiver.last_event_id(), None);
+
+            // The last record is incomplete, and thus discarded
+            assert_eq!(receiver.recv().await.unwrap(), None::<(u32, u32)>);
+        });
+    }
+
+    #[test]
+    fn event_filter() {
+        let body = b"event: tick\ndata: 1\n\n\
+            data: 2\n\n\
+            event: tock\ndata: 3\n\n\
+            event: message\ndata: 4\n\n\
+            event: tick\ndata: 5\n\n";
+
+        let received = |event| values(recv::<u32>(body, 64, SseFormat::Json, event));
+
+        assert_eq!(received(None), [1, 2, 3, 4, 5]);
+        assert_eq!(received(Some("tick")), [1, 5]);
+        assert_eq!(received(Some("message")), [2, 4]);
+
+        let body = send(&[message(1), message(2)], SseFormat::Base64, Some("tock"));
+
+        assert_eq!(
+            body.split(|byte| *byte == b'\n')
+                .filter(|line| *line == b"event: tock")
+                .count(),
+            2
+        );
+
+        let received = |event| values(recv::<Message>(&body, 128, SseFormat::Base64, event));
+
+        assert_eq!(received(None), [message(1), message(2)]);
+        assert_eq!(received(Some("tock")), [message(1), message(2)]);
+        assert!(received(Some("tick")).is_empty());
+    }
+
+    #[test]
+    fn oversized() {
+        let long = "x".repeat(100);
+
+        let body = std::format!(
+            "data: 1\n\n\
+            data: \"{long}\"\n\n\
+            data: 2\n\n\
+            : {long}\n\n\
+            data: 3\n\n\
+            data: 4\n\
+            data: \"{long}\"\n\n\
+            data: 5\n\n"
+        );
+
+        let received = recv::<u32>(body.as_bytes(), 32, SseFormat::Json, None);
+
+        assert_eq!(received.len(), 7);
+        assert!(matches!(received[0], Ok(1)));
+        assert!(matches!(received[1], Err(SseError::OversizedFrame(_))));
+        assert!(matches!(received[2], Ok(2)));
+        // The long comment is not of interest, but still does not fit
+        assert!(matches!(received[3], Err(SseError::OversizedFrame(_))));
+        assert!(matches!(received[4], Ok(3)));
+        assert!(matches!(received[5], Err(SseError::OversizedFrame(_))));
+        assert!(matches!(received[6], Ok(5)));
+    }
+
+    #[test]
+    fn base64() {
+        let data = (0..=255).collect::<Vec<u8>>();
+
+        for len in (0_usize..10).chain([47, 48, 49, 256]) {
+            let mut encoded = std::vec![0; len.div_ceil(3) * 4];
+            let mut encoded_len = 0;
+
+            for chunk in data[..len].chunks(BASE64_CHUNK_SIZE) {
+                encoded_len += base64_encode(chunk, &mut encoded[encoded_len..]);
+            }
+
+            assert_eq!(encoded_len, encoded.len());
+            assert!(!encoded[..encoded_len.saturating_sub(2)].contains(&b'='));
+
+            let decoded_len = base64_decode_in_place(&mut encoded).unwrap();
+            assert_eq!(encoded[..decoded_len], data[..len]);
+        }
+
+        let mut encoded = *b"TWFu";
+        assert_eq!(base64_decode_in_place(&mut encoded), Some(3));
+        assert_eq!(&encoded[..3], b"Man");
+
+        for invalid in [
+            &b"TWF"[..],
+            b"TW=u",
+            b"T===",
+            b"=WFu",
+            b"TWE=TWFu",
+            b"TW!u",
+            b"TWFu TWFu",
+        ] {
+            assert_eq!(base64_decode_in_place(&mut invalid.to_vec()), None);
+        }
+
+        // Invalid records are reported, but do not break the stream
+        let body = b"data: TW!u\n\ndata: AQ==\n\n";
+
+        let received = recv::<u8>(body, 32, SseFormat::Base64, None);
+
+        assert!(matches!(received[0], Err(SseError::InvalidBase64)));
+        assert!(matches!(received[1], Ok(1)));
+    }
+}
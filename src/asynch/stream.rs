@@ -0,0 +1,387 @@
+//! Typed channels over plain byte streams (i.e. TCP sockets or pipes), where each
+//! serialized message is framed with a varint length prefix.
+
+use core::fmt::{self, Debug, Display};
+use core::marker::PhantomData;
+
+use embedded_io_async::{Read, ReadExactError, Write};
+
+use crate::codec::{self, CodecError, FrameError, ReceiveData, SendData};
+
+#[cfg(feature = "tokio")]
+pub use tokio_impl::*;
+
+#[derive(Debug)]
+pub enum StreamError<E> {
+    IoError(E),
+    CodecError(CodecError),
+    InvalidLength,
+    UnexpectedEof,
+    OversizedFrame(usize),
+}
+
+impl<E> Display for StreamError<E>
+where
+    E: Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IoError(e) => write!(f, "IO Error: {e}"),
+            Self::CodecError(e) => write!(f, "Codec Error: {e}"),
+            Self::InvalidLength => write!(f, "Invalid Length Prefix Error"),
+            Self::UnexpectedEof => write!(f, "Unexpected EOF Error"),
+            Self::OversizedFrame(delta) => write!(
+                f,
+                "Oversized Frame Error: Frame exceeds max size by {delta}"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E> std::error::Error for StreamError<E> where E: Display + Debug {}
+
+impl<E> From<CodecError> for StreamError<E> {
+    fn from(e: CodecError) -> Self {
+        Self::CodecError(e)
+    }
+}
+
+impl<E> From<FrameError<E>> for StreamError<E> {
+    fn from(e: FrameError<E>) -> Self {
+        match e {
+            FrameError::IoError(e) => Self::IoError(e),
+            FrameError::InvalidLength => Self::InvalidLength,
+            FrameError::UnexpectedEof => Self::UnexpectedEof,
+            FrameError::OversizedFrame(delta) => Self::OversizedFrame(delta),
+        }
+    }
+}
+
+impl<E> From<ReadExactError<E>> for StreamError<E> {
+    fn from(e: ReadExactError<E>) -> Self {
+        match e {
+            ReadExactError::UnexpectedEof => Self::UnexpectedEof,
+            ReadExactError::Other(e) => Self::IoError(e),
+        }
+    }
+}
+
+pub struct StreamSender<'a, W, D> {
+    write: W,
+    buf: &'a mut [u8],
+    _type: PhantomData<fn() -> D>,
+}
+
+impl<'a, W, D> StreamSender<'a, W, D>
+where
+    W: Write,
+    D: SendData,
+{
+    /// Create a new sender; `buf` needs room for the length prefix as well, i.e.
+    /// `codec::MAX_LEN_PREFIX_SIZE` bytes more than the largest serialized message.
+    pub fn new(write: W, buf: &'a mut [u8]) -> Self {
+        Self {
+            write,
+            buf,
+            _type: PhantomData,
+        }
+    }
+
+    pub async fn send(&mut self, data: D) -> Result<(), StreamError<W::Error>> {
+        let frame = codec::encode_framed(&data, self.buf)?;
+
+        self.write
+            .write_all(frame)
+            .await
+            .map_err(StreamError::IoError)?;
+        self.write.flush().await.map_err(StreamError::IoError)?;
+
+        Ok(())
+    }
+}
+
+impl<W, D> crate::asynch::Sender for StreamSender<'_, W, D>
+where
+    W: Write,
+    D: SendData,
+{
+    type Error = StreamError<W::Error>;
+
+    type Data = D;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        StreamSender::send(self, data).await
+    }
+}
+
+pub struct StreamReceiver<'a, R, D> {
+    read: R,
+    buf: &'a mut [u8],
+    _type: PhantomData<fn() -> D>,
+}
+
+impl<'a, R, D> StreamReceiver<'a, R, D>
+where
+    R: Read,
+{
+    pub fn new(read: R, buf: &'a mut [u8]) -> Self {
+        Self {
+            read,
+            buf,
+            _type: PhantomData,
+        }
+    }
+
+    /// Receive a message which borrows its `&str` / `&[u8]` fields directly
+    /// from the internal buffer, thus avoiding any allocation or copying.
+    ///
+    /// The returned value keeps `self` borrowed until it is dropped.
+    #[cfg(not(feature = "prost"))]
+    pub async fn recv_borrowed<'b, T>(&'b mut self) -> Result<Option<T>, StreamError<R::Error>>
+    where
+        T: serde::Deserialize<'b>,
+    {
+        match self.recv_frame().await? {
+            Some(frame_buf) => Ok(Some(codec::decode_borrowed(frame_buf)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Receive the next frame, or `None` if the stream was closed on a frame boundary.
+    ///
+    /// Oversized frames are skipped, so that the stream stays usable after an
+    /// `OversizedFrame` error.
+    async fn recv_frame(&mut self) -> Result<Option<&[u8]>, StreamError<R::Error>> {
+        Ok(codec::read_frame(&mut self.read, self.buf).await?)
+    }
+}
+
+impl<R, D> StreamReceiver<'_, R, D>
+where
+    R: Read,
+    D: ReceiveData,
+{
+    pub async fn recv(&mut self) -> Result<Option<D>, StreamError<R::Error>> {
+        match self.recv_frame().await? {
+            Some(frame_buf) => Ok(Some(codec::decode(frame_buf)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<R, D> crate::asynch::Receiver for StreamReceiver<'_, R, D>
+where
+    R: Read,
+    D: ReceiveData,
+{
+    type Error = StreamError<R::Error>;
+
+    type Data = Option<D>;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        StreamReceiver::recv(self).await
+    }
+}
+
+#[cfg(feature = "tokio")]
+mod tokio_impl {
+    use embedded_io_adapters::tokio_1::FromTokio;
+
+    use tokio::io::BufReader;
+    use tokio::net::{tcp, TcpStream};
+    #[cfg(unix)]
+    use tokio::net::{unix, UnixStream};
+
+    use super::*;
+
+    pub type TokioStreamSender<'a, T, D> = StreamSender<'a, FromTokio<T>, D>;
+    /// A receiver reading through a `BufReader`, so that the length prefixes, which are read
+    /// byte by byte, do not cost a system call per byte.
+    pub type TokioStreamReceiver<'a, T, D> = StreamReceiver<'a, FromTokio<BufReader<T>>, D>;
+
+    /// Split a connected tokio TCP stream into a sender and a receiver.
+    pub fn tcp<'a, S, R>(
+        stream: TcpStream,
+        send_buf: &'a mut [u8],
+        recv_buf: &'a mut [u8],
+    ) -> (
+        TokioStreamSender<'a, tcp::OwnedWriteHalf, S>,
+        TokioStreamReceiver<'a, tcp::OwnedReadHalf, R>,
+    )
+    where
+        S: SendData,
+        R: ReceiveData,
+    {
+        let (read, write) = stream.into_split();
+
+        (
+            StreamSender::new(FromTokio::new(write), send_buf),
+            StreamReceiver::new(FromTokio::new(BufReader::new(read)), recv_buf),
+        )
+    }
+
+    /// Split a connected tokio Unix domain socket stream into a sender and a receiver.
+    #[cfg(unix)]
+    pub fn unix<'a, S, R>(
+        stream: UnixStream,
+        send_buf: &'a mut [u8],
+        recv_buf: &'a mut [u8],
+    ) -> (
+        TokioStreamSender<'a, unix::OwnedWriteHalf, S>,
+        TokioStreamReceiver<'a, unix::OwnedReadHalf, R>,
+    )
+    where
+        S: SendData,
+        R: ReceiveData,
+    {
+        let (read, write) = stream.into_split();
+
+        (
+            StreamSender::new(FromTokio::new(write), send_buf),
+            StreamReceiver::new(FromTokio::new(BufReader::new(read)), recv_buf),
+        )
+    }
+}
+
+#[cfg(all(test, not(feature = "prost")))]
+mod tests {
+    use crate::adapt::block_on;
+
+    use super::*;
+
+    /// Send all `messages` into `wire`, returning the used part of it.
+    fn send<'w>(messages: &[&[u8]], wire: &'w mut [u8]) -> &'w [u8] {
+        let mut buf = [0; 256];
+
+        let len = {
+            let mut write = &mut *wire;
+            let mut sender = StreamSender::new(&mut write, &mut buf);
+
+            for message in messages {
+                block_on(sender.send(*message)).unwrap();
+            }
+
+            let rest = write.len();
+            wire.len() - rest
+        };
+
+        &wire[..len]
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut wire = [0; 1024];
+        let wire = send(&[b"", b"hello", &[0x55; 200]], &mut wire);
+
+        let mut buf = [0; 256];
+        let mut receiver = StreamReceiver::<_, &[u8]>::new(wire, &mut buf);
+
+        for message in [&b""[..], b"hello", &[0x55; 200]] {
+            assert_eq!(
+                block_on(receiver.recv_borrowed::<&[u8]>()).unwrap(),
+                Some(message)
+            );
+        }
+
+        assert_eq!(block_on(receiver.recv_borrowed::<&[u8]>()).unwrap(), None);
+    }
+
+    #[test]
+    fn oversized() {
+        let mut wire = [0; 1024];
+        // Serialized as 202 and 6 bytes respectively
+        let wire = send(&[&[0x55; 200], b"small"], &mut wire);
+
+        for size in [0, 1, 5, 6, 64, 201, 202] {
+            let mut buf = [0; 256];
+            let mut receiver = StreamReceiver::<_, &[u8]>::new(wire, &mut buf[..size]);
+
+            for (message, len) in [(&[0x55; 200][..], 202), (b"small", 6)] {
+                let received = block_on(receiver.recv_borrowed::<&[u8]>());
+
+                if size >= len {
+                    assert_eq!(received.unwrap(), Some(message));
+                } else {
+                    assert!(matches!(
+                        received,
+                        Err(StreamError::OversizedFrame(delta)) if delta == len - size
+                    ));
+                }
+            }
+
+            assert_eq!(block_on(receiver.recv_borrowed::<&[u8]>()).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        let mut wire = [0; 1024];
+        let wire = send(&[b"hello"], &mut wire);
+
+        let mut buf = [0; 256];
+
+        for len in 1..wire.len() {
+            let mut receiver = StreamReceiver::<_, &[u8]>::new(&wire[..len], &mut buf);
+
+            assert!(matches!(
+                block_on(receiver.recv_borrowed::<&[u8]>()),
+                Err(StreamError::UnexpectedEof)
+            ));
+        }
+
+        let mut receiver = StreamReceiver::<_, &[u8]>::new(&[0xff; 6][..], &mut buf);
+
+        assert!(matches!(
+            block_on(receiver.recv_borrowed::<&[u8]>()),
+            Err(StreamError::InvalidLength)
+        ));
+    }
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Record<'a> {
+        id: u32,
+        name: &'a str,
+        payload: &'a [u8],
+    }
+
+    #[test]
+    fn borrowed() {
+        let record = Record {
+            id: 7,
+            name: "sensor",
+            payload: &[0, 1, 2],
+        };
+
+        let mut wire = [0; 1024];
+
+        let len = {
+            let mut write = &mut wire[..];
+            let mut buf = [0; 64];
+
+            block_on(StreamSender::new(&mut write, &mut buf).send(&record)).unwrap();
+            block_on(StreamSender::new(&mut write, &mut buf).send("plain")).unwrap();
+
+            1024 - write.len()
+        };
+
+        let mut buf = [0; 64];
+        let range = buf.as_ptr_range();
+        let mut receiver = StreamReceiver::<_, Record<'static>>::new(&wire[..len], &mut buf);
+
+        let received = block_on(receiver.recv_borrowed::<Record>())
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, record);
+
+        // The fields are not copied out of the receive buffer
+        assert!(range.contains(&received.name.as_ptr()));
+        assert!(range.contains(&received.payload.as_ptr()));
+
+        assert_eq!(
+            block_on(receiver.recv_borrowed::<&str>()).unwrap(),
+            Some("plain")
+        );
+        assert_eq!(block_on(receiver.recv_borrowed::<&str>()).unwrap(), None);
+    }
+}
@@ -0,0 +1,560 @@
+//! Rate limiting of senders and debouncing of receivers.
+//!
+//! `Throttle` limits a `Sender` to a maximum number of messages per interval, using a token
+//! bucket which allows bursts of up to that many messages. `Debounce` holds back the values of
+//! a `Receiver` until they have not changed for a while.
+//!
+//! Both take a `Clock`, so that they can be driven by a simulated time source in tests.
+
+use embassy_futures::select::{select, Either};
+
+use super::time::Clock;
+use super::{Receiver, Sender};
+
+/// What `Throttle` does with a message which exceeds the rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottlePolicy {
+    /// Wait until the message can be sent
+    Wait,
+    /// Drop the message
+    DropNewest,
+    /// Keep the message as pending, replacing any previously pending one; the pending message
+    /// is sent ahead of the message of the next `send` once the rate limit allows, or by `flush`
+    Coalesce,
+}
+
+/// A `Sender` wrapper which sends at most `max` messages every `interval` milliseconds.
+///
+/// Up to `max` messages can be sent back-to-back; after that, one message is let through every
+/// `interval / max` milliseconds.
+pub struct Throttle<S, C>
+where
+    S: Sender,
+{
+    sender: S,
+    clock: C,
+    policy: ThrottlePolicy,
+    max: u64,
+    interval: u64,
+    // The theoretical arrival time of the next message, in units of `1 / max` milliseconds
+    tat: u64,
+    pending: Option<S::Data>,
+    dropped: u64,
+}
+
+impl<S, C> Throttle<S, C>
+where
+    S: Sender,
+    C: Clock,
+{
+    /// Create a new throttle which sends at most `max` messages every `interval` milliseconds.
+    ///
+    /// # Panics
+    ///
+    /// If `max` is zero.
+    pub fn new(sender: S, clock: C, max: u32, interval: u64, policy: ThrottlePolicy) -> Self {
+        assert!(max > 0);
+
+        Self {
+            sender,
+            clock,
+            policy,
+            max: max as _,
+            interval,
+            tat: 0,
+            pending: None,
+            dropped: 0,
+        }
+    }
+
+    /// The number of messages dropped or coalesced so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Whether a coalesced message is waiting to be sent.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub async fn send(&mut self, data: S::Data) -> Result<(), S::Error> {
+        // The pending message goes first, so that messages are never reordered
+        if self.pending.is_some() && self.try_acquire() {
+            if let Some(pending) = self.pending.take() {
+                self.sender.send(pending).await?;
+            }
+        }
+
+        if self.try_acquire() {
+            return self.sender.send(data).await;
+        }
+
+        match self.policy {
+            ThrottlePolicy::Wait => {
+                self.acquire().await;
+                self.sender.send(data).await
+            }
+            ThrottlePolicy::DropNewest => {
+                self.dropped += 1;
+                Ok(())
+            }
+            ThrottlePolicy::Coalesce => {
+                if self.pending.replace(data).is_some() {
+                    self.dropped += 1;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Wait until the rate limit allows and send the pending coalesced message, if any.
+    ///
+    /// This is cancel-safe up until the pending message is handed over to the wrapped sender,
+    /// so it can be raced against the arrival of the next message.
+    pub async fn flush(&mut self) -> Result<(), S::Error> {
+        if self.pending.is_some() {
+            self.acquire().await;
+
+            if let Some(data) = self.pending.take() {
+                self.sender.send(data).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn acquire(&mut self) {
+        while !self.try_acquire() {
+            let at = (self.tat - (self.max - 1) * self.interval).div_ceil(self.max);
+
+            self.clock.wait_until(at).await;
+        }
+    }
+
+    fn try_acquire(&mut self) -> bool {
+        let now = self.clock.now() * self.max;
+
+        if self.tat <= now + (self.max - 1) * self.interval {
+            self.tat = self.tat.max(now) + self.interval;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<S, C> Sender for Throttle<S, C>
+where
+    S: Sender,
+    C: Clock,
+{
+    type Error = S::Error;
+
+    type Data = S::Data;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        Throttle::send(self, data).await
+    }
+}
+
+/// A `Receiver` wrapper which yields a value only once it has not changed for `stable`
+/// milliseconds.
+///
+/// Values equal to the previously yielded one are not yielded again, so the receiver yields
+/// the settled changes of a noisy input. Receiving from the wrapped receiver needs to be
+/// cancel-safe, as it is raced against the clock.
+pub struct Debounce<R, C>
+where
+    R: Receiver,
+{
+    receiver: R,
+    clock: C,
+    stable: u64,
+    last: Option<R::Data>,
+}
+
+impl<R, C> Debounce<R, C>
+where
+    R: Receiver,
+    R::Data: Clone + PartialEq,
+    C: Clock,
+{
+    pub fn new(receiver: R, clock: C, stable: u64) -> Self {
+        Self {
+            receiver,
+            clock,
+            stable,
+            last: None,
+        }
+    }
+
+    pub async fn recv(&mut self) -> Result<R::Data, R::Error> {
+        let mut candidate = self.receiver.recv().await?;
+        let mut deadline = self.clock.now() + self.stable;
+
+        loop {
+            match select(self.receiver.recv(), self.clock.wait_until(deadline)).await {
+                Either::First(data) => {
+                    let data = data?;
+
+                    if data != candidate {
+                        candidate = data;
+                        deadline = self.clock.now() + self.stable;
+                    }
+                }
+                Either::Second(_) => {
+                    if self.last.as_ref() != Some(&candidate) {
+                        self.last = Some(candidate.clone());
+
+                        break Ok(candidate);
+                    }
+
+                    candidate = self.receiver.recv().await?;
+                    deadline = self.clock.now() + self.stable;
+                }
+            }
+        }
+    }
+}
+
+impl<R, C> Receiver for Debounce<R, C>
+where
+    R: Receiver,
+    R::Data: Clone + PartialEq,
+    C: Clock,
+{
+    type Error = R::Error;
+
+    type Data = R::Data;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        Debounce::recv(self).await
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use core::cell::Cell;
+    use core::convert::Infallible;
+    use core::future::poll_fn;
+    use core::task::Poll;
+
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    use crate::adapt::block_on;
+
+    use super::*;
+
+    /// A simulated clock, which jumps straight to the instant waited for.
+    struct TestClock(Cell<u64>);
+
+    impl TestClock {
+        fn set(&self, now: u64) {
+            self.0.set(now);
+        }
+    }
+
+    impl Clock for TestClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+
+        async fn wait_until(&self, instant: u64) {
+            self.0.set(self.0.get().max(instant));
+        }
+    }
+
+    /// A simulated clock, which advances by one millisecond whenever a wait is polled.
+    ///
+    /// As opposed to `TestClock`, racing waits resolve in the order of their instants.
+    struct SteppingClock(Cell<u64>);
+
+    impl Clock for SteppingClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+
+        async fn wait_until(&self, instant: u64) {
+            poll_fn(|cx| {
+                if self.0.get() >= instant {
+                    Poll::Ready(())
+                } else {
+                    self.0.set(self.0.get() + 1);
+                    cx.waker().wake_by_ref();
+
+                    Poll::Pending
+                }
+            })
+            .await
+        }
+    }
+
+    /// A receiver yielding each of its values at the given instant, and failing once done.
+    struct Script<'a> {
+        clock: &'a SteppingClock,
+        values: VecDeque<(u64, u32)>,
+    }
+
+    impl Receiver for Script<'_> {
+        type Error = ();
+
+        type Data = u32;
+
+        async fn recv(&mut self) -> Result<u32, ()> {
+            let (at, data) = *self.values.front().ok_or(())?;
+
+            self.clock.wait_until(at).await;
+            self.values.pop_front();
+
+            Ok(data)
+        }
+    }
+
+    /// A sender recording the messages and the time they were sent at.
+    struct Recorder<'a> {
+        clock: &'a TestClock,
+        sent: Vec<(u64, u32)>,
+    }
+
+    impl Sender for Recorder<'_> {
+        type Error = Infallible;
+
+        type Data = u32;
+
+        async fn send(&mut self, data: u32) -> Result<(), Infallible> {
+            self.sent.push((self.clock.now(), data));
+
+            Ok(())
+        }
+    }
+
+    fn throttle(clock: &TestClock, policy: ThrottlePolicy) -> Throttle<Recorder<'_>, &TestClock> {
+        let recorder = Recorder {
+            clock,
+            sent: Vec::new(),
+        };
+
+        Throttle::new(recorder, clock, 3, 300, policy)
+    }
+
+    #[test]
+    fn burst() {
+        let clock = TestClock(Cell::new(1000));
+        let mut throttle = throttle(&clock, ThrottlePolicy::Wait);
+
+        block_on(async {
+            for data in 0..6 {
+                throttle.send(data).await.unwrap();
+            }
+        });
+
+        assert_eq!(
+            throttle.sender.sent,
+            [
+                (1000, 0),
+                (1000, 1),
+                (1000, 2),
+                (1100, 3),
+                (1200, 4),
+                (1300, 5)
+            ]
+        );
+        assert_eq!(throttle.dropped(), 0);
+    }
+
+    #[test]
+    fn steady_rate() {
+        let clock = TestClock(Cell::new(1000));
+        let mut throttle = throttle(&clock, ThrottlePolicy::DropNewest);
+
+        block_on(async {
+            // At the rate limit
+            for data in 0..10 {
+                clock.set(1000 + data as u64 * 100);
+                throttle.send(data).await.unwrap();
+            }
+
+            // After a pause refilling the bucket, at twice the rate limit
+            for data in 10..20 {
+                clock.set(3000 + (data as u64 - 10) * 50);
+                throttle.send(data).await.unwrap();
+            }
+        });
+
+        let sent = throttle.sender.sent.iter().map(|(_, data)| *data);
+
+        assert!(sent.clone().take(10).eq(0..10));
+        // The full bucket, then every other message once it is empty
+        assert!(sent.skip(10).eq([10, 11, 12, 13, 14, 16, 18]));
+        assert_eq!(throttle.dropped(), 3);
+    }
+
+    #[test]
+    fn drop_newest() {
+        let clock = TestClock(Cell::new(1000));
+        let mut throttle = throttle(&clock, ThrottlePolicy::DropNewest);
+
+        block_on(async {
+            for data in 0..5 {
+                throttle.send(data).await.unwrap();
+            }
+
+            clock.set(1100);
+            throttle.send(5).await.unwrap();
+            throttle.send(6).await.unwrap();
+        });
+
+        assert_eq!(
+            throttle.sender.sent,
+            [(1000, 0), (1000, 1), (1000, 2), (1100, 5)]
+        );
+        assert_eq!(throttle.dropped(), 3);
+        assert!(!throttle.is_pending());
+    }
+
+    #[test]
+    fn coalesce() {
+        let clock = TestClock(Cell::new(1000));
+        let mut throttle = throttle(&clock, ThrottlePolicy::Coalesce);
+
+        block_on(async {
+            for data in 0..5 {
+                throttle.send(data).await.unwrap();
+            }
+
+            assert!(throttle.is_pending());
+
+            // The pending message is sent first, with the new one pending in turn
+            clock.set(1100);
+            throttle.send(5).await.unwrap();
+
+            assert!(throttle.is_pending());
+
+            // Both are sent
+            clock.set(1400);
+            throttle.send(6).await.unwrap();
+
+            assert!(!throttle.is_pending());
+
+            clock.set(1450);
+            throttle.send(7).await.unwrap();
+            throttle.send(8).await.unwrap();
+            throttle.flush().await.unwrap();
+        });
+
+        assert_eq!(
+            throttle.sender.sent,
+            [
+                (1000, 0),
+                (1000, 1),
+                (1000, 2),
+                (1100, 4),
+                (1400, 5),
+                (1400, 6),
+                (1450, 7),
+                (1500, 8)
+            ]
+        );
+        assert_eq!(throttle.dropped(), 1);
+        assert!(!throttle.is_pending());
+    }
+
+    #[test]
+    fn flush() {
+        let clock = TestClock(Cell::new(1000));
+        let mut throttle = throttle(&clock, ThrottlePolicy::Coalesce);
+
+        block_on(async {
+            // Nothing to flush
+            throttle.flush().await.unwrap();
+
+            for data in 0..5 {
+                throttle.send(data).await.unwrap();
+            }
+
+            // Waits for the rate limit and sends the latest coalesced message
+            throttle.flush().await.unwrap();
+
+            assert!(!throttle.is_pending());
+
+            throttle.flush().await.unwrap();
+        });
+
+        assert_eq!(
+            throttle.sender.sent,
+            [(1000, 0), (1000, 1), (1000, 2), (1100, 4)]
+        );
+        assert_eq!(throttle.dropped(), 1);
+    }
+
+    #[test]
+    fn pending_first() {
+        let clock = TestClock(Cell::new(1000));
+        let mut throttle = throttle(&clock, ThrottlePolicy::Coalesce);
+
+        block_on(async {
+            for data in 0..4 {
+                throttle.send(data).await.unwrap();
+            }
+
+            // With room for both, the pending message still goes out ahead of the new one
+            clock.set(2000);
+            throttle.send(4).await.unwrap();
+        });
+
+        assert_eq!(
+            throttle.sender.sent,
+            [(1000, 0), (1000, 1), (1000, 2), (2000, 3), (2000, 4)]
+        );
+        assert_eq!(throttle.dropped(), 0);
+        assert!(!throttle.is_pending());
+    }
+
+    #[test]
+    fn debounce() {
+        let clock = SteppingClock(Cell::new(0));
+
+        let script = Script {
+            clock: &clock,
+            values: [
+                // Rapid changes, coalesced into the last one
+                (0, 1),
+                (10, 2),
+                (20, 3),
+                // Unchanged since the last yielded value
+                (300, 3),
+                // A change, repeated while settling
+                (500, 4),
+                (520, 4),
+                // A glitch, back to the last yielded value before settling
+                (700, 5),
+                (750, 4),
+                (1000, 6),
+                // Keeps the script going until the last change has settled
+                (2000, 6),
+            ]
+            .into(),
+        };
+
+        let mut debounce = Debounce::new(script, &clock, 100);
+
+        let mut received = Vec::new();
+
+        block_on(async {
+            while let Ok(data) = debounce.recv().await {
+                received.push((clock.now(), data));
+            }
+        });
+
+        assert_eq!(received.len(), 3);
+
+        for ((at, data), (settled, expected)) in
+            received.into_iter().zip([(120, 3), (600, 4), (1100, 6)])
+        {
+            assert_eq!(data, expected);
+            assert!((settled..settled + 5).contains(&at), "{data} at {at}");
+        }
+    }
+}
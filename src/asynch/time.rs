@@ -0,0 +1,69 @@
+//! A pluggable time source for the channel wrappers which need timeouts.
+
+/// A monotonic clock with millisecond resolution.
+pub trait Clock {
+    /// The current time, in milliseconds since an arbitrary fixed point.
+    fn now(&self) -> u64;
+
+    /// Wait until `now()` reaches `instant`.
+    async fn wait_until(&self, instant: u64);
+}
+
+impl<T> Clock for &T
+where
+    T: Clock,
+{
+    fn now(&self) -> u64 {
+        (*self).now()
+    }
+
+    async fn wait_until(&self, instant: u64) {
+        (*self).wait_until(instant).await
+    }
+}
+
+/// A `Clock` backed by `embassy-time`.
+#[cfg(feature = "embassy-time")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EmbassyClock;
+
+#[cfg(feature = "embassy-time")]
+impl Clock for EmbassyClock {
+    fn now(&self) -> u64 {
+        embassy_time::Instant::now().as_millis()
+    }
+
+    async fn wait_until(&self, instant: u64) {
+        embassy_time::Timer::at(embassy_time::Instant::from_millis(instant)).await
+    }
+}
+
+/// A `Clock` backed by the `tokio` timer.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy)]
+pub struct TokioClock(tokio::time::Instant);
+
+#[cfg(feature = "tokio")]
+impl TokioClock {
+    pub fn new() -> Self {
+        Self(tokio::time::Instant::now())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Default for TokioClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Clock for TokioClock {
+    fn now(&self) -> u64 {
+        self.0.elapsed().as_millis() as _
+    }
+
+    async fn wait_until(&self, instant: u64) {
+        tokio::time::sleep_until(self.0 + core::time::Duration::from_millis(instant)).await
+    }
+}
@@ -0,0 +1,287 @@
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+
+use crate::ChannelError;
+
+use super::{Receiver, Sender};
+
+impl<T> Sender for mpsc::Sender<T> {
+    type Error = ChannelError;
+
+    type Data = T;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        mpsc::Sender::send(self, data)
+            .await
+            .map_err(|_| ChannelError::Disconnected)
+    }
+}
+
+impl<T> Sender for mpsc::UnboundedSender<T> {
+    type Error = ChannelError;
+
+    type Data = T;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        mpsc::UnboundedSender::send(self, data).map_err(|_| ChannelError::Disconnected)
+    }
+}
+
+impl<T> Receiver for mpsc::Receiver<T> {
+    type Error = ChannelError;
+
+    type Data = T;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        mpsc::Receiver::recv(self)
+            .await
+            .ok_or(ChannelError::Disconnected)
+    }
+}
+
+impl<T> Receiver for mpsc::UnboundedReceiver<T> {
+    type Error = ChannelError;
+
+    type Data = T;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        mpsc::UnboundedReceiver::recv(self)
+            .await
+            .ok_or(ChannelError::Disconnected)
+    }
+}
+
+impl<T> Sender for broadcast::Sender<T> {
+    type Error = ChannelError;
+
+    type Data = T;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        broadcast::Sender::send(self, data)
+            .map(|_| ())
+            .map_err(|_| ChannelError::Disconnected)
+    }
+}
+
+impl<T> Receiver for broadcast::Receiver<T>
+where
+    T: Clone,
+{
+    type Error = ChannelError;
+
+    type Data = T;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        broadcast::Receiver::recv(self).await.map_err(|e| match e {
+            broadcast::error::RecvError::Closed => ChannelError::Disconnected,
+            broadcast::error::RecvError::Lagged(skipped) => ChannelError::Lagged(skipped),
+        })
+    }
+}
+
+impl<T> Sender for watch::Sender<T> {
+    type Error = ChannelError;
+
+    type Data = T;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        watch::Sender::send(self, data).map_err(|_| ChannelError::Disconnected)
+    }
+}
+
+impl<T> Receiver for watch::Receiver<T>
+where
+    T: Clone,
+{
+    type Error = ChannelError;
+
+    type Data = T;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        self.changed()
+            .await
+            .map_err(|_| ChannelError::Disconnected)?;
+
+        Ok(self.borrow_and_update().clone())
+    }
+}
+
+/// A oneshot sender is consumed when sending, hence it is wrapped in an `Option`
+/// which becomes `None` after the first send.
+impl<T> Sender for Option<oneshot::Sender<T>> {
+    type Error = ChannelError;
+
+    type Data = T;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        self.take()
+            .ok_or(ChannelError::Disconnected)?
+            .send(data)
+            .map_err(|_| ChannelError::Disconnected)
+    }
+}
+
+impl<T> Receiver for oneshot::Receiver<T> {
+    type Error = ChannelError;
+
+    type Data = T;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        if self.is_terminated() {
+            Err(ChannelError::Disconnected)
+        } else {
+            self.await.map_err(|_| ChannelError::Disconnected)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::{broadcast, mpsc, oneshot, watch};
+
+    use crate::adapt::block_on;
+    use crate::asynch::{Receiver, Sender};
+    use crate::ChannelError;
+
+    #[test]
+    fn mpsc() {
+        block_on(async {
+            let (mut sender, mut receiver) = mpsc::channel(2);
+
+            Sender::send(&mut sender, 1).await.unwrap();
+            Sender::send(&mut sender, 2).await.unwrap();
+
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(1));
+
+            drop(sender);
+
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(2));
+            assert_eq!(
+                Receiver::recv(&mut receiver).await,
+                Err(ChannelError::Disconnected)
+            );
+
+            let (mut sender, receiver) = mpsc::channel(2);
+
+            drop(receiver);
+
+            assert_eq!(
+                Sender::send(&mut sender, 1).await,
+                Err(ChannelError::Disconnected)
+            );
+        });
+    }
+
+    #[test]
+    fn unbounded() {
+        block_on(async {
+            let (mut sender, mut receiver) = mpsc::unbounded_channel();
+
+            Sender::send(&mut sender, 1).await.unwrap();
+
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(1));
+
+            drop(receiver);
+
+            assert_eq!(
+                Sender::send(&mut sender, 2).await,
+                Err(ChannelError::Disconnected)
+            );
+        });
+    }
+
+    #[test]
+    fn broadcast() {
+        block_on(async {
+            let (mut sender, mut receiver) = broadcast::channel(2);
+
+            for data in 0..4 {
+                Sender::send(&mut sender, data).await.unwrap();
+            }
+
+            // The two oldest messages got overwritten
+            assert_eq!(
+                Receiver::recv(&mut receiver).await,
+                Err(ChannelError::Lagged(2))
+            );
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(2));
+
+            drop(sender);
+
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(3));
+            assert_eq!(
+                Receiver::recv(&mut receiver).await,
+                Err(ChannelError::Disconnected)
+            );
+
+            // Without any receivers, nobody gets the message
+            let (mut sender, receiver) = broadcast::channel(2);
+
+            drop(receiver);
+
+            assert_eq!(
+                Sender::send(&mut sender, 1).await,
+                Err(ChannelError::Disconnected)
+            );
+        });
+    }
+
+    #[test]
+    fn watch() {
+        block_on(async {
+            let (mut sender, mut receiver) = watch::channel(0);
+
+            // Only the latest value is received
+            Sender::send(&mut sender, 1).await.unwrap();
+            Sender::send(&mut sender, 2).await.unwrap();
+
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(2));
+
+            drop(sender);
+
+            assert_eq!(
+                Receiver::recv(&mut receiver).await,
+                Err(ChannelError::Disconnected)
+            );
+
+            let (mut sender, receiver) = watch::channel(0);
+
+            drop(receiver);
+
+            assert_eq!(
+                Sender::send(&mut sender, 1).await,
+                Err(ChannelError::Disconnected)
+            );
+        });
+    }
+
+    #[test]
+    fn oneshot() {
+        block_on(async {
+            let (sender, mut receiver) = oneshot::channel();
+            let mut sender = Some(sender);
+
+            Sender::send(&mut sender, 1).await.unwrap();
+
+            // The sender is used up
+            assert_eq!(
+                Sender::send(&mut sender, 2).await,
+                Err(ChannelError::Disconnected)
+            );
+
+            assert_eq!(Receiver::recv(&mut receiver).await, Ok(1));
+            assert_eq!(
+                Receiver::recv(&mut receiver).await,
+                Err(ChannelError::Disconnected)
+            );
+
+            let (sender, mut receiver) = oneshot::channel::<u32>();
+
+            drop(sender);
+
+            assert_eq!(
+                Receiver::recv(&mut receiver).await,
+                Err(ChannelError::Disconnected)
+            );
+        });
+    }
+}
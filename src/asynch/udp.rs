@@ -0,0 +1,249 @@
+//! Typed channels over UDP sockets implementing the `edge-nal` traits,
+//! where each message is sent as a single datagram.
+//!
+//! On `std`, `edge-nal-std` provides an implementation of these traits.
+
+use core::fmt::{self, Debug, Display};
+use core::marker::PhantomData;
+use core::net::SocketAddr;
+
+use edge_nal::{UdpReceive, UdpSend};
+
+use crate::codec::{self, CodecError, ReceiveData, SendData};
+
+#[derive(Debug)]
+pub enum UdpError<E> {
+    IoError(E),
+    CodecError(CodecError),
+    OversizedFrame(usize),
+}
+
+impl<E> Display for UdpError<E>
+where
+    E: Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IoError(e) => write!(f, "IO Error: {e}"),
+            Self::CodecError(e) => write!(f, "Codec Error: {e}"),
+            Self::OversizedFrame(delta) => write!(
+                f,
+                "Oversized Frame Error: Frame exceeds max size by {delta}"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E> std::error::Error for UdpError<E> where E: Display + Debug {}
+
+impl<E> From<CodecError> for UdpError<E> {
+    fn from(e: CodecError) -> Self {
+        Self::CodecError(e)
+    }
+}
+
+pub struct UdpSender<'a, S, D> {
+    socket: S,
+    remote: SocketAddr,
+    buf: &'a mut [u8],
+    _type: PhantomData<fn() -> D>,
+}
+
+impl<'a, S, D> UdpSender<'a, S, D>
+where
+    S: UdpSend,
+    D: SendData,
+{
+    /// Create a new sender which sends to `remote` by default.
+    pub fn new(socket: S, remote: SocketAddr, buf: &'a mut [u8]) -> Self {
+        Self {
+            socket,
+            remote,
+            buf,
+            _type: PhantomData,
+        }
+    }
+
+    pub async fn send(&mut self, data: D) -> Result<(), UdpError<S::Error>> {
+        self.send_to(self.remote, data).await
+    }
+
+    pub async fn send_to(&mut self, remote: SocketAddr, data: D) -> Result<(), UdpError<S::Error>> {
+        let frame_data = codec::encode(&data, self.buf)?;
+
+        self.socket
+            .send(remote, frame_data)
+            .await
+            .map_err(UdpError::IoError)
+    }
+}
+
+impl<S, D> crate::asynch::Sender for UdpSender<'_, S, D>
+where
+    S: UdpSend,
+    D: SendData,
+{
+    type Error = UdpError<S::Error>;
+
+    type Data = D;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        UdpSender::send(self, data).await
+    }
+}
+
+pub struct UdpReceiver<'a, R, D> {
+    socket: R,
+    peer: Option<SocketAddr>,
+    buf: &'a mut [u8],
+    _type: PhantomData<fn() -> D>,
+}
+
+impl<'a, R, D> UdpReceiver<'a, R, D>
+where
+    R: UdpReceive,
+{
+    /// Create a new receiver.
+    ///
+    /// If `peer` is provided, datagrams from all other addresses are silently dropped.
+    ///
+    /// `buf` needs to be at least one byte larger than the largest message, as a datagram
+    /// which fills it completely is treated as truncated.
+    pub fn new(socket: R, peer: Option<SocketAddr>, buf: &'a mut [u8]) -> Self {
+        Self {
+            socket,
+            peer,
+            buf,
+            _type: PhantomData,
+        }
+    }
+
+    /// Receive a message which borrows its `&str` / `&[u8]` fields directly
+    /// from the internal buffer, thus avoiding any allocation or copying.
+    ///
+    /// The returned value keeps `self` borrowed until it is dropped.
+    #[cfg(not(feature = "prost"))]
+    pub async fn recv_borrowed<'b, T>(&'b mut self) -> Result<(SocketAddr, T), UdpError<R::Error>>
+    where
+        T: serde::Deserialize<'b>,
+    {
+        let (remote, frame_buf) = self.recv_frame().await?;
+
+        Ok((remote, codec::decode_borrowed(frame_buf)?))
+    }
+
+    async fn recv_frame(&mut self) -> Result<(SocketAddr, &[u8]), UdpError<R::Error>> {
+        let (size, remote) = loop {
+            let (size, remote) = self
+                .socket
+                .receive(self.buf)
+                .await
+                .map_err(UdpError::IoError)?;
+
+            if self.peer.is_none() || self.peer == Some(remote) {
+                break (size, remote);
+            }
+        };
+
+        // Not all implementations report the full size of a truncated datagram (i.e. `std`
+        // sockets do not), so a datagram which fills the whole buffer might be truncated too
+        if size >= self.buf.len() {
+            return Err(UdpError::OversizedFrame(size + 1 - self.buf.len()));
+        }
+
+        Ok((remote, &self.buf[..size]))
+    }
+}
+
+impl<R, D> UdpReceiver<'_, R, D>
+where
+    R: UdpReceive,
+    D: ReceiveData,
+{
+    /// Receive a message, along with the address it was sent from.
+    pub async fn recv(&mut self) -> Result<(SocketAddr, D), UdpError<R::Error>> {
+        let (remote, frame_buf) = self.recv_frame().await?;
+
+        Ok((remote, codec::decode(frame_buf)?))
+    }
+}
+
+impl<R, D> crate::asynch::Receiver for UdpReceiver<'_, R, D>
+where
+    R: UdpReceive,
+    D: ReceiveData,
+{
+    type Error = UdpError<R::Error>;
+
+    type Data = (SocketAddr, D);
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        UdpReceiver::recv(self).await
+    }
+}
+
+#[cfg(all(test, not(feature = "prost")))]
+mod tests {
+    use core::convert::Infallible;
+    use core::net::{Ipv4Addr, SocketAddrV4};
+
+    use edge_nal::io::ErrorType;
+
+    use crate::adapt::block_on;
+
+    use super::*;
+
+    const REMOTE: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1234));
+
+    /// Receives a 33-byte datagram - a 1-byte length prefix and 32 bytes of data - truncating
+    /// it to the buffer and reporting either its full or its truncated size.
+    struct Datagram {
+        full_size: bool,
+    }
+
+    impl ErrorType for Datagram {
+        type Error = Infallible;
+    }
+
+    impl UdpReceive for Datagram {
+        async fn receive(&mut self, buffer: &mut [u8]) -> Result<(usize, SocketAddr), Infallible> {
+            let mut buf = [0; 64];
+            let data = codec::encode(&[7_u8; 32].as_slice(), &mut buf).unwrap();
+
+            let received = data.len().min(buffer.len());
+            buffer[..received].copy_from_slice(&data[..received]);
+
+            Ok((if self.full_size { data.len() } else { received }, REMOTE))
+        }
+    }
+
+    #[test]
+    fn truncation() {
+        for full_size in [false, true] {
+            let mut buf = [0; 34];
+            let mut receiver = UdpReceiver::<_, &[u8]>::new(Datagram { full_size }, None, &mut buf);
+
+            assert_eq!(
+                block_on(receiver.recv_borrowed::<&[u8]>()).unwrap(),
+                (REMOTE, &[7_u8; 32][..])
+            );
+
+            let mut buf = [0; 33];
+            let mut receiver = UdpReceiver::<_, &[u8]>::new(Datagram { full_size }, None, &mut buf);
+
+            assert!(matches!(
+                block_on(receiver.recv_borrowed::<&[u8]>()),
+                Err(UdpError::OversizedFrame(1))
+            ));
+
+            let mut buf = [0; 20];
+            let mut receiver = UdpReceiver::<_, &[u8]>::new(Datagram { full_size }, None, &mut buf);
+
+            assert!(matches!(
+                block_on(receiver.recv_borrowed::<&[u8]>()),
+                Err(UdpError::OversizedFrame(_))
+            ));
+        }
+    }
+}
@@ -9,38 +9,16 @@ pub use embedded_svc_impl::*;
 #[cfg(feature = "wasm")]
 pub use wasm_impl::*;
 
-#[cfg(not(feature = "prost"))]
-use serde::de::DeserializeOwned as ReceiveData;
-#[cfg(not(feature = "prost"))]
-use serde::Serialize as SendData;
+use crate::codec::{CodecError, SendData};
 
+#[cfg(not(feature = "prost"))]
+use crate::codec::ReceiveData;
 #[cfg(feature = "prost")]
-use prost::Message as SendData;
-#[cfg(feature = "prost")]
-pub trait ReceiveData: prost::Message + Default {}
-#[cfg(feature = "prost")]
-impl<T: prost::Message + Default> ReceiveData for T {}
+pub use crate::codec::{ProstError, ReceiveData};
 
 pub const DEFAULT_HANDLER_TASKS_COUNT: usize = 4;
 pub const DEFAULT_BUF_SIZE: usize = 4096;
 
-#[cfg(feature = "prost")]
-#[derive(Debug)]
-pub enum ProstError {
-    Encode(prost::EncodeError),
-    Decode(prost::DecodeError),
-}
-
-#[cfg(feature = "prost")]
-impl Display for ProstError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            ProstError::Encode(e) => write!(f, "[Encode]: {}", e),
-            ProstError::Decode(e) => write!(f, "[Decode]: {}", e),
-        }
-    }
-}
-
 #[derive(Debug)]
 pub enum WsError<E> {
     IoError(E),
@@ -49,6 +27,7 @@ pub enum WsError<E> {
     PostcardError(postcard::Error),
     #[cfg(feature = "prost")]
     ProstError(ProstError),
+    CompressionError,
     OversizedFrame(usize),
 }
 
@@ -64,6 +43,7 @@ where
             Self::PostcardError(e) => write!(f, "Postcard Error: {e}"),
             #[cfg(feature = "prost")]
             Self::ProstError(e) => write!(f, "Prost Error {e}"),
+            Self::CompressionError => write!(f, "Compression Error: Invalid compressed frame"),
             Self::OversizedFrame(delta) => write!(
                 f,
                 "Oversized Frame Error: Frame exceeds max size by {delta}"
@@ -96,13 +76,26 @@ impl<E> From<prost::DecodeError> for WsError<E> {
     }
 }
 
+impl<E> From<CodecError> for WsError<E> {
+    fn from(e: CodecError) -> Self {
+        match e {
+            #[cfg(not(feature = "prost"))]
+            CodecError::PostcardError(e) => WsError::PostcardError(e),
+            #[cfg(feature = "prost")]
+            CodecError::ProstError(e) => WsError::ProstError(e),
+            CodecError::InvalidCompressedFrame => WsError::CompressionError,
+            CodecError::OversizedFrame(delta) => WsError::OversizedFrame(delta),
+        }
+    }
+}
+
 #[cfg(feature = "edge-ws")]
 mod edge_ws_impl {
     use core::marker::PhantomData;
 
     use embedded_io_async::{Read, Write};
 
-    use edge_ws::{io, FrameType};
+    use edge_ws::{io, FrameHeader, FrameType};
 
     use super::*;
 
@@ -110,6 +103,8 @@ mod edge_ws_impl {
         write: W,
         buf: &'a mut [u8],
         mask: Option<u32>,
+        #[cfg(feature = "compression")]
+        compression: bool,
         _type: PhantomData<fn() -> D>,
     }
 
@@ -123,19 +118,30 @@ mod edge_ws_impl {
                 write,
                 buf,
                 mask,
+                #[cfg(feature = "compression")]
+                compression: false,
                 _type: PhantomData,
             }
         }
 
+        /// Compress the messages (see `crate::codec::encode_compressed`);
+        /// the receiving end needs to have compression enabled as well.
+        #[cfg(feature = "compression")]
+        pub fn set_compression(&mut self, compression: bool) {
+            self.compression = compression;
+        }
+
         pub async fn send(&mut self, data: D) -> Result<(), WsError<io::Error<W::Error>>> {
-            #[cfg(not(feature = "prost"))]
-            let frame_data = postcard::to_slice(&data, self.buf)?;
-            #[cfg(feature = "prost")]
-            let frame_data = {
-                data.encode(self.buf).map_err(WsError::from)?;
-                &self.buf[..data.encoded_len()]
+            #[cfg(feature = "compression")]
+            let frame_data = if self.compression {
+                crate::codec::encode_compressed(&data, self.buf)?
+            } else {
+                crate::codec::encode(&data, self.buf)?
             };
 
+            #[cfg(not(feature = "compression"))]
+            let frame_data = crate::codec::encode(&data, self.buf)?;
+
             io::send(
                 &mut self.write,
                 FrameType::Binary(false),
@@ -166,50 +172,110 @@ mod edge_ws_impl {
     pub struct WsReceiver<'a, R, D> {
         read: R,
         buf: &'a mut [u8],
+        #[cfg(feature = "compression")]
+        compression: bool,
         _type: PhantomData<fn() -> D>,
     }
 
     impl<'a, R, D> WsReceiver<'a, R, D>
     where
         R: Read,
-        D: ReceiveData,
     {
         pub fn new(read: R, buf: &'a mut [u8]) -> Self {
             Self {
                 read,
                 buf,
+                #[cfg(feature = "compression")]
+                compression: false,
                 _type: PhantomData,
             }
         }
 
-        pub async fn recv(&mut self) -> Result<Option<D>, WsError<io::Error<R::Error>>> {
-            let (frame_type, frame_buf) = loop {
-                let (frame_type, size) = io::recv(&mut self.read, self.buf)
+        /// Decompress the messages (see `crate::codec::decompress`),
+        /// as sent by a sender with compression enabled.
+        #[cfg(feature = "compression")]
+        pub fn set_compression(&mut self, compression: bool) {
+            self.compression = compression;
+        }
+
+        /// Receive a message which borrows its `&str` / `&[u8]` fields directly
+        /// from the internal buffer, thus avoiding any allocation or copying.
+        ///
+        /// The returned value keeps `self` borrowed until it is dropped.
+        #[cfg(not(feature = "prost"))]
+        pub async fn recv_borrowed<'b, T>(
+            &'b mut self,
+        ) -> Result<Option<T>, WsError<io::Error<R::Error>>>
+        where
+            T: serde::Deserialize<'b>,
+        {
+            match self.recv_frame().await? {
+                Some(frame_buf) => Ok(Some(crate::codec::decode_borrowed(frame_buf)?)),
+                None => Ok(None),
+            }
+        }
+
+        async fn recv_frame(&mut self) -> Result<Option<&[u8]>, WsError<io::Error<R::Error>>> {
+            let (frame_type, size) = loop {
+                let header = recv_header(&mut self.read)
                     .await
                     .map_err(WsError::IoError)?;
 
-                if frame_type != FrameType::Ping && frame_type != FrameType::Pong {
-                    if size > self.buf.len() {
-                        return Err(WsError::OversizedFrame(size - self.buf.len()));
+                let size = header.payload_len as usize;
+
+                if size > self.buf.len() {
+                    // Skip the payload, so that the connection stays usable
+                    crate::codec::skip(&mut self.read, size)
+                        .await
+                        .map_err(|e| WsError::IoError(e.into()))?;
+
+                    match header.frame_type {
+                        // Control frames carry nothing the receiver needs
+                        FrameType::Ping | FrameType::Pong => continue,
+                        FrameType::Close => return Ok(None),
+                        _ => return Err(WsError::OversizedFrame(size - self.buf.len())),
                     }
-                    break (frame_type, &self.buf[..size]);
+                }
+
+                header
+                    .recv_payload(&mut self.read, self.buf)
+                    .await
+                    .map_err(WsError::IoError)?;
+
+                if header.frame_type != FrameType::Ping && header.frame_type != FrameType::Pong {
+                    break (header.frame_type, size);
                 }
             };
 
             match frame_type {
                 FrameType::Text(_) | FrameType::Continue(_) => Err(WsError::UnknownFrameError),
-                FrameType::Binary(_) => Ok(Some(
-                    #[cfg(not(feature = "prost"))]
-                    postcard::from_bytes(frame_buf).map_err(WsError::PostcardError)?,
-                    #[cfg(feature = "prost")]
-                    prost::Message::decode(frame_buf).map_err(WsError::from)?,
-                )),
+                FrameType::Binary(_) => {
+                    #[cfg(feature = "compression")]
+                    if self.compression {
+                        return Ok(Some(crate::codec::decompress(size, self.buf)?));
+                    }
+
+                    Ok(Some(&self.buf[..size]))
+                }
                 FrameType::Close => Ok(None),
                 _ => unreachable!(),
             }
         }
     }
 
+    impl<R, D> WsReceiver<'_, R, D>
+    where
+        R: Read,
+        D: ReceiveData,
+    {
+        pub async fn recv(&mut self) -> Result<Option<D>, WsError<io::Error<R::Error>>> {
+            match self.recv_frame().await? {
+                Some(frame_buf) => Ok(Some(crate::codec::decode(frame_buf)?)),
+                None => Ok(None),
+            }
+        }
+    }
+
     impl<R, D> crate::asynch::Receiver for WsReceiver<'_, R, D>
     where
         R: Read,
@@ -223,12 +289,186 @@ mod edge_ws_impl {
             WsReceiver::recv(self).await
         }
     }
+
+    /// Like `FrameHeader::recv`, except that it keeps the payload length of the
+    /// control frames, which `FrameHeader` reports as empty, leaving their payload
+    /// in the stream.
+    async fn recv_header<R>(mut read: R) -> Result<FrameHeader, io::Error<R::Error>>
+    where
+        R: Read,
+    {
+        let mut header_buf = [0; FrameHeader::MAX_LEN];
+        let mut read_offset = 0;
+        let mut read_end = FrameHeader::MIN_LEN;
+
+        loop {
+            read.read_exact(&mut header_buf[read_offset..read_end])
+                .await?;
+
+            match FrameHeader::deserialize(&header_buf[..read_end]) {
+                Ok((mut header, _)) => {
+                    if matches!(
+                        header.frame_type,
+                        FrameType::Ping | FrameType::Pong | FrameType::Close
+                    ) {
+                        header.payload_len = match header_buf[1] & 0x7f {
+                            126 => u16::from_be_bytes([header_buf[2], header_buf[3]]) as _,
+                            127 => u64::from_be_bytes(header_buf[2..10].try_into().unwrap()),
+                            len => len as _,
+                        };
+                    }
+
+                    return Ok(header);
+                }
+                Err(io::Error::Incomplete(more)) => {
+                    read_offset = read_end;
+                    read_end += more;
+                }
+                Err(e) => return Err(e.recast()),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::adapt::block_on;
+
+        use super::*;
+
+        #[test]
+        fn oversized() {
+            let mut wire = [0; 1024];
+            let mut buf = [0; 8];
+
+            let len = {
+                let mut write = &mut wire[..];
+
+                let control = [0x55; 100];
+
+                block_on(async {
+                    io::send(&mut write, FrameType::Ping, None, &control).await?;
+
+                    WsSender::new(&mut write, &mut buf, None)
+                        .send(1_u32)
+                        .await
+                        .unwrap();
+
+                    io::send(&mut write, FrameType::Pong, None, &control[..3]).await?;
+                    io::send(&mut write, FrameType::Binary(false), None, &control).await?;
+
+                    WsSender::new(&mut write, &mut buf, Some(0x12345678))
+                        .send(2_u32)
+                        .await
+                        .unwrap();
+
+                    io::send(&mut write, FrameType::Pong, None, &control).await?;
+                    io::send(&mut write, FrameType::Close, None, &control).await
+                })
+                .unwrap();
+
+                1024 - write.len()
+            };
+
+            let mut receiver = WsReceiver::<_, u32>::new(&wire[..len], &mut buf);
+
+            block_on(async {
+                // The oversized ping is skipped
+                assert_eq!(receiver.recv().await.unwrap(), Some(1));
+
+                assert!(matches!(
+                    receiver.recv().await,
+                    Err(WsError::OversizedFrame(92))
+                ));
+
+                // The oversized pong is skipped too, and the oversized close still closes
+                assert_eq!(receiver.recv().await.unwrap(), Some(2));
+                assert_eq!(receiver.recv().await.unwrap(), None);
+            });
+
+            // Without any buffer, every payload gets skipped
+            let frame_len = |data: u32| crate::codec::encode(&data, &mut [0; 8]).unwrap().len();
+
+            let mut receiver = WsReceiver::<_, u32>::new(&wire[..len], &mut []);
+
+            block_on(async {
+                assert!(matches!(
+                    receiver.recv().await,
+                    Err(WsError::OversizedFrame(delta)) if delta == frame_len(1)
+                ));
+                assert!(matches!(
+                    receiver.recv().await,
+                    Err(WsError::OversizedFrame(100))
+                ));
+                assert!(matches!(
+                    receiver.recv().await,
+                    Err(WsError::OversizedFrame(delta)) if delta == frame_len(2)
+                ));
+                assert_eq!(receiver.recv().await.unwrap(), None);
+            });
+        }
+
+        #[cfg(not(feature = "prost"))]
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Record<'a> {
+            id: u32,
+            name: &'a str,
+            payload: &'a [u8],
+        }
+
+        #[cfg(not(feature = "prost"))]
+        #[test]
+        fn borrowed() {
+            let record = Record {
+                id: 7,
+                name: "sensor",
+                payload: &[0, 1, 2],
+            };
+
+            let mut wire = [0; 256];
+
+            let len = {
+                let mut write = &mut wire[..];
+                let mut buf = [0; 64];
+
+                block_on(async {
+                    WsSender::new(&mut write, &mut buf, None)
+                        .send(&record)
+                        .await
+                        .unwrap();
+
+                    // Masked frames get unmasked in place
+                    WsSender::new(&mut write, &mut buf, Some(0x12345678))
+                        .send("masked")
+                        .await
+                        .unwrap();
+                });
+
+                256 - write.len()
+            };
+
+            let mut buf = [0; 64];
+            let range = buf.as_ptr_range();
+            let mut receiver = WsReceiver::<_, Record<'static>>::new(&wire[..len], &mut buf);
+
+            block_on(async {
+                let received = receiver.recv_borrowed::<Record>().await.unwrap().unwrap();
+
+                assert_eq!(received, record);
+                assert!(range.contains(&received.name.as_ptr()));
+                assert!(range.contains(&received.payload.as_ptr()));
+
+                let received = receiver.recv_borrowed::<&str>().await.unwrap().unwrap();
+
+                assert_eq!(received, "masked");
+                assert!(range.contains(&received.as_ptr()));
+            });
+        }
+    }
 }
 
 #[cfg(feature = "embedded-svc")]
 pub mod embedded_svc_impl {
     use core::marker::PhantomData;
-    use core::mem::MaybeUninit;
     use core::pin::pin;
 
     use log::{info, warn};
@@ -243,6 +483,8 @@ pub mod embedded_svc_impl {
     pub struct WsSvcSender<'a, S, D> {
         ws_sender: S,
         buf: &'a mut [u8],
+        #[cfg(feature = "compression")]
+        compression: bool,
         _type: PhantomData<fn() -> D>,
     }
 
@@ -255,19 +497,30 @@ pub mod embedded_svc_impl {
             Self {
                 ws_sender,
                 buf,
+                #[cfg(feature = "compression")]
+                compression: false,
                 _type: PhantomData,
             }
         }
 
+        /// Compress the messages (see `crate::codec::encode_compressed`);
+        /// the receiving end needs to have compression enabled as well.
+        #[cfg(feature = "compression")]
+        pub fn set_compression(&mut self, compression: bool) {
+            self.compression = compression;
+        }
+
         pub async fn send(&mut self, data: &D) -> Result<(), WsError<S::Error>> {
-            #[cfg(not(feature = "prost"))]
-            let frame_data = postcard::to_slice(data, self.buf)?;
-            #[cfg(feature = "prost")]
-            let frame_data = {
-                data.encode(self.buf).map_err(WsError::from)?;
-                &self.buf[..data.encoded_len()]
+            #[cfg(feature = "compression")]
+            let frame_data = if self.compression {
+                crate::codec::encode_compressed(data, self.buf)?
+            } else {
+                crate::codec::encode(data, self.buf)?
             };
 
+            #[cfg(not(feature = "compression"))]
+            let frame_data = crate::codec::encode(data, self.buf)?;
+
             self.ws_sender
                 .send(FrameType::Binary(false), frame_data)
                 .await
@@ -294,24 +547,49 @@ pub mod embedded_svc_impl {
     pub struct WsSvcReceiver<'a, R, D> {
         ws_receiver: R,
         buf: &'a mut [u8],
+        #[cfg(feature = "compression")]
+        compression: bool,
         _type: PhantomData<fn() -> D>,
     }
 
     impl<'a, R, D> WsSvcReceiver<'a, R, D>
     where
         R: embedded_svc::ws::asynch::Receiver,
-        D: ReceiveData,
     {
         pub fn new(ws_receiver: R, buf: &'a mut [u8]) -> Self {
             Self {
                 ws_receiver,
                 buf,
+                #[cfg(feature = "compression")]
+                compression: false,
                 _type: PhantomData,
             }
         }
 
-        pub async fn recv(&mut self) -> Result<Option<D>, WsError<R::Error>> {
-            let (frame_type, frame_buf) = loop {
+        /// Decompress the messages (see `crate::codec::decompress`),
+        /// as sent by a sender with compression enabled.
+        #[cfg(feature = "compression")]
+        pub fn set_compression(&mut self, compression: bool) {
+            self.compression = compression;
+        }
+
+        /// Receive a message which borrows its `&str` / `&[u8]` fields directly
+        /// from the internal buffer, thus avoiding any allocation or copying.
+        ///
+        /// The returned value keeps `self` borrowed until it is dropped.
+        #[cfg(not(feature = "prost"))]
+        pub async fn recv_borrowed<'b, T>(&'b mut self) -> Result<Option<T>, WsError<R::Error>>
+        where
+            T: serde::Deserialize<'b>,
+        {
+            match self.recv_frame().await? {
+                Some(frame_buf) => Ok(Some(crate::codec::decode_borrowed(frame_buf)?)),
+                None => Ok(None),
+            }
+        }
+
+        async fn recv_frame(&mut self) -> Result<Option<&[u8]>, WsError<R::Error>> {
+            let (frame_type, size) = loop {
                 let (frame_type, size) = self
                     .ws_receiver
                     .recv(self.buf)
@@ -322,24 +600,39 @@ pub mod embedded_svc_impl {
                     if size > self.buf.len() {
                         return Err(WsError::OversizedFrame(size - self.buf.len()));
                     }
-                    break (frame_type, &self.buf[..size]);
+                    break (frame_type, size);
                 }
             };
 
             match frame_type {
                 FrameType::Text(_) | FrameType::Continue(_) => Err(WsError::UnknownFrameError),
-                FrameType::Binary(_) => Ok(Some(
-                    #[cfg(not(feature = "prost"))]
-                    postcard::from_bytes(frame_buf).map_err(WsError::PostcardError)?,
-                    #[cfg(feature = "prost")]
-                    prost::Message::decode(frame_buf).map_err(WsError::from)?,
-                )),
+                FrameType::Binary(_) => {
+                    #[cfg(feature = "compression")]
+                    if self.compression {
+                        return Ok(Some(crate::codec::decompress(size, self.buf)?));
+                    }
+
+                    Ok(Some(&self.buf[..size]))
+                }
                 FrameType::Close | FrameType::SocketClose => Ok(None),
                 _ => unreachable!(),
             }
         }
     }
 
+    impl<R, D> WsSvcReceiver<'_, R, D>
+    where
+        R: embedded_svc::ws::asynch::Receiver,
+        D: ReceiveData,
+    {
+        pub async fn recv(&mut self) -> Result<Option<D>, WsError<R::Error>> {
+            match self.recv_frame().await? {
+                Some(frame_buf) => Ok(Some(crate::codec::decode(frame_buf)?)),
+                None => Ok(None),
+            }
+        }
+    }
+
     impl<R, D> crate::asynch::Receiver for WsSvcReceiver<'_, R, D>
     where
         R: ws::asynch::Receiver,
@@ -375,7 +668,7 @@ pub mod embedded_svc_impl {
         const P: usize = DEFAULT_HANDLER_TASKS_COUNT,
         const B: usize = DEFAULT_BUF_SIZE,
         const W: usize = 2,
-    >([MaybeUninit<[u8; B]>; P], [MaybeUninit<[u8; B]>; P]);
+    >([[u8; B]; P], [[u8; B]; P]);
 
     impl<const P: usize, const B: usize, const W: usize> Default for Acceptor<P, B, W> {
         fn default() -> Self {
@@ -386,7 +679,7 @@ pub mod embedded_svc_impl {
     impl<const P: usize, const B: usize, const W: usize> Acceptor<P, B, W> {
         #[inline(always)]
         pub const fn new() -> Self {
-            Self([MaybeUninit::uninit(); P], [MaybeUninit::uninit(); P])
+            Self([[0; B]; P], [[0; B]; P])
         }
 
         #[inline(never)]
@@ -401,53 +694,43 @@ pub mod embedded_svc_impl {
             info!("Creating queue for {W} tasks");
             let channel = embassy_sync::channel::Channel::<NoopRawMutex, _, W>::new();
 
-            let mut workers = heapless::Vec::<_, { P }>::new();
+            let mut bufs = self.0.iter_mut().zip(self.1.iter_mut());
 
-            for task_id in 0..P {
+            // An owned array pinned on the stack, rather than a `heapless::Vec`,
+            // as `select_slice` needs a `Pin<&mut [F]>`
+            let workers = pin!(core::array::from_fn::<_, P, _>(|task_id| {
                 let channel = &channel;
-
-                workers
-                    .push({
-                        let handler = &handler;
-                        let send_buf = self.0[task_id].as_mut_ptr();
-                        let recv_buf = self.1[task_id].as_mut_ptr();
-
-                        async move {
-                            loop {
-                                let (sender, receiver) = channel.receive().await;
-
-                                info!("Handler task {}: Got new connection", task_id);
-
-                                let res = handler
-                                    .handle(
-                                        WsSvcSender::new(
-                                            sender,
-                                            unsafe { send_buf.as_mut() }.unwrap(),
-                                        ),
-                                        WsSvcReceiver::new(
-                                            receiver,
-                                            unsafe { recv_buf.as_mut() }.unwrap(),
-                                        ),
-                                        task_id,
-                                    )
-                                    .await;
-
-                                match res {
-                                    Ok(()) => {
-                                        info!("Handler task {}: connection closed", task_id);
-                                    }
-                                    Err(e) => {
-                                        warn!(
-                                            "Handler task {}: connection closed with error {:?}",
-                                            task_id, e
-                                        );
-                                    }
-                                }
+                let handler = &handler;
+                let (send_buf, recv_buf) = bufs.next().unwrap();
+
+                async move {
+                    loop {
+                        let (sender, receiver) = channel.receive().await;
+
+                        info!("Handler task {}: Got new connection", task_id);
+
+                        let res = handler
+                            .handle(
+                                WsSvcSender::new(sender, send_buf),
+                                WsSvcReceiver::new(receiver, recv_buf),
+                                task_id,
+                            )
+                            .await;
+
+                        match res {
+                            Ok(()) => {
+                                info!("Handler task {}: connection closed", task_id);
+                            }
+                            Err(e) => {
+                                warn!(
+                                    "Handler task {}: connection closed with error {:?}",
+                                    task_id, e
+                                );
                             }
                         }
-                    })
-                    .unwrap_or_else(|_| unreachable!());
-            }
+                    }
+                }
+            }));
 
             let acceptor = pin!(async {
                 loop {
@@ -468,13 +751,187 @@ pub mod embedded_svc_impl {
 
             embassy_futures::select::select(
                 acceptor,
-                embassy_futures::select::select_slice(&mut workers),
+                embassy_futures::select::select_slice(workers),
             )
             .await;
 
             info!("Server processing loop quit");
         }
     }
+
+    #[cfg(all(test, feature = "std"))]
+    mod tests {
+        use core::cell::{Cell, RefCell};
+        use core::future::poll_fn;
+        use core::task::Poll;
+
+        use std::collections::VecDeque;
+
+        use crate::adapt::block_on;
+        use crate::asynch::mock::{Event, MockReceiver, MockSender};
+
+        use super::*;
+
+        type Error = &'static str;
+
+        type Connection = (usize, MockReceiver<Vec<u8>, Error>);
+
+        /// A websocket server whose connections replay the frames of a `MockReceiver`,
+        /// recording the frames sent back by connection in a shared `MockSender`.
+        struct MockAcceptor {
+            connections: RefCell<VecDeque<Connection>>,
+            sent: RefCell<MockSender<(usize, Vec<u8>), Error>>,
+        }
+
+        struct MockWsSender<'a>(usize, &'a RefCell<MockSender<(usize, Vec<u8>), Error>>);
+
+        struct MockWsReceiver(MockReceiver<Vec<u8>, Error>);
+
+        impl ws::ErrorType for MockAcceptor {
+            type Error = Error;
+        }
+
+        impl server::Acceptor for MockAcceptor {
+            type Sender<'a> = MockWsSender<'a>;
+            type Receiver<'a> = MockWsReceiver;
+
+            async fn accept(&self) -> Result<(MockWsSender<'_>, MockWsReceiver), Error> {
+                let connection = self.connections.borrow_mut().pop_front();
+
+                match connection {
+                    Some((id, receiver)) => {
+                        Ok((MockWsSender(id, &self.sent), MockWsReceiver(receiver)))
+                    }
+                    None => core::future::pending().await,
+                }
+            }
+        }
+
+        impl ws::ErrorType for MockWsSender<'_> {
+            type Error = Error;
+        }
+
+        impl embedded_svc::ws::asynch::Sender for MockWsSender<'_> {
+            // `MockSender::send` completes right away, so the borrow is never held while suspended
+            #[allow(clippy::await_holding_refcell_ref)]
+            async fn send(
+                &mut self,
+                frame_type: FrameType,
+                frame_data: &[u8],
+            ) -> Result<(), Error> {
+                assert_eq!(frame_type, FrameType::Binary(false));
+
+                let mut sent = self.1.borrow_mut();
+                crate::asynch::Sender::send(&mut *sent, (self.0, frame_data.to_vec())).await
+            }
+        }
+
+        impl ws::ErrorType for MockWsReceiver {
+            type Error = Error;
+        }
+
+        impl embedded_svc::ws::asynch::Receiver for MockWsReceiver {
+            async fn recv(
+                &mut self,
+                frame_data_buf: &mut [u8],
+            ) -> Result<(FrameType, usize), Error> {
+                match crate::asynch::Receiver::recv(&mut self.0).await? {
+                    Some(frame_data) => {
+                        frame_data_buf[..frame_data.len()].copy_from_slice(&frame_data);
+
+                        Ok((FrameType::Binary(false), frame_data.len()))
+                    }
+                    None => Ok((FrameType::Close, 0)),
+                }
+            }
+        }
+
+        /// Echoes the doubled values back, until the connection is closed.
+        struct Echo<'a> {
+            closed: &'a Cell<usize>,
+        }
+
+        impl AcceptorHandler for Echo<'_> {
+            type SendData = u32;
+            type ReceiveData = u32;
+
+            async fn handle<S, R>(
+                &self,
+                mut sender: S,
+                mut receiver: R,
+                _task_id: usize,
+            ) -> Result<(), S::Error>
+            where
+                S: crate::asynch::Sender<Data = Self::SendData>,
+                R: crate::asynch::Receiver<Error = S::Error, Data = Option<Self::ReceiveData>>,
+            {
+                let res = async {
+                    while let Some(data) = receiver.recv().await? {
+                        sender.send(data * 2).await?;
+                    }
+
+                    Ok(())
+                }
+                .await;
+
+                self.closed.set(self.closed.get() + 1);
+
+                res
+            }
+        }
+
+        fn frame(data: u32) -> Event<Vec<u8>, Error> {
+            let mut buf = [0; 8];
+
+            Event::Ok(crate::codec::encode(&data, &mut buf).unwrap().to_vec())
+        }
+
+        #[test]
+        fn acceptor() {
+            let connections = [
+                (0, vec![frame(1), Event::Pending, frame(2), Event::Close]),
+                (1, vec![frame(3), Event::Err("reset"), frame(100)]),
+                (2, vec![Event::Pending, frame(4), Event::Close]),
+                (3, vec![frame(5), frame(6), Event::Close]),
+            ];
+
+            let acceptor = MockAcceptor {
+                connections: RefCell::new(
+                    connections
+                        .into_iter()
+                        .map(|(id, events)| (id, events.into_iter().collect()))
+                        .collect(),
+                ),
+                sent: RefCell::new(MockSender::new()),
+            };
+
+            let closed = Cell::new(0);
+
+            // Fewer handler tasks than connections, so that tasks get reused
+            let mut server = Acceptor::<2, 64, 1>::new();
+
+            block_on(embassy_futures::select::select(
+                server.run(&acceptor, Echo { closed: &closed }),
+                poll_fn(|_| {
+                    if closed.get() == 4 {
+                        Poll::Ready(())
+                    } else {
+                        Poll::Pending
+                    }
+                }),
+            ));
+
+            let mut sent = acceptor.sent.borrow_mut().take_sent();
+            sent.sort_by_key(|(id, _)| *id);
+
+            let sent = sent
+                .into_iter()
+                .map(|(id, frame_data)| (id, crate::codec::decode::<u32>(&frame_data).unwrap()))
+                .collect::<Vec<_>>();
+
+            assert_eq!(sent, [(0, 2), (0, 4), (1, 6), (2, 8), (3, 10), (3, 12)]);
+        }
+    }
 }
 
 #[cfg(feature = "wasm")]
@@ -488,25 +945,59 @@ mod wasm_impl {
 
     use super::*;
 
-    pub struct WsWebSender<D>(SplitSink<WebSocket, Message>, PhantomData<fn() -> D>);
+    pub struct WsWebSender<D> {
+        sender: SplitSink<WebSocket, Message>,
+        #[cfg(feature = "compression")]
+        compression: bool,
+        _type: PhantomData<fn() -> D>,
+    }
 
     impl<D> WsWebSender<D>
     where
         D: SendData,
     {
         pub const fn new(sender: SplitSink<WebSocket, Message>) -> Self {
-            Self(sender, PhantomData)
+            Self {
+                sender,
+                #[cfg(feature = "compression")]
+                compression: false,
+                _type: PhantomData,
+            }
+        }
+
+        /// Compress the messages (see `crate::codec::encode_compressed`);
+        /// the receiving end needs to have compression enabled as well.
+        #[cfg(feature = "compression")]
+        pub fn set_compression(&mut self, compression: bool) {
+            self.compression = compression;
         }
 
         pub async fn send(&mut self, data: D) -> Result<(), WsError<WebSocketError>> {
             #[cfg(not(feature = "prost"))]
-            let message =
-                Message::Bytes(postcard::to_allocvec(&data).map_err(WsError::PostcardError)?);
+            let bytes = postcard::to_allocvec(&data).map_err(WsError::PostcardError)?;
 
             #[cfg(feature = "prost")]
-            let message = Message::Bytes(data.encode_to_vec());
+            let bytes = data.encode_to_vec();
 
-            self.0.send(message).await.map_err(WsError::IoError)
+            #[cfg(feature = "compression")]
+            let bytes = if self.compression {
+                let len = bytes.len();
+
+                // Room for the flag byte, the message and - after it - the compressed message,
+                // which is only used if it is smaller than the message
+                let mut buf = bytes;
+                buf.insert(0, 0);
+                buf.resize(2 * (len + 1) + crate::codec::MAX_LEN_PREFIX_SIZE, 0);
+
+                crate::codec::compress(len, &mut buf).to_vec()
+            } else {
+                bytes
+            };
+
+            self.sender
+                .send(Message::Bytes(bytes))
+                .await
+                .map_err(WsError::IoError)
         }
     }
 
@@ -523,28 +1014,55 @@ mod wasm_impl {
         }
     }
 
-    pub struct WsWebReceiver<D>(SplitStream<WebSocket>, PhantomData<fn() -> D>);
+    pub struct WsWebReceiver<D> {
+        receiver: SplitStream<WebSocket>,
+        #[cfg(feature = "compression")]
+        compression: bool,
+        _type: PhantomData<fn() -> D>,
+    }
 
     impl<D> WsWebReceiver<D>
     where
         D: ReceiveData,
     {
         pub const fn new(receiver: SplitStream<WebSocket>) -> Self {
-            Self(receiver, PhantomData)
+            Self {
+                receiver,
+                #[cfg(feature = "compression")]
+                compression: false,
+                _type: PhantomData,
+            }
+        }
+
+        /// Decompress the messages (see `crate::codec::decompress`),
+        /// as sent by a sender with compression enabled.
+        #[cfg(feature = "compression")]
+        pub fn set_compression(&mut self, compression: bool) {
+            self.compression = compression;
         }
 
         pub async fn recv(&mut self) -> Result<Option<D>, WsError<WebSocketError>> {
-            if let Some(message) = self.0.next().await {
+            if let Some(message) = self.receiver.next().await {
                 let message = message.map_err(WsError::IoError)?;
 
                 if let Message::Bytes(bytes) = message {
-                    #[cfg(not(feature = "prost"))]
-                    let payload = postcard::from_bytes(&bytes).map_err(WsError::PostcardError)?;
+                    #[cfg(feature = "compression")]
+                    if self.compression {
+                        let len = bytes.len();
 
-                    #[cfg(feature = "prost")]
-                    let payload = prost::Message::decode(&*bytes).map_err(WsError::from)?;
+                        // The size comes from the peer, but is bounded by
+                        // `crate::codec::MAX_COMPRESSED_SIZE`
+                        let decompressed_len = crate::codec::decompressed_len(&bytes)?;
+
+                        let mut buf = bytes;
+                        buf.resize(len + decompressed_len, 0);
+
+                        return Ok(Some(crate::codec::decode(crate::codec::decompress(
+                            len, &mut buf,
+                        )?)?));
+                    }
 
-                    Ok(Some(payload))
+                    Ok(Some(crate::codec::decode(&bytes)?))
                 } else {
                     Err(WsError::UnknownFrameError)
                 }
@@ -0,0 +1,701 @@
+//! The message serialization shared by all framed transports - postcard by default, or prost
+//! when the `prost` feature is enabled - as well as the varint length prefix used by the
+//! stream transports.
+
+use core::fmt::{self, Debug, Display};
+
+#[cfg(feature = "embedded-io-async")]
+use embedded_io_async::ReadExactError;
+
+#[cfg(all(feature = "embedded-io", not(feature = "embedded-io-async")))]
+use embedded_io::ReadExactError;
+
+#[cfg(feature = "compression")]
+pub use compression_impl::*;
+
+#[cfg(not(feature = "prost"))]
+pub use serde::de::DeserializeOwned as ReceiveData;
+#[cfg(not(feature = "prost"))]
+pub use serde::Serialize as SendData;
+
+#[cfg(feature = "prost")]
+pub use prost::Message as SendData;
+#[cfg(feature = "prost")]
+pub trait ReceiveData: prost::Message + Default {}
+#[cfg(feature = "prost")]
+impl<T: prost::Message + Default> ReceiveData for T {}
+
+/// The maximum size of a varint-encoded `u32` length prefix.
+pub const MAX_LEN_PREFIX_SIZE: usize = 5;
+
+/// The size of the stack buffer through which `skip` discards data.
+#[cfg(any(feature = "embedded-io", feature = "embedded-io-async"))]
+const SKIP_CHUNK_SIZE: usize = 64;
+
+#[cfg(feature = "prost")]
+#[derive(Debug)]
+pub enum ProstError {
+    Encode(prost::EncodeError),
+    Decode(prost::DecodeError),
+}
+
+#[cfg(feature = "prost")]
+impl Display for ProstError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProstError::Encode(e) => write!(f, "[Encode]: {}", e),
+            ProstError::Decode(e) => write!(f, "[Decode]: {}", e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CodecError {
+    #[cfg(not(feature = "prost"))]
+    PostcardError(postcard::Error),
+    #[cfg(feature = "prost")]
+    ProstError(ProstError),
+    InvalidCompressedFrame,
+    OversizedFrame(usize),
+}
+
+impl Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(not(feature = "prost"))]
+            Self::PostcardError(e) => write!(f, "Postcard Error: {e}"),
+            #[cfg(feature = "prost")]
+            Self::ProstError(e) => write!(f, "Prost Error {e}"),
+            Self::InvalidCompressedFrame => {
+                write!(f, "Compression Error: Invalid compressed frame")
+            }
+            Self::OversizedFrame(delta) => write!(
+                f,
+                "Oversized Frame Error: Frame exceeds max size by {delta}"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CodecError {}
+
+#[cfg(not(feature = "prost"))]
+impl From<postcard::Error> for CodecError {
+    fn from(e: postcard::Error) -> Self {
+        Self::PostcardError(e)
+    }
+}
+
+#[cfg(feature = "prost")]
+impl From<prost::EncodeError> for CodecError {
+    fn from(e: prost::EncodeError) -> Self {
+        Self::ProstError(ProstError::Encode(e))
+    }
+}
+
+#[cfg(feature = "prost")]
+impl From<prost::DecodeError> for CodecError {
+    fn from(e: prost::DecodeError) -> Self {
+        Self::ProstError(ProstError::Decode(e))
+    }
+}
+
+/// Serialize `data` into `buf`, returning the used part of `buf`.
+pub fn encode<'b, D>(data: &D, buf: &'b mut [u8]) -> Result<&'b [u8], CodecError>
+where
+    D: SendData,
+{
+    #[cfg(not(feature = "prost"))]
+    let frame_data = postcard::to_slice(data, buf)?;
+
+    #[cfg(feature = "prost")]
+    let frame_data = {
+        let len = data.encoded_len();
+        data.encode(&mut &mut *buf)?;
+        &buf[..len]
+    };
+
+    Ok(frame_data)
+}
+
+/// Deserialize a message from `frame_data`.
+pub fn decode<D>(frame_data: &[u8]) -> Result<D, CodecError>
+where
+    D: ReceiveData,
+{
+    #[cfg(not(feature = "prost"))]
+    let data = postcard::from_bytes(frame_data)?;
+
+    #[cfg(feature = "prost")]
+    let data = D::decode(frame_data)?;
+
+    Ok(data)
+}
+
+/// Deserialize a message which borrows its `&str` / `&[u8]` fields from `frame_data`.
+#[cfg(not(feature = "prost"))]
+pub fn decode_borrowed<'b, T>(frame_data: &'b [u8]) -> Result<T, CodecError>
+where
+    T: serde::Deserialize<'b>,
+{
+    Ok(postcard::from_bytes(frame_data)?)
+}
+
+/// Encode `len` as a LEB128 varint into `buf`, returning the used part of `buf`.
+pub fn encode_len(len: u32, buf: &mut [u8; MAX_LEN_PREFIX_SIZE]) -> &[u8] {
+    let mut len = len;
+    let mut size = 0;
+
+    loop {
+        let byte = (len & 0x7f) as u8;
+        len >>= 7;
+
+        if len == 0 {
+            buf[size] = byte;
+            size += 1;
+            break;
+        }
+
+        buf[size] = byte | 0x80;
+        size += 1;
+    }
+
+    &buf[..size]
+}
+
+/// Incremental decoder of a LEB128 varint length prefix.
+#[derive(Debug, Default)]
+pub struct LenDecoder {
+    len: u32,
+    shift: u32,
+}
+
+impl LenDecoder {
+    pub const fn new() -> Self {
+        Self { len: 0, shift: 0 }
+    }
+
+    /// Feed the next byte of the prefix.
+    ///
+    /// Returns `Ok(Some(len))` once the prefix is complete, `Ok(None)` if more bytes are needed
+    /// and `Err(())` if the prefix is malformed or overflows `u32`.
+    #[allow(clippy::result_unit_err)]
+    pub fn push(&mut self, byte: u8) -> Result<Option<u32>, ()> {
+        let value = (byte & 0x7f) as u32;
+
+        if self.shift >= 32 || (self.shift == 28 && value > 0x0f) {
+            return Err(());
+        }
+
+        self.len |= value << self.shift;
+        self.shift += 7;
+
+        if byte & 0x80 == 0 {
+            let len = self.len;
+            *self = Self::new();
+
+            Ok(Some(len))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Serialize `data` into `buf`, preceded by its varint length prefix, returning the frame as a
+/// part of `buf`.
+///
+/// `buf` needs room for the prefix as well, i.e. `MAX_LEN_PREFIX_SIZE` bytes more than the
+/// largest serialized message.
+pub fn encode_framed<'b, D>(data: &D, buf: &'b mut [u8]) -> Result<&'b [u8], CodecError>
+where
+    D: SendData,
+{
+    let len = encode(data, buf.get_mut(MAX_LEN_PREFIX_SIZE..).unwrap_or_default())?.len();
+
+    let mut len_buf = [0; MAX_LEN_PREFIX_SIZE];
+    let len_prefix = encode_len(len as _, &mut len_buf);
+
+    let start = MAX_LEN_PREFIX_SIZE - len_prefix.len();
+    buf[start..MAX_LEN_PREFIX_SIZE].copy_from_slice(len_prefix);
+
+    Ok(&buf[start..MAX_LEN_PREFIX_SIZE + len])
+}
+
+/// Error returned when receiving a varint length-prefixed frame from a byte stream.
+#[cfg(any(feature = "embedded-io", feature = "embedded-io-async"))]
+#[derive(Debug)]
+pub enum FrameError<E> {
+    IoError(E),
+    InvalidLength,
+    UnexpectedEof,
+    /// The frame exceeded the buffer by the given number of bytes, and was skipped
+    OversizedFrame(usize),
+}
+
+#[cfg(any(feature = "embedded-io", feature = "embedded-io-async"))]
+impl<E> Display for FrameError<E>
+where
+    E: Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IoError(e) => write!(f, "IO Error: {e}"),
+            Self::InvalidLength => write!(f, "Invalid Length Prefix Error"),
+            Self::UnexpectedEof => write!(f, "Unexpected EOF Error"),
+            Self::OversizedFrame(delta) => write!(
+                f,
+                "Oversized Frame Error: Frame exceeds max size by {delta}"
+            ),
+        }
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    any(feature = "embedded-io", feature = "embedded-io-async")
+))]
+impl<E> std::error::Error for FrameError<E> where E: Display + Debug {}
+
+#[cfg(any(feature = "embedded-io", feature = "embedded-io-async"))]
+impl<E> From<ReadExactError<E>> for FrameError<E> {
+    fn from(e: ReadExactError<E>) -> Self {
+        match e {
+            ReadExactError::UnexpectedEof => Self::UnexpectedEof,
+            ReadExactError::Other(e) => Self::IoError(e),
+        }
+    }
+}
+
+/// Receive the next varint length-prefixed frame into `buf`, returning it as a part of `buf`,
+/// or `None` if the stream was closed on a frame boundary.
+///
+/// Frames larger than `buf` are skipped, so that the stream stays usable after a
+/// `FrameError::OversizedFrame` error.
+#[cfg(feature = "embedded-io-async")]
+pub async fn read_frame<'b, R>(
+    read: &mut R,
+    buf: &'b mut [u8],
+) -> Result<Option<&'b [u8]>, FrameError<R::Error>>
+where
+    R: embedded_io_async::Read,
+{
+    let mut decoder = LenDecoder::new();
+    let mut first = true;
+
+    let len = loop {
+        let mut byte = [0];
+
+        if read.read(&mut byte).await.map_err(FrameError::IoError)? == 0 {
+            return if first {
+                Ok(None)
+            } else {
+                Err(FrameError::UnexpectedEof)
+            };
+        }
+
+        first = false;
+
+        if let Some(len) = decoder
+            .push(byte[0])
+            .map_err(|_| FrameError::InvalidLength)?
+        {
+            break len as usize;
+        }
+    };
+
+    if len > buf.len() {
+        skip(read, len).await?;
+
+        return Err(FrameError::OversizedFrame(len - buf.len()));
+    }
+
+    read.read_exact(&mut buf[..len]).await?;
+
+    Ok(Some(&buf[..len]))
+}
+
+/// Read and discard the next `len` bytes of `read`, i.e. the rest of a frame which does not
+/// fit in the receive buffer.
+#[cfg(feature = "embedded-io-async")]
+pub async fn skip<R>(read: &mut R, len: usize) -> Result<(), ReadExactError<R::Error>>
+where
+    R: embedded_io_async::Read,
+{
+    let mut scratch = [0; SKIP_CHUNK_SIZE];
+    let mut remaining = len;
+
+    while remaining > 0 {
+        let chunk = remaining.min(scratch.len());
+        read.read_exact(&mut scratch[..chunk]).await?;
+
+        remaining -= chunk;
+    }
+
+    Ok(())
+}
+
+/// The blocking counterpart of `read_frame`.
+#[cfg(feature = "embedded-io")]
+pub fn read_frame_blocking<'b, R>(
+    read: &mut R,
+    buf: &'b mut [u8],
+) -> Result<Option<&'b [u8]>, FrameError<R::Error>>
+where
+    R: embedded_io::Read,
+{
+    let mut decoder = LenDecoder::new();
+    let mut first = true;
+
+    let len = loop {
+        let mut byte = [0];
+
+        if read.read(&mut byte).map_err(FrameError::IoError)? == 0 {
+            return if first {
+                Ok(None)
+            } else {
+                Err(FrameError::UnexpectedEof)
+            };
+        }
+
+        first = false;
+
+        if let Some(len) = decoder
+            .push(byte[0])
+            .map_err(|_| FrameError::InvalidLength)?
+        {
+            break len as usize;
+        }
+    };
+
+    if len > buf.len() {
+        skip_blocking(read, len)?;
+
+        return Err(FrameError::OversizedFrame(len - buf.len()));
+    }
+
+    read.read_exact(&mut buf[..len])?;
+
+    Ok(Some(&buf[..len]))
+}
+
+/// The blocking counterpart of `skip`.
+#[cfg(feature = "embedded-io")]
+pub fn skip_blocking<R>(read: &mut R, len: usize) -> Result<(), ReadExactError<R::Error>>
+where
+    R: embedded_io::Read,
+{
+    let mut scratch = [0; SKIP_CHUNK_SIZE];
+    let mut remaining = len;
+
+    while remaining > 0 {
+        let chunk = remaining.min(scratch.len());
+        read.read_exact(&mut scratch[..chunk])?;
+
+        remaining -= chunk;
+    }
+
+    Ok(())
+}
+
+/// Message compression, applied between the serialization and the framing of a message.
+///
+/// A compressed frame starts with a flag byte: either `FLAG_UNCOMPRESSED`, followed by the
+/// serialized message, or `FLAG_LZ4`, followed by the varint-encoded size of the serialized
+/// message and the message compressed as an LZ4 block (with `lz4_flex`). Messages which are
+/// small or which do not compress well are sent uncompressed.
+#[cfg(feature = "compression")]
+mod compression_impl {
+    use super::*;
+
+    pub const FLAG_UNCOMPRESSED: u8 = 0;
+    pub const FLAG_LZ4: u8 = 1;
+
+    /// Serialized messages smaller than this are not compressed.
+    pub const COMPRESSION_THRESHOLD: usize = 32;
+
+    /// Serialized messages larger than this are not compressed.
+    pub const MAX_COMPRESSED_SIZE: usize = u16::MAX as usize;
+
+    /// Serialize `data` into `buf` and compress it, returning the frame as a part of `buf`.
+    ///
+    /// The message is compressed within `buf`, after the serialized message, where `lz4_flex`
+    /// needs room for the worst case (see `lz4_flex::block::get_maximum_output_size`), so messages
+    /// are only compressed if they take up somewhat less than half of `buf`; larger ones are sent
+    /// uncompressed.
+    pub fn encode_compressed<'b, D>(data: &D, buf: &'b mut [u8]) -> Result<&'b [u8], CodecError>
+    where
+        D: SendData,
+    {
+        let len = encode(data, buf.get_mut(1..).unwrap_or_default())?.len();
+
+        Ok(compress(len, buf))
+    }
+
+    /// Compress the serialized message in `buf[1..=len]` in place, returning the frame as a part
+    /// of `buf`; `buf[0]` is reserved for the flag byte.
+    pub fn compress(len: usize, buf: &mut [u8]) -> &[u8] {
+        if (COMPRESSION_THRESHOLD..=MAX_COMPRESSED_SIZE).contains(&len) {
+            let mut len_buf = [0; MAX_LEN_PREFIX_SIZE];
+            let len_prefix = encode_len(len as u32, &mut len_buf);
+
+            let header_len = 1 + len_prefix.len();
+            let start = 1 + len + header_len;
+
+            if start < buf.len() {
+                let (message, out) = buf.split_at_mut(start);
+
+                let compressed_len = lz4_flex::block::compress_into(&message[1..=len], out)
+                    .ok()
+                    .filter(|compressed_len| header_len + compressed_len < 1 + len);
+
+                if let Some(compressed_len) = compressed_len {
+                    let header = &mut buf[start - header_len..start];
+                    header[0] = FLAG_LZ4;
+                    header[1..].copy_from_slice(len_prefix);
+
+                    return &buf[start - header_len..start + compressed_len];
+                }
+            }
+        }
+
+        buf[0] = FLAG_UNCOMPRESSED;
+
+        &buf[..=len]
+    }
+
+    /// The size of the message contained in `frame`, once decompressed.
+    ///
+    /// Compressed messages claiming to be larger than `MAX_COMPRESSED_SIZE` are rejected with
+    /// `CodecError::OversizedFrame`, so the size is safe to allocate for.
+    pub fn decompressed_len(frame: &[u8]) -> Result<usize, CodecError> {
+        Ok(header(frame)?.0)
+    }
+
+    /// Decompress the frame in `buf[..len]` in place, returning the serialized message
+    /// as a part of `buf`.
+    ///
+    /// `buf` needs to have room for both the compressed frame and the decompressed message.
+    pub fn decompress(len: usize, buf: &mut [u8]) -> Result<&[u8], CodecError> {
+        let (message_len, offset) = header(&buf[..len])?;
+
+        if buf[0] == FLAG_UNCOMPRESSED {
+            return Ok(&buf[offset..len]);
+        }
+
+        // Move the compressed message to the end of `buf`, and decompress it to the start
+        let space = buf.len() - (len - offset);
+
+        if message_len > space {
+            return Err(CodecError::OversizedFrame(message_len - space));
+        }
+
+        buf.copy_within(offset..len, space);
+
+        let (out, compressed) = buf.split_at_mut(space);
+
+        let decompressed_len =
+            lz4_flex::block::decompress_into(compressed, &mut out[..message_len]);
+
+        if decompressed_len.is_ok_and(|decompressed_len| decompressed_len == message_len) {
+            Ok(&buf[..message_len])
+        } else {
+            Err(CodecError::InvalidCompressedFrame)
+        }
+    }
+
+    /// The size of the message in `frame` and the offset of its payload.
+    fn header(frame: &[u8]) -> Result<(usize, usize), CodecError> {
+        match frame.first() {
+            Some(&FLAG_UNCOMPRESSED) => Ok((frame.len() - 1, 1)),
+            Some(&FLAG_LZ4) => {
+                let mut decoder = LenDecoder::new();
+
+                for (index, byte) in frame[1..].iter().enumerate() {
+                    match decoder.push(*byte) {
+                        Ok(Some(len)) => {
+                            let len = len as usize;
+
+                            if len > MAX_COMPRESSED_SIZE {
+                                return Err(CodecError::OversizedFrame(len - MAX_COMPRESSED_SIZE));
+                            }
+
+                            return Ok((len, index + 2));
+                        }
+                        Ok(None) => (),
+                        Err(()) => break,
+                    }
+                }
+
+                Err(CodecError::InvalidCompressedFrame)
+            }
+            _ => Err(CodecError::InvalidCompressedFrame),
+        }
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    mod tests {
+        use std::string::String;
+        use std::vec::Vec;
+
+        use super::*;
+
+        fn compressed(input: &[u8]) -> Vec<u8> {
+            let mut buf = vec![
+                0;
+                2 + input.len()
+                    + MAX_LEN_PREFIX_SIZE
+                    + lz4_flex::block::get_maximum_output_size(input.len())
+            ];
+            buf[1..=input.len()].copy_from_slice(input);
+
+            compress(input.len(), &mut buf).to_vec()
+        }
+
+        fn decompressed(frame: &[u8]) -> Result<Vec<u8>, CodecError> {
+            let mut buf = frame.to_vec();
+            buf.resize(frame.len() + decompressed_len(frame)?, 0);
+
+            decompress(frame.len(), &mut buf).map(<[u8]>::to_vec)
+        }
+
+        /// Incompressible bytes, from a xorshift generator.
+        fn noise(len: usize) -> Vec<u8> {
+            let mut state = 0x2545_f491_u32;
+
+            (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    state as u8
+                })
+                .collect()
+        }
+
+        #[test]
+        fn roundtrip() {
+            let text = b"The quick brown fox jumps over the lazy dog. ".repeat(50);
+            // Long literals followed by a long match
+            let repeated = noise(300).repeat(3);
+
+            for (input, flag) in [
+                (&[][..], FLAG_UNCOMPRESSED),
+                (&[0; COMPRESSION_THRESHOLD - 1], FLAG_UNCOMPRESSED),
+                (&[0; COMPRESSION_THRESHOLD], FLAG_LZ4),
+                (&text, FLAG_LZ4),
+                // Overlapping matches
+                (&[7; 1000], FLAG_LZ4),
+                (&repeated, FLAG_LZ4),
+                (&noise(1000), FLAG_UNCOMPRESSED),
+                (&[0; MAX_COMPRESSED_SIZE], FLAG_LZ4),
+                (&[0; MAX_COMPRESSED_SIZE + 1], FLAG_UNCOMPRESSED),
+            ] {
+                let frame = compressed(input);
+
+                assert_eq!(frame[0], flag);
+                assert!(frame.len() <= input.len() + 1);
+                assert_eq!(decompressed_len(&frame).unwrap(), input.len());
+                assert_eq!(decompressed(&frame).unwrap(), input);
+            }
+        }
+
+        #[test]
+        fn encode_decode() {
+            let message = String::from("compressed ").repeat(10);
+
+            let mut buf = [0; 512];
+            let frame = encode_compressed(&message, &mut buf).unwrap();
+
+            assert_eq!(frame[0], FLAG_LZ4);
+
+            let len = frame.len();
+            let offset = frame.as_ptr() as usize - buf.as_ptr() as usize;
+            buf.copy_within(offset..offset + len, 0);
+
+            assert_eq!(
+                decode::<String>(decompress(len, &mut buf).unwrap()).unwrap(),
+                message
+            );
+        }
+
+        #[test]
+        fn malformed() {
+            for frame in [
+                &[][..],
+                // Unknown flag
+                &[2, 0],
+                // Truncated length
+                &[FLAG_LZ4],
+                &[FLAG_LZ4, 0x80],
+                // Missing block
+                &[FLAG_LZ4, 4],
+                // Truncated literals
+                &[FLAG_LZ4, 4, 0x40, 1, 2],
+                &[FLAG_LZ4, 4, 0xf0, 255, 255],
+                // Truncated offset
+                &[FLAG_LZ4, 8, 0x40, 1, 2, 3, 4, 1],
+                // Zero offset
+                &[FLAG_LZ4, 8, 0x40, 1, 2, 3, 4, 0, 0],
+                // Offset before the start of the message
+                &[FLAG_LZ4, 8, 0x40, 1, 2, 3, 4, 5, 0],
+                // Match beyond the size of the message
+                &[FLAG_LZ4, 6, 0x40, 1, 2, 3, 4, 1, 0],
+                // Literals beyond the size of the message
+                &[FLAG_LZ4, 2, 0x40, 1, 2, 3, 4],
+                // Message shorter than its size
+                &[FLAG_LZ4, 10, 0x40, 1, 2, 3, 4],
+            ] {
+                assert!(
+                    matches!(decompressed(frame), Err(CodecError::InvalidCompressedFrame)),
+                    "{frame:?}"
+                );
+            }
+
+            // Sizes which are never compressed are not trusted
+            for (frame, delta) in [
+                (&[FLAG_LZ4, 0x80, 0x80, 0x04][..], 1),
+                (
+                    &[FLAG_LZ4, 0xff, 0xff, 0xff, 0xff, 0x0f],
+                    u32::MAX as usize - 0xffff,
+                ),
+            ] {
+                assert!(matches!(
+                    decompressed_len(frame),
+                    Err(CodecError::OversizedFrame(d)) if d == delta
+                ));
+            }
+
+            // No room for the decompressed message
+            let mut frame = compressed(&[0; 100]);
+            let len = frame.len();
+
+            assert!(matches!(
+                decompress(len, &mut frame),
+                Err(CodecError::OversizedFrame(_))
+            ));
+        }
+
+        #[test]
+        fn corrupted() {
+            let frame = compressed(&b"corrupted, corrupted, corrupted, ".repeat(8));
+
+            assert_eq!(frame[0], FLAG_LZ4);
+
+            for len in 0..frame.len() {
+                assert!(decompressed(&frame[..len]).is_err());
+            }
+
+            for index in 0..frame.len() {
+                for bit in 0..8 {
+                    let mut frame = frame.clone();
+                    frame[index] ^= 1 << bit;
+
+                    if let Ok(message) = decompressed(&frame) {
+                        assert_eq!(message.len(), decompressed_len(&frame).unwrap());
+                    }
+                }
+            }
+        }
+    }
+}
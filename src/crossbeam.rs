@@ -0,0 +1,62 @@
+use crate::{ChannelError, Receiver, Sender};
+
+impl<T> Sender for crossbeam_channel::Sender<T>
+where
+    T: Clone,
+{
+    type Error = ChannelError;
+
+    type Data = T;
+
+    fn send(&mut self, data: &Self::Data) -> Result<(), Self::Error> {
+        crossbeam_channel::Sender::send(self, data.clone()).map_err(|_| ChannelError::Disconnected)
+    }
+}
+
+impl<T> Receiver for crossbeam_channel::Receiver<T> {
+    type Error = ChannelError;
+
+    type Data = T;
+
+    fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        crossbeam_channel::Receiver::recv(self).map_err(|_| ChannelError::Disconnected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ChannelError, Receiver, Sender};
+
+    #[test]
+    fn roundtrip() {
+        let (mut sender, mut receiver) = crossbeam_channel::bounded(2);
+
+        Sender::send(&mut sender, &1).unwrap();
+        Sender::send(&mut sender, &2).unwrap();
+
+        assert_eq!(Receiver::recv(&mut receiver), Ok(1));
+        assert_eq!(Receiver::recv(&mut receiver), Ok(2));
+
+        // The messages sent before the disconnection are still received
+        Sender::send(&mut sender, &3).unwrap();
+        drop(sender);
+
+        assert_eq!(Receiver::recv(&mut receiver), Ok(3));
+        assert_eq!(
+            Receiver::recv(&mut receiver),
+            Err(ChannelError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn send_disconnected() {
+        let (mut sender, receiver) = crossbeam_channel::unbounded::<u32>();
+
+        drop(receiver);
+
+        assert_eq!(
+            Sender::send(&mut sender, &1),
+            Err(ChannelError::Disconnected)
+        );
+    }
+}
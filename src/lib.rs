@@ -1,10 +1,44 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 #![allow(async_fn_in_trait)]
 
-use core::{fmt::Debug, marker::PhantomData};
+use core::fmt::{self, Debug, Display};
+use core::marker::PhantomData;
 
+pub mod adapt;
 pub mod asynch;
+#[cfg(any(feature = "postcard", feature = "prost"))]
+pub mod codec;
+#[cfg(feature = "crossbeam")]
+pub mod crossbeam;
+#[cfg(feature = "std")]
+pub mod mpsc;
 pub mod notification;
+#[cfg(feature = "stream")]
+pub mod stream;
+#[cfg(feature = "embedded-svc")]
+pub mod ws;
+
+/// Error returned by the `Sender` and `Receiver` implementations of channels
+/// which can be closed by their other side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelError {
+    /// All senders or all receivers of the channel have been dropped
+    Disconnected,
+    /// The receiver fell behind and the given number of messages were skipped
+    Lagged(u64),
+}
+
+impl Display for ChannelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Disconnected => write!(f, "Channel Disconnected"),
+            Self::Lagged(skipped) => write!(f, "Channel Lagged: {skipped} messages skipped"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ChannelError {}
 
 pub trait Sender {
     type Error: Debug;
@@ -0,0 +1,95 @@
+use std::sync::mpsc;
+
+use crate::{ChannelError, Receiver, Sender};
+
+impl<T> Sender for mpsc::Sender<T>
+where
+    T: Clone,
+{
+    type Error = ChannelError;
+
+    type Data = T;
+
+    fn send(&mut self, data: &Self::Data) -> Result<(), Self::Error> {
+        mpsc::Sender::send(self, data.clone()).map_err(|_| ChannelError::Disconnected)
+    }
+}
+
+impl<T> Sender for mpsc::SyncSender<T>
+where
+    T: Clone,
+{
+    type Error = ChannelError;
+
+    type Data = T;
+
+    fn send(&mut self, data: &Self::Data) -> Result<(), Self::Error> {
+        mpsc::SyncSender::send(self, data.clone()).map_err(|_| ChannelError::Disconnected)
+    }
+}
+
+impl<T> Receiver for mpsc::Receiver<T> {
+    type Error = ChannelError;
+
+    type Data = T;
+
+    fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        mpsc::Receiver::recv(self).map_err(|_| ChannelError::Disconnected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use crate::{ChannelError, Receiver, Sender};
+
+    #[test]
+    fn roundtrip() {
+        let (mut sender, mut receiver) = mpsc::channel();
+
+        Sender::send(&mut sender, &1).unwrap();
+        Sender::send(&mut sender, &2).unwrap();
+
+        assert_eq!(Receiver::recv(&mut receiver), Ok(1));
+        assert_eq!(Receiver::recv(&mut receiver), Ok(2));
+
+        // The messages sent before the disconnection are still received
+        Sender::send(&mut sender, &3).unwrap();
+        drop(sender);
+
+        assert_eq!(Receiver::recv(&mut receiver), Ok(3));
+        assert_eq!(
+            Receiver::recv(&mut receiver),
+            Err(ChannelError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn sync_roundtrip() {
+        let (mut sender, mut receiver) = mpsc::sync_channel(1);
+
+        Sender::send(&mut sender, &1).unwrap();
+
+        assert_eq!(Receiver::recv(&mut receiver), Ok(1));
+
+        drop(receiver);
+
+        assert_eq!(
+            Sender::send(&mut sender, &2),
+            Err(ChannelError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn send_disconnected() {
+        let (mut sender, receiver) = mpsc::channel::<u32>();
+
+        drop(receiver);
+
+        assert_eq!(
+            Sender::send(&mut sender, &1),
+            Err(ChannelError::Disconnected)
+        );
+    }
+}
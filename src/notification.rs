@@ -1,9 +1,42 @@
+//! Lightweight notification primitives, for signalling between tasks, threads and interrupt
+//! handlers.
+//!
+//! # Interrupt safety
+//!
+//! `Notification::notify`, `CountingNotification::notify`, `AtomicSignal::signal` and the
+//! `EventGroup` setters are lock-free and never block, so they can be called from interrupt
+//! handlers, while the waiting task runs in thread mode. The waker of the waiting task is
+//! invoked from the interrupt handler, so the executor's wakers need to be interrupt-safe too
+//! (as is the case with `embassy-executor`).
+//!
+//! On targets without atomic read-modify-write instructions (i.e. `thumbv6m` or `riscv32imc`),
+//! enable the `portable-atomic` feature, along with either the `critical-section` feature of
+//! `portable-atomic` or its `unsafe-assume-single-core` cfg. The read-modify-write operations
+//! then run in a critical section, which keeps them atomic with respect to interrupt handlers.
+//!
+//! `MultiNotification` is guarded by a blocking mutex instead, and is only interrupt-safe with
+//! a raw mutex which masks interrupts, like `CriticalSectionRawMutex`.
+
+use core::cell::RefCell;
 use core::future::{poll_fn, Future};
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::marker::PhantomData;
 use core::task::{Context, Poll};
 
+#[cfg(not(feature = "portable-atomic"))]
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+#[cfg(feature = "portable-atomic")]
+use portable_atomic::{AtomicBool, AtomicU32, Ordering};
+
 use atomic_waker::AtomicWaker;
 
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::waitqueue::MultiWakerRegistration;
+
+/// A lock-free notification for a single waiter.
+///
+/// Only the waker of the task which polled last is retained, so if several tasks wait
+/// concurrently, all but one of them starve. Use `MultiNotification` for that case.
 pub struct Notification {
     waker: AtomicWaker,
     triggered: AtomicBool,
@@ -44,6 +77,12 @@ impl Notification {
             Poll::Pending
         }
     }
+
+    /// Block the current thread until notified, parking it while waiting.
+    #[cfg(feature = "std")]
+    pub fn wait_blocking(&self) {
+        crate::adapt::block_on(self.wait())
+    }
 }
 
 impl Default for Notification {
@@ -51,3 +90,626 @@ impl Default for Notification {
         Self::new()
     }
 }
+
+/// A lock-free group of up to 32 event bits.
+///
+/// Events are `set` as bits of a mask, and can be waited for until any or all of the bits of
+/// another mask are set. With `clear`, the waited-for bits are cleared atomically when the wait
+/// completes, so that the same events are not observed twice. Like `Notification`, it supports
+/// a single waiter.
+pub struct EventGroup {
+    waker: AtomicWaker,
+    bits: AtomicU32,
+}
+
+impl EventGroup {
+    pub const fn new() -> Self {
+        Self {
+            waker: AtomicWaker::new(),
+            bits: AtomicU32::new(0),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.bits.store(0, Ordering::SeqCst);
+        self.waker.take();
+    }
+
+    /// Set `bits`, waking the waiter.
+    pub fn set(&self, bits: u32) {
+        self.bits.fetch_or(bits, Ordering::SeqCst);
+        self.waker.wake();
+    }
+
+    /// Clear `bits`, returning the bits set before.
+    pub fn clear(&self, bits: u32) -> u32 {
+        self.bits.fetch_and(!bits, Ordering::SeqCst)
+    }
+
+    /// The currently set bits.
+    pub fn bits(&self) -> u32 {
+        self.bits.load(Ordering::SeqCst)
+    }
+
+    /// Wait until any of the bits in `mask` is set and return the set bits of `mask`.
+    pub fn wait_any(&self, mask: u32, clear: bool) -> impl Future<Output = u32> + '_ {
+        poll_fn(move |cx| self.poll_wait_any(mask, clear, cx))
+    }
+
+    /// Wait until all of the bits in `mask` are set and return `mask`.
+    pub fn wait_all(&self, mask: u32, clear: bool) -> impl Future<Output = u32> + '_ {
+        poll_fn(move |cx| self.poll_wait_all(mask, clear, cx))
+    }
+
+    pub fn poll_wait_any(&self, mask: u32, clear: bool, cx: &mut Context<'_>) -> Poll<u32> {
+        self.poll_wait(mask, clear, |bits| bits & mask != 0, cx)
+    }
+
+    pub fn poll_wait_all(&self, mask: u32, clear: bool, cx: &mut Context<'_>) -> Poll<u32> {
+        self.poll_wait(mask, clear, |bits| bits & mask == mask, cx)
+    }
+
+    /// Block the current thread until any of the bits in `mask` is set, parking it while waiting.
+    #[cfg(feature = "std")]
+    pub fn wait_any_blocking(&self, mask: u32, clear: bool) -> u32 {
+        crate::adapt::block_on(self.wait_any(mask, clear))
+    }
+
+    /// Block the current thread until all of the bits in `mask` are set, parking it while waiting.
+    #[cfg(feature = "std")]
+    pub fn wait_all_blocking(&self, mask: u32, clear: bool) -> u32 {
+        crate::adapt::block_on(self.wait_all(mask, clear))
+    }
+
+    fn poll_wait<F>(&self, mask: u32, clear: bool, ready: F, cx: &mut Context<'_>) -> Poll<u32>
+    where
+        F: Fn(u32) -> bool,
+    {
+        self.waker.register(cx.waker());
+
+        let result = self
+            .bits
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |bits| {
+                ready(bits).then_some(if clear { bits & !mask } else { bits })
+            });
+
+        match result {
+            Ok(bits) => Poll::Ready(bits & mask),
+            Err(_) => Poll::Pending,
+        }
+    }
+}
+
+impl Default for EventGroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A notification which wakes all of its waiters.
+///
+/// `notify` completes every `wait` which is pending at the time; if nobody is waiting,
+/// the next `wait` completes right away, as with `Notification`.
+///
+/// Wakers are kept in a fixed-capacity set of `N` entries. `N` should be at least the number
+/// of tasks waiting concurrently; beyond that, the waiters are still notified, but are woken
+/// spuriously whenever the set overflows.
+pub struct MultiNotification<M, const N: usize>
+where
+    M: RawMutex,
+{
+    state: Mutex<M, RefCell<MultiState<N>>>,
+}
+
+struct MultiState<const N: usize> {
+    triggered: bool,
+    generation: u32,
+    wakers: MultiWakerRegistration<N>,
+}
+
+impl<M, const N: usize> MultiNotification<M, N>
+where
+    M: RawMutex,
+{
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(MultiState {
+                triggered: false,
+                generation: 0,
+                wakers: MultiWakerRegistration::new(),
+            })),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.state
+            .lock(|state| state.borrow_mut().triggered = false);
+    }
+
+    pub fn notify(&self) {
+        self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+
+            state.triggered = true;
+            state.generation = state.generation.wrapping_add(1);
+            state.wakers.wake();
+        });
+    }
+
+    pub fn triggered(&self) -> bool {
+        self.state.lock(|state| state.borrow().triggered)
+    }
+
+    pub fn wait(&self) -> impl Future<Output = ()> + '_ {
+        let mut generation = None;
+
+        poll_fn(move |cx| self.poll_wait(&mut generation, cx))
+    }
+
+    /// Block the current thread until notified, parking it while waiting.
+    #[cfg(feature = "std")]
+    pub fn wait_blocking(&self) {
+        crate::adapt::block_on(self.wait())
+    }
+
+    fn poll_wait(&self, generation: &mut Option<u32>, cx: &mut Context<'_>) -> Poll<()> {
+        self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+
+            let notified = match generation {
+                // A notification which arrived while nobody was waiting
+                None => state.triggered,
+                // A notification which arrived since this waiter started waiting
+                Some(generation) => *generation != state.generation,
+            };
+
+            if notified {
+                state.triggered = false;
+                Poll::Ready(())
+            } else {
+                *generation = Some(state.generation);
+                state.wakers.register(cx.waker());
+                Poll::Pending
+            }
+        })
+    }
+}
+
+impl<M, const N: usize> Default for MultiNotification<M, N>
+where
+    M: RawMutex,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A lock-free notification which counts the notifications that arrived before `wait`.
+///
+/// Unlike with `Notification`, several `notify` calls do not coalesce: `wait` returns the
+/// number of notifications since the previous `wait` completed. Like `Notification`, it
+/// supports a single waiter.
+pub struct CountingNotification {
+    waker: AtomicWaker,
+    pending: AtomicU32,
+}
+
+impl CountingNotification {
+    pub const fn new() -> Self {
+        Self {
+            waker: AtomicWaker::new(),
+            pending: AtomicU32::new(0),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.pending.store(0, Ordering::SeqCst);
+        self.waker.take();
+    }
+
+    pub fn notify(&self) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        self.waker.wake();
+    }
+
+    /// The number of notifications which have not been waited for yet.
+    pub fn pending(&self) -> u32 {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// Wait for at least one notification and return the number of pending notifications,
+    /// resetting it to zero.
+    pub fn wait(&self) -> impl Future<Output = u32> + '_ {
+        poll_fn(move |cx| self.poll_wait(cx))
+    }
+
+    pub fn poll_wait(&self, cx: &mut Context<'_>) -> Poll<u32> {
+        self.waker.register(cx.waker());
+
+        match self.pending.swap(0, Ordering::SeqCst) {
+            0 => Poll::Pending,
+            pending => Poll::Ready(pending),
+        }
+    }
+
+    /// Block the current thread until notified, parking it while waiting.
+    #[cfg(feature = "std")]
+    pub fn wait_blocking(&self) -> u32 {
+        crate::adapt::block_on(self.wait())
+    }
+}
+
+impl Default for CountingNotification {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A value which can be stored in an `AtomicSignal`, by converting it to and from 32 bits.
+pub trait AtomicValue: Copy {
+    fn to_bits(self) -> u32;
+
+    fn from_bits(bits: u32) -> Self;
+}
+
+macro_rules! impl_atomic_value {
+    ($($t:ty),*) => {
+        $(
+            impl AtomicValue for $t {
+                fn to_bits(self) -> u32 {
+                    self as u32
+                }
+
+                fn from_bits(bits: u32) -> Self {
+                    bits as Self
+                }
+            }
+        )*
+    };
+}
+
+impl_atomic_value!(u8, u16, u32, i8, i16, i32);
+
+impl AtomicValue for () {
+    fn to_bits(self) -> u32 {
+        0
+    }
+
+    fn from_bits(_bits: u32) -> Self {}
+}
+
+impl AtomicValue for bool {
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        bits != 0
+    }
+}
+
+impl AtomicValue for char {
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        char::from_u32(bits).unwrap_or(char::REPLACEMENT_CHARACTER)
+    }
+}
+
+impl AtomicValue for f32 {
+    fn to_bits(self) -> u32 {
+        f32::to_bits(self)
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        f32::from_bits(bits)
+    }
+}
+
+/// A lock-free `Notification` carrying a small value.
+///
+/// Signalling again before `wait` overwrites the previous value, so `wait` always returns the
+/// latest one. Like `Notification`, it supports a single waiter.
+pub struct AtomicSignal<T> {
+    waker: AtomicWaker,
+    // The number of `signal` calls which started and completed storing their value; the value
+    // is consistent with `completed` only while no `signal` is in progress, i.e. both are equal
+    started: AtomicU32,
+    completed: AtomicU32,
+    // The value of `completed` when the value was last taken
+    taken: AtomicU32,
+    value: AtomicU32,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> AtomicSignal<T>
+where
+    T: AtomicValue,
+{
+    pub const fn new() -> Self {
+        Self {
+            waker: AtomicWaker::new(),
+            started: AtomicU32::new(0),
+            completed: AtomicU32::new(0),
+            taken: AtomicU32::new(0),
+            value: AtomicU32::new(0),
+            _type: PhantomData,
+        }
+    }
+
+    pub fn reset(&self) {
+        self.taken
+            .store(self.completed.load(Ordering::SeqCst), Ordering::SeqCst);
+        self.waker.take();
+    }
+
+    pub fn signal(&self, value: T) {
+        self.started.fetch_add(1, Ordering::SeqCst);
+        self.value.store(value.to_bits(), Ordering::SeqCst);
+        self.completed.fetch_add(1, Ordering::SeqCst);
+        self.waker.wake();
+    }
+
+    pub fn signaled(&self) -> bool {
+        self.completed.load(Ordering::SeqCst) != self.taken.load(Ordering::SeqCst)
+    }
+
+    /// Take the signalled value, if any, without waiting.
+    ///
+    /// While a `signal` is in progress (i.e. when called from an interrupt handler which
+    /// preempted it), its value is not taken yet, as it is not known which value it stored.
+    pub fn try_take(&self) -> Option<T> {
+        loop {
+            let taken = self.taken.load(Ordering::SeqCst);
+            let completed = self.completed.load(Ordering::SeqCst);
+
+            if completed == taken {
+                break None;
+            }
+
+            let value = self.value.load(Ordering::SeqCst);
+            let started = self.started.load(Ordering::SeqCst);
+
+            if started == completed {
+                // No `signal` started since `completed`, so `value` is the latest value
+                if self
+                    .taken
+                    .compare_exchange(taken, completed, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+                {
+                    break Some(T::from_bits(value));
+                }
+            } else if self.completed.load(Ordering::SeqCst) != started {
+                // A `signal` is in progress, and wakes the waiter once it completes
+                break None;
+            }
+
+            // Another `signal` completed in the meantime, so retry with its value
+        }
+    }
+
+    pub fn wait(&self) -> impl Future<Output = T> + '_ {
+        poll_fn(move |cx| self.poll_wait(cx))
+    }
+
+    pub fn poll_wait(&self, cx: &mut Context<'_>) -> Poll<T> {
+        self.waker.register(cx.waker());
+
+        match self.try_take() {
+            Some(value) => Poll::Ready(value),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Block the current thread until signalled, parking it while waiting.
+    #[cfg(feature = "std")]
+    pub fn wait_blocking(&self) -> T {
+        crate::adapt::block_on(self.wait())
+    }
+}
+
+impl<T> Default for AtomicSignal<T>
+where
+    T: AtomicValue,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use core::pin::pin;
+    use core::task::Waker;
+
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::thread;
+
+    use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+
+    use crate::adapt::block_on;
+
+    use super::*;
+
+    const ROUNDS: u32 = 10_000;
+
+    /// Run `f` on another thread for each of `0..rounds`, every time in a critical section,
+    /// as an interrupt handler on a single core would.
+    fn interrupts<'s, F>(scope: &'s thread::Scope<'s, '_>, rounds: u32, f: F)
+    where
+        F: Fn(u32) + Send + 's,
+    {
+        scope.spawn(move || {
+            for round in 0..rounds {
+                critical_section::with(|_| f(round));
+                thread::yield_now();
+            }
+        });
+    }
+
+    struct Flag(AtomicBool);
+
+    impl Flag {
+        fn new() -> Arc<Self> {
+            Arc::new(Self(AtomicBool::new(false)))
+        }
+
+        fn take(&self) -> bool {
+            self.0.swap(false, Ordering::SeqCst)
+        }
+    }
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn notification() {
+        let notification = Notification::new();
+
+        let flag = Flag::new();
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let mut wait = pin!(notification.wait());
+        assert!(wait.as_mut().poll(&mut cx).is_pending());
+
+        thread::scope(|scope| interrupts(scope, 1, |_| notification.notify()));
+
+        assert!(flag.take());
+        assert!(wait.poll(&mut cx).is_ready());
+        assert!(!notification.triggered());
+
+        // Ping-pong with the interrupt handler, which notifies again only once woken
+        let pong = Notification::new();
+
+        thread::scope(|scope| {
+            interrupts(scope, ROUNDS, |_| {
+                notification.notify();
+                pong.wait_blocking();
+            });
+
+            for _ in 0..ROUNDS {
+                block_on(notification.wait());
+                pong.notify();
+            }
+        });
+    }
+
+    #[test]
+    fn counting_notification() {
+        let notification = CountingNotification::new();
+
+        thread::scope(|scope| {
+            interrupts(scope, ROUNDS, |_| notification.notify());
+
+            let mut notified = 0;
+
+            while notified < ROUNDS {
+                notified += block_on(notification.wait());
+            }
+
+            assert_eq!(notified, ROUNDS);
+        });
+
+        assert_eq!(notification.pending(), 0);
+    }
+
+    #[test]
+    fn atomic_signal() {
+        let signal = AtomicSignal::<u32>::new();
+
+        thread::scope(|scope| {
+            interrupts(scope, ROUNDS, |round| signal.signal(round + 1));
+
+            let mut last = 0;
+
+            while last < ROUNDS {
+                let value = block_on(signal.wait());
+
+                // Values may be overwritten, but never come out of order
+                assert!(value > last);
+                last = value;
+            }
+        });
+
+        assert_eq!(signal.try_take(), None);
+    }
+
+    #[test]
+    fn event_group() {
+        let events = EventGroup::new();
+
+        thread::scope(|scope| {
+            interrupts(scope, 32, |round| events.set(1 << round));
+
+            assert_eq!(block_on(events.wait_all(u32::MAX, true)), u32::MAX);
+        });
+
+        assert_eq!(events.bits(), 0);
+
+        thread::scope(|scope| {
+            interrupts(scope, 1, |_| events.set(0b100));
+
+            assert_eq!(block_on(events.wait_any(0b110, false)), 0b100);
+        });
+
+        assert_eq!(events.bits(), 0b100);
+        assert_eq!(events.clear(0b100), 0b100);
+        assert_eq!(events.bits(), 0);
+    }
+
+    #[test]
+    fn multi_notification() {
+        let notification = MultiNotification::<CriticalSectionRawMutex, 4>::new();
+
+        let flags = [Flag::new(), Flag::new(), Flag::new()];
+        let wakers = flags.each_ref().map(|flag| Waker::from(flag.clone()));
+
+        let mut waits = [
+            notification.wait(),
+            notification.wait(),
+            notification.wait(),
+        ]
+        .map(Box::pin);
+
+        for (wait, waker) in waits.iter_mut().zip(&wakers) {
+            assert!(wait
+                .as_mut()
+                .poll(&mut Context::from_waker(waker))
+                .is_pending());
+        }
+
+        thread::scope(|scope| interrupts(scope, 1, |_| notification.notify()));
+
+        for ((wait, waker), flag) in waits.iter_mut().zip(&wakers).zip(&flags) {
+            assert!(flag.take());
+            assert!(wait
+                .as_mut()
+                .poll(&mut Context::from_waker(waker))
+                .is_ready());
+        }
+
+        // Waiters blocked on other threads, notified until all of them are done
+        let done = AtomicU32::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..3 {
+                scope.spawn(|| {
+                    notification.wait_blocking();
+                    done.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+
+            while done.load(Ordering::SeqCst) < 3 {
+                critical_section::with(|_| notification.notify());
+                thread::yield_now();
+            }
+        });
+    }
+}
@@ -0,0 +1,332 @@
+//! Blocking typed channels over plain byte streams, with the same varint length-prefixed
+//! framing as `crate::asynch::stream`, so that blocking and async peers can talk to each other.
+
+use core::marker::PhantomData;
+
+use embedded_io::{Read, Write};
+
+use crate::codec::{self, ReceiveData, SendData};
+
+pub use crate::asynch::stream::StreamError;
+
+#[cfg(feature = "std")]
+pub use std_impl::*;
+
+pub struct StreamSender<'a, W, D> {
+    write: W,
+    buf: &'a mut [u8],
+    _type: PhantomData<fn() -> D>,
+}
+
+impl<'a, W, D> StreamSender<'a, W, D>
+where
+    W: Write,
+    D: SendData,
+{
+    /// Create a new sender; `buf` needs room for the length prefix as well, i.e.
+    /// `codec::MAX_LEN_PREFIX_SIZE` bytes more than the largest serialized message.
+    pub fn new(write: W, buf: &'a mut [u8]) -> Self {
+        Self {
+            write,
+            buf,
+            _type: PhantomData,
+        }
+    }
+
+    pub fn send(&mut self, data: &D) -> Result<(), StreamError<W::Error>> {
+        let frame = codec::encode_framed(data, self.buf)?;
+
+        self.write.write_all(frame).map_err(StreamError::IoError)?;
+        self.write.flush().map_err(StreamError::IoError)?;
+
+        Ok(())
+    }
+}
+
+impl<W, D> crate::Sender for StreamSender<'_, W, D>
+where
+    W: Write,
+    D: SendData,
+{
+    type Error = StreamError<W::Error>;
+
+    type Data = D;
+
+    fn send(&mut self, data: &Self::Data) -> Result<(), Self::Error> {
+        StreamSender::send(self, data)
+    }
+}
+
+pub struct StreamReceiver<'a, R, D> {
+    read: R,
+    buf: &'a mut [u8],
+    _type: PhantomData<fn() -> D>,
+}
+
+impl<'a, R, D> StreamReceiver<'a, R, D>
+where
+    R: Read,
+{
+    pub fn new(read: R, buf: &'a mut [u8]) -> Self {
+        Self {
+            read,
+            buf,
+            _type: PhantomData,
+        }
+    }
+
+    /// Receive a message which borrows its `&str` / `&[u8]` fields directly
+    /// from the internal buffer, thus avoiding any allocation or copying.
+    ///
+    /// The returned value keeps `self` borrowed until it is dropped.
+    #[cfg(not(feature = "prost"))]
+    pub fn recv_borrowed<'b, T>(&'b mut self) -> Result<Option<T>, StreamError<R::Error>>
+    where
+        T: serde::Deserialize<'b>,
+    {
+        match self.recv_frame()? {
+            Some(frame_buf) => Ok(Some(codec::decode_borrowed(frame_buf)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Receive the next frame, or `None` if the stream was closed on a frame boundary.
+    ///
+    /// Oversized frames are skipped, so that the stream stays usable after an
+    /// `OversizedFrame` error.
+    fn recv_frame(&mut self) -> Result<Option<&[u8]>, StreamError<R::Error>> {
+        Ok(codec::read_frame_blocking(&mut self.read, self.buf)?)
+    }
+}
+
+impl<R, D> StreamReceiver<'_, R, D>
+where
+    R: Read,
+    D: ReceiveData,
+{
+    pub fn recv(&mut self) -> Result<Option<D>, StreamError<R::Error>> {
+        match self.recv_frame()? {
+            Some(frame_buf) => Ok(Some(codec::decode(frame_buf)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<R, D> crate::Receiver for StreamReceiver<'_, R, D>
+where
+    R: Read,
+    D: ReceiveData,
+{
+    type Error = StreamError<R::Error>;
+
+    type Data = Option<D>;
+
+    fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        StreamReceiver::recv(self)
+    }
+}
+
+#[cfg(feature = "std")]
+mod std_impl {
+    use std::io::{self, BufReader};
+    use std::net::TcpStream;
+    #[cfg(unix)]
+    use std::os::unix::net::UnixStream;
+
+    use embedded_io_adapters::std::FromStd;
+
+    use super::*;
+
+    pub type StdStreamSender<'a, T, D> = StreamSender<'a, FromStd<T>, D>;
+    /// A receiver reading through a `BufReader`, so that the length prefixes, which are read
+    /// byte by byte, do not cost a system call per byte.
+    pub type StdStreamReceiver<'a, T, D> = StreamReceiver<'a, FromStd<BufReader<T>>, D>;
+
+    /// Split a connected TCP stream into a sender and a receiver.
+    pub fn tcp<'a, S, R>(
+        stream: TcpStream,
+        send_buf: &'a mut [u8],
+        recv_buf: &'a mut [u8],
+    ) -> io::Result<(
+        StdStreamSender<'a, TcpStream, S>,
+        StdStreamReceiver<'a, TcpStream, R>,
+    )>
+    where
+        S: SendData,
+        R: ReceiveData,
+    {
+        let read = stream.try_clone()?;
+
+        Ok((
+            StreamSender::new(FromStd::new(stream), send_buf),
+            StreamReceiver::new(FromStd::new(BufReader::new(read)), recv_buf),
+        ))
+    }
+
+    /// Split a connected Unix domain socket stream into a sender and a receiver.
+    #[cfg(unix)]
+    pub fn unix<'a, S, R>(
+        stream: UnixStream,
+        send_buf: &'a mut [u8],
+        recv_buf: &'a mut [u8],
+    ) -> io::Result<(
+        StdStreamSender<'a, UnixStream, S>,
+        StdStreamReceiver<'a, UnixStream, R>,
+    )>
+    where
+        S: SendData,
+        R: ReceiveData,
+    {
+        let read = stream.try_clone()?;
+
+        Ok((
+            StreamSender::new(FromStd::new(stream), send_buf),
+            StreamReceiver::new(FromStd::new(BufReader::new(read)), recv_buf),
+        ))
+    }
+}
+
+#[cfg(all(test, not(feature = "prost")))]
+mod tests {
+    use crate::adapt::block_on;
+
+    use super::*;
+
+    const MESSAGES: [&[u8]; 4] = [b"", b"hello", &[0x55; 127], &[0xaa; 300]];
+
+    #[test]
+    fn roundtrip() {
+        let mut wire = [0; 1024];
+        let mut buf = [0; 512];
+
+        let len = {
+            let mut write = &mut wire[..];
+            let mut sender = StreamSender::new(&mut write, &mut buf);
+
+            for message in MESSAGES {
+                sender.send(&message).unwrap();
+            }
+
+            1024 - write.len()
+        };
+
+        let mut buf = [0; 256];
+        let mut receiver = StreamReceiver::<_, &[u8]>::new(&wire[..len], &mut buf);
+
+        for message in MESSAGES {
+            if message.len() <= 256 {
+                assert_eq!(receiver.recv_borrowed::<&[u8]>().unwrap(), Some(message));
+            } else {
+                // Skipped, as it exceeds the buffer
+                assert!(matches!(
+                    receiver.recv_borrowed::<&[u8]>(),
+                    Err(StreamError::OversizedFrame(_))
+                ));
+            }
+        }
+
+        assert_eq!(receiver.recv_borrowed::<&[u8]>().unwrap(), None);
+    }
+
+    #[test]
+    fn borrowed() {
+        let mut wire = [0; 1024];
+        let mut buf = [0; 64];
+
+        let len = {
+            let mut write = &mut wire[..];
+            let mut sender = StreamSender::new(&mut write, &mut buf);
+
+            sender.send(&("name", &b"\0data"[..])).unwrap();
+
+            1024 - write.len()
+        };
+
+        let range = buf.as_ptr_range();
+        let mut receiver = StreamReceiver::<_, (&str, &[u8])>::new(&wire[..len], &mut buf);
+
+        let (name, data) = receiver.recv_borrowed::<(&str, &[u8])>().unwrap().unwrap();
+
+        assert_eq!((name, data), ("name", &b"\0data"[..]));
+
+        // Both point into the receive buffer rather than being copied
+        assert!(range.contains(&name.as_ptr()));
+        assert!(range.contains(&data.as_ptr()));
+    }
+
+    #[test]
+    fn async_compatible() {
+        let mut wire = [0; 1024];
+        let mut buf = [0; 512];
+
+        let len = {
+            let mut write = &mut wire[..];
+            let mut sender = StreamSender::new(&mut write, &mut buf);
+
+            for message in MESSAGES {
+                sender.send(&message).unwrap();
+            }
+
+            1024 - write.len()
+        };
+
+        let mut async_wire = [0; 1024];
+
+        let async_len = {
+            let mut write = &mut async_wire[..];
+            let mut sender = crate::asynch::stream::StreamSender::new(&mut write, &mut buf);
+
+            for message in MESSAGES {
+                block_on(sender.send(message)).unwrap();
+            }
+
+            1024 - write.len()
+        };
+
+        assert_eq!(wire[..len], async_wire[..async_len]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn tcp() {
+        use std::net::{TcpListener, TcpStream};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = std::thread::spawn(move || {
+            let mut send_buf = [0; 512];
+            let mut recv_buf = [0; 512];
+
+            let (mut sender, mut receiver) = super::tcp::<u32, u32>(
+                TcpStream::connect(addr).unwrap(),
+                &mut send_buf,
+                &mut recv_buf,
+            )
+            .unwrap();
+
+            // Echo doubled
+            while let Some(value) = receiver.recv().unwrap() {
+                sender.send(&(value * 2)).unwrap();
+            }
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+
+        let mut send_buf = [0; 512];
+        let mut recv_buf = [0; 512];
+
+        let (mut sender, mut receiver) =
+            super::tcp::<u32, u32>(stream, &mut send_buf, &mut recv_buf).unwrap();
+
+        for value in [0, 1, 300, u32::MAX / 2] {
+            sender.send(&value).unwrap();
+            assert_eq!(receiver.recv().unwrap(), Some(value * 2));
+        }
+
+        drop(sender);
+        drop(receiver);
+
+        client.join().unwrap();
+    }
+}
@@ -0,0 +1,370 @@
+//! Blocking typed channels over web sockets implementing the blocking `embedded-svc` traits,
+//! with the same framing as `crate::asynch::ws`, for code running on plain threads.
+
+use core::marker::PhantomData;
+
+use embedded_svc::ws::{self, FrameType};
+
+use crate::codec::{ReceiveData, SendData};
+
+pub use crate::asynch::ws::WsError;
+
+pub struct WsSvcSender<'a, S, D> {
+    ws_sender: S,
+    buf: &'a mut [u8],
+    #[cfg(feature = "compression")]
+    compression: bool,
+    _type: PhantomData<fn() -> D>,
+}
+
+impl<'a, S, D> WsSvcSender<'a, S, D>
+where
+    S: ws::Sender,
+    D: SendData,
+{
+    pub fn new(ws_sender: S, buf: &'a mut [u8]) -> Self {
+        Self {
+            ws_sender,
+            buf,
+            #[cfg(feature = "compression")]
+            compression: false,
+            _type: PhantomData,
+        }
+    }
+
+    /// Compress the messages (see `crate::codec::encode_compressed`);
+    /// the receiving end needs to have compression enabled as well.
+    #[cfg(feature = "compression")]
+    pub fn set_compression(&mut self, compression: bool) {
+        self.compression = compression;
+    }
+
+    pub fn send(&mut self, data: &D) -> Result<(), WsError<S::Error>> {
+        #[cfg(feature = "compression")]
+        let frame_data = if self.compression {
+            crate::codec::encode_compressed(data, self.buf)?
+        } else {
+            crate::codec::encode(data, self.buf)?
+        };
+
+        #[cfg(not(feature = "compression"))]
+        let frame_data = crate::codec::encode(data, self.buf)?;
+
+        self.ws_sender
+            .send(FrameType::Binary(false), frame_data)
+            .map_err(WsError::IoError)?;
+
+        Ok(())
+    }
+}
+
+impl<S, D> crate::Sender for WsSvcSender<'_, S, D>
+where
+    S: ws::Sender,
+    D: SendData,
+{
+    type Error = WsError<S::Error>;
+
+    type Data = D;
+
+    fn send(&mut self, data: &Self::Data) -> Result<(), Self::Error> {
+        WsSvcSender::send(self, data)
+    }
+}
+
+pub struct WsSvcReceiver<'a, R, D> {
+    ws_receiver: R,
+    buf: &'a mut [u8],
+    #[cfg(feature = "compression")]
+    compression: bool,
+    _type: PhantomData<fn() -> D>,
+}
+
+impl<'a, R, D> WsSvcReceiver<'a, R, D>
+where
+    R: ws::Receiver,
+{
+    pub fn new(ws_receiver: R, buf: &'a mut [u8]) -> Self {
+        Self {
+            ws_receiver,
+            buf,
+            #[cfg(feature = "compression")]
+            compression: false,
+            _type: PhantomData,
+        }
+    }
+
+    /// Decompress the messages (see `crate::codec::decompress`),
+    /// as sent by a sender with compression enabled.
+    #[cfg(feature = "compression")]
+    pub fn set_compression(&mut self, compression: bool) {
+        self.compression = compression;
+    }
+
+    /// Receive a message which borrows its `&str` / `&[u8]` fields directly
+    /// from the internal buffer, thus avoiding any allocation or copying.
+    ///
+    /// The returned value keeps `self` borrowed until it is dropped.
+    #[cfg(not(feature = "prost"))]
+    pub fn recv_borrowed<'b, T>(&'b mut self) -> Result<Option<T>, WsError<R::Error>>
+    where
+        T: serde::Deserialize<'b>,
+    {
+        match self.recv_frame()? {
+            Some(frame_buf) => Ok(Some(crate::codec::decode_borrowed(frame_buf)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn recv_frame(&mut self) -> Result<Option<&[u8]>, WsError<R::Error>> {
+        let (frame_type, size) = loop {
+            let (frame_type, size) = self.ws_receiver.recv(self.buf).map_err(WsError::IoError)?;
+
+            if frame_type != FrameType::Ping && frame_type != FrameType::Pong {
+                if size > self.buf.len() {
+                    return Err(WsError::OversizedFrame(size - self.buf.len()));
+                }
+                break (frame_type, size);
+            }
+        };
+
+        match frame_type {
+            FrameType::Text(_) | FrameType::Continue(_) => Err(WsError::UnknownFrameError),
+            FrameType::Binary(_) => {
+                #[cfg(feature = "compression")]
+                if self.compression {
+                    return Ok(Some(crate::codec::decompress(size, self.buf)?));
+                }
+
+                Ok(Some(&self.buf[..size]))
+            }
+            FrameType::Close | FrameType::SocketClose => Ok(None),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<R, D> WsSvcReceiver<'_, R, D>
+where
+    R: ws::Receiver,
+    D: ReceiveData,
+{
+    pub fn recv(&mut self) -> Result<Option<D>, WsError<R::Error>> {
+        match self.recv_frame()? {
+            Some(frame_buf) => Ok(Some(crate::codec::decode(frame_buf)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<R, D> crate::Receiver for WsSvcReceiver<'_, R, D>
+where
+    R: ws::Receiver,
+    D: ReceiveData,
+{
+    type Error = WsError<R::Error>;
+
+    type Data = Option<D>;
+
+    fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        WsSvcReceiver::recv(self)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::vec::Vec;
+
+    use crate::adapt::block_on;
+    use crate::asynch::mock::{Event, MockReceiver, MockSender};
+
+    use super::*;
+
+    type Error = &'static str;
+
+    type Frame = (FrameType, Vec<u8>);
+
+    /// Records the sent frames.
+    struct MockWsSender(MockSender<Frame, Error>);
+
+    /// Replays the scripted frames, then reports the socket as closed.
+    struct MockWsReceiver(MockReceiver<Frame, Error>);
+
+    impl ws::ErrorType for MockWsSender {
+        type Error = Error;
+    }
+
+    impl ws::Sender for MockWsSender {
+        fn send(&mut self, frame_type: FrameType, frame_data: &[u8]) -> Result<(), Error> {
+            block_on(crate::asynch::Sender::send(
+                &mut self.0,
+                (frame_type, frame_data.to_vec()),
+            ))
+        }
+    }
+
+    impl ws::ErrorType for MockWsReceiver {
+        type Error = Error;
+    }
+
+    impl ws::Receiver for MockWsReceiver {
+        fn recv(&mut self, frame_data_buf: &mut [u8]) -> Result<(FrameType, usize), Error> {
+            if self.0.is_done() {
+                return Ok((FrameType::SocketClose, 0));
+            }
+
+            match block_on(crate::asynch::Receiver::recv(&mut self.0))? {
+                Some((frame_type, frame_data)) => {
+                    // As with a real socket, a frame exceeding the buffer is truncated
+                    let len = frame_data.len().min(frame_data_buf.len());
+                    frame_data_buf[..len].copy_from_slice(&frame_data[..len]);
+
+                    Ok((frame_type, frame_data.len()))
+                }
+                None => Ok((FrameType::Close, 0)),
+            }
+        }
+    }
+
+    /// Send `values` through a `WsSvcSender`, returning the frames it sent.
+    fn send(values: &[u32]) -> Vec<Frame> {
+        let mut buf = [0; 16];
+        let mut sender = WsSvcSender::new(MockWsSender(MockSender::new()), &mut buf);
+
+        for value in values {
+            sender.send(value).unwrap();
+        }
+
+        sender.ws_sender.0.take_sent()
+    }
+
+    fn receiver<'a, D>(
+        events: impl IntoIterator<Item = Event<Frame, Error>>,
+        buf: &'a mut [u8],
+    ) -> WsSvcReceiver<'a, MockWsReceiver, D> {
+        WsSvcReceiver::new(MockWsReceiver(events.into_iter().collect()), buf)
+    }
+
+    #[test]
+    fn roundtrip() {
+        let frames = send(&[1, 2, u32::MAX]);
+
+        assert!(frames
+            .iter()
+            .all(|(frame_type, _)| *frame_type == FrameType::Binary(false)));
+
+        let [first, second, third] = frames.try_into().unwrap();
+
+        let mut buf = [0; 16];
+        let mut receiver = receiver::<u32>(
+            [
+                Event::Ok(first),
+                // Control frames are skipped
+                Event::Ok((FrameType::Ping, vec![1, 2, 3])),
+                Event::Ok(second),
+                Event::Ok((FrameType::Pong, vec![])),
+                Event::Ok(third),
+                Event::Close,
+            ],
+            &mut buf,
+        );
+
+        assert_eq!(receiver.recv().unwrap(), Some(1));
+        assert_eq!(receiver.recv().unwrap(), Some(2));
+        assert_eq!(receiver.recv().unwrap(), Some(u32::MAX));
+        assert_eq!(receiver.recv().unwrap(), None);
+
+        // The socket is gone as well
+        assert_eq!(receiver.recv().unwrap(), None);
+    }
+
+    #[test]
+    fn errors() {
+        let [frame] = send(&[7]).try_into().unwrap();
+
+        let mut buf = [0; 8];
+        let mut receiver = receiver::<u32>(
+            [
+                Event::Ok((FrameType::Binary(false), vec![0; 100])),
+                Event::Ok((FrameType::Text(false), b"text".to_vec())),
+                Event::Err("broken"),
+                Event::Ok(frame),
+            ],
+            &mut buf,
+        );
+
+        assert!(matches!(receiver.recv(), Err(WsError::OversizedFrame(92))));
+        assert!(matches!(receiver.recv(), Err(WsError::UnknownFrameError)));
+        assert!(matches!(receiver.recv(), Err(WsError::IoError("broken"))));
+
+        // The receiver stays usable
+        assert_eq!(receiver.recv().unwrap(), Some(7));
+    }
+
+    #[test]
+    fn send_errors() {
+        let mut mock = MockSender::new();
+        mock.ok_next().fail_next("broken");
+
+        let mut buf = [0; 16];
+        let mut sender = WsSvcSender::new(MockWsSender(mock), &mut buf);
+
+        sender.send(&1_u32).unwrap();
+        assert!(matches!(sender.send(&2), Err(WsError::IoError("broken"))));
+        sender.send(&3).unwrap();
+
+        assert_eq!(sender.ws_sender.0.sent().len(), 2);
+
+        // Not enough room to encode the message
+        let mut buf = [0; 2];
+        let mut small = WsSvcSender::new(MockWsSender(MockSender::new()), &mut buf);
+
+        assert!(small.send(&u32::MAX).is_err());
+        assert!(small.ws_sender.0.sent().is_empty());
+    }
+
+    #[cfg(not(feature = "prost"))]
+    #[test]
+    fn recv_borrowed() {
+        let mut buf = [0; 32];
+        let mut sender = WsSvcSender::new(MockWsSender(MockSender::new()), &mut buf);
+
+        sender.send(&("name", &b"data"[..])).unwrap();
+
+        let frames = sender.ws_sender.0.take_sent();
+
+        let mut buf = [0; 32];
+        let range = buf.as_ptr_range();
+        let mut receiver = receiver::<(&str, &[u8])>(frames.into_iter().map(Event::Ok), &mut buf);
+
+        let (name, data) = receiver.recv_borrowed::<(&str, &[u8])>().unwrap().unwrap();
+
+        assert_eq!((name, data), ("name", &b"data"[..]));
+        assert!(range.contains(&name.as_ptr()));
+        assert!(range.contains(&data.as_ptr()));
+    }
+
+    #[cfg(all(feature = "compression", not(feature = "prost")))]
+    #[test]
+    fn compression() {
+        let message = [0x55_u8; 200];
+
+        let mut buf = [0; 512];
+        let mut sender = WsSvcSender::new(MockWsSender(MockSender::new()), &mut buf);
+        sender.set_compression(true);
+
+        sender.send(&&message[..]).unwrap();
+
+        let frames = sender.ws_sender.0.take_sent();
+        assert!(frames[0].1.len() < message.len());
+
+        let mut buf = [0; 512];
+        let mut receiver = receiver::<&[u8]>(frames.into_iter().map(Event::Ok), &mut buf);
+        receiver.set_compression(true);
+
+        assert_eq!(
+            receiver.recv_borrowed::<&[u8]>().unwrap(),
+            Some(&message[..])
+        );
+    }
+}